@@ -0,0 +1,151 @@
+// Addresses of the nodes. A node is reached at a socket address, or at a
+// hostname such as a Kubernetes or docker-compose service name, which is
+// resolved whenever it is connected to.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    /// An IPv4 or IPv6 address, e.g. `10.0.0.1:7000` or `[::1]:7000`
+    Socket(SocketAddr),
+    /// A name to resolve, e.g. `node-0.randpiper:7000`
+    Host(String, u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPeerAddr(pub String);
+
+impl fmt::Display for InvalidPeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid address {:?}, expected host:port", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPeerAddr {}
+
+impl FromStr for PeerAddr {
+    type Err = InvalidPeerAddr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(PeerAddr::Socket(addr));
+        }
+        let err = || InvalidPeerAddr(s.to_string());
+        let (host, port) = s.rsplit_once(':').ok_or_else(err)?;
+        // An IPv6 address needs brackets to be told apart from the port
+        if host.is_empty() || host.contains(':') || host.starts_with('[') {
+            return Err(err());
+        }
+        let port = port.parse().map_err(|_| err())?;
+        Ok(PeerAddr::Host(host.to_string(), port))
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Socket(addr) => write!(f, "{}", addr),
+            PeerAddr::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+impl PeerAddr {
+    pub fn port(&self) -> u16 {
+        match self {
+            PeerAddr::Socket(addr) => addr.port(),
+            PeerAddr::Host(_, port) => *port,
+        }
+    }
+
+    /// The same host at another port
+    pub fn with_port(&self, port: u16) -> PeerAddr {
+        match self {
+            PeerAddr::Socket(addr) => PeerAddr::Socket(SocketAddr::new(addr.ip(), port)),
+            PeerAddr::Host(host, _) => PeerAddr::Host(host.clone(), port),
+        }
+    }
+
+    /// Looks the address up now. Hostnames may resolve differently later.
+    pub fn resolve(&self) -> io::Result<SocketAddr> {
+        match self {
+            PeerAddr::Socket(addr) => Ok(*addr),
+            PeerAddr::Host(host, port) => (host.as_str(), *port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found")),
+        }
+    }
+
+    /// The address to listen on to be reachable at this address: every
+    /// interface of the same family, on the same port
+    pub fn bind_addr(&self) -> SocketAddr {
+        let ip = match self {
+            PeerAddr::Socket(SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+        SocketAddr::new(ip, self.port())
+    }
+}
+
+// Addresses are written as strings in every config format
+impl Serialize for PeerAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let v4: PeerAddr = "10.0.0.1:7000".parse().unwrap();
+        assert_eq!(v4, PeerAddr::Socket("10.0.0.1:7000".parse().unwrap()));
+        assert_eq!(v4.bind_addr().to_string(), "0.0.0.0:7000");
+
+        let v6: PeerAddr = "[::1]:7000".parse().unwrap();
+        assert_eq!(v6.port(), 7000);
+        assert_eq!(v6.to_string(), "[::1]:7000");
+        assert_eq!(v6.bind_addr().to_string(), "[::]:7000");
+        assert_eq!(v6.with_port(6000).to_string(), "[::1]:6000");
+
+        let host: PeerAddr = "node-0.randpiper:7000".parse().unwrap();
+        assert_eq!(host, PeerAddr::Host("node-0.randpiper".to_string(), 7000));
+        assert_eq!(host.to_string(), "node-0.randpiper:7000");
+        assert_eq!(
+            "localhost:7000"
+                .parse::<PeerAddr>()
+                .unwrap()
+                .resolve()
+                .unwrap()
+                .port(),
+            7000
+        );
+
+        for bad in &["10.0.0.1", "::1:7000", ":7000", "node:port", "node:70000"] {
+            assert!(bad.parse::<PeerAddr>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn serde() {
+        let addr: PeerAddr = "[::1]:7000".parse().unwrap();
+        assert_eq!(serde_json::to_string(&addr).unwrap(), "\"[::1]:7000\"");
+        let back: PeerAddr = serde_json::from_str("\"[::1]:7000\"").unwrap();
+        assert_eq!(back, addr);
+        assert!(serde_json::from_str::<PeerAddr>("\"[::1]\"").is_err());
+    }
+}
@@ -5,20 +5,20 @@
 // Client Config:
 //     map[id]ip, n, f, blocksize
 
-use super::{is_valid_replica, ParseError};
+use super::format::{self, Format};
+use super::{is_valid_replica, ConfigError, ParseError, PeerAddr, Violations};
 use crypto::Algorithm;
 use serde::{Deserialize, Serialize};
-use serde_json::from_reader;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
-use toml::from_str;
+use std::path::Path;
 use types::Replica;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Client {
-    pub net_map: HashMap<Replica, String>,
+    #[serde(serialize_with = "super::ordered")]
+    pub net_map: HashMap<Replica, PeerAddr>,
     pub crypto_alg: Algorithm,
+    #[serde(serialize_with = "super::ordered")]
     pub server_pk: HashMap<Replica, Vec<u8>>,
 
     pub num_nodes: usize,
@@ -28,48 +28,33 @@ pub struct Client {
 }
 
 impl Client {
-    pub fn validate(&self) -> Result<(), ParseError> {
-        if self.net_map.len() != self.num_nodes {
-            return Err(ParseError::InvalidMapLen(
-                self.num_nodes,
-                self.net_map.len(),
-            ));
+    pub fn validate(&self) -> Result<(), Violations> {
+        let mut errs = Vec::new();
+        let n = self.num_nodes;
+        if self.net_map.len() != n {
+            errs.push(ParseError::InvalidMapLen(n, self.net_map.len()));
         }
-        if 2 * self.num_faults >= self.num_nodes {
-            return Err(ParseError::IncorrectFaults(self.num_faults, self.num_nodes));
+        if 2 * self.num_faults >= n {
+            errs.push(ParseError::IncorrectFaults(self.num_faults, n));
         }
-        for repl in &self.net_map {
-            if !is_valid_replica(*repl.0, self.num_nodes) {
-                return Err(ParseError::InvalidMapEntry(*repl.0));
+        for r in self.net_map.keys() {
+            if !is_valid_replica(*r, n) {
+                errs.push(ParseError::InvalidMapEntry(*r));
             }
         }
-        match self.crypto_alg {
-            Algorithm::ED25519 => {
-                for repl in &self.server_pk {
-                    if !is_valid_replica(*repl.0, self.num_nodes) {
-                        return Err(ParseError::InvalidMapEntry(*repl.0));
-                    }
-                    if repl.1.len() != crypto::ED25519_PK_SIZE {
-                        return Err(ParseError::InvalidPkSize(repl.1.len()));
-                    }
-                }
-            }
-            Algorithm::SECP256K1 => {
-                for repl in &self.server_pk {
-                    if !is_valid_replica(*repl.0, self.num_nodes) {
-                        return Err(ParseError::InvalidMapEntry(*repl.0));
-                    }
-                    if repl.1.len() != crypto::SECP256K1_PK_SIZE {
-                        return Err(ParseError::InvalidPkSize(repl.1.len()));
+        match crypto::scheme::lookup(&self.crypto_alg) {
+            Err(_) => errs.push(ParseError::Unimplemented("signature scheme")),
+            Ok(scheme) => {
+                for (r, pk) in &self.server_pk {
+                    if !is_valid_replica(*r, n) {
+                        errs.push(ParseError::InvalidPkEntry(*r));
+                    } else if pk.len() != scheme.pk_size() {
+                        errs.push(ParseError::InvalidPkSize(pk.len()));
                     }
                 }
             }
-            Algorithm::RSA => {
-                // Because unimplemented
-                return Err(ParseError::Unimplemented("RSA"));
-            }
         }
-        Ok(())
+        Violations::check(errs)
     }
 
     pub fn new() -> Client {
@@ -84,42 +69,33 @@ impl Client {
         }
     }
 
-    pub fn from_json(filename: String) -> Client {
-        let f = File::open(filename).unwrap();
-        let c: Client = from_reader(f).unwrap();
-        return c;
+    /// Loads a config in the format of its extension (`.json`, `.toml`,
+    /// `.yaml` or `.yml`, `.dat`), or of its contents if the extension is
+    /// unknown, and validates it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Client, ConfigError> {
+        Client::read(path.as_ref(), None)
     }
 
-    pub fn from_toml(filename: String) -> Client {
-        let mut buf = String::new();
-        let mut f = File::open(filename).unwrap();
-        f.read_to_string(&mut buf).unwrap();
-        let c: Client = from_str(&buf).unwrap();
-        return c;
+    /// Loads a config in `format`, whatever its extension
+    pub fn load_as<P: AsRef<Path>>(path: P, format: Format) -> Result<Client, ConfigError> {
+        Client::read(path.as_ref(), Some(format))
     }
 
-    pub fn from_yaml(filename: String) -> Client {
-        let f = File::open(filename).unwrap();
-        let c: Client = serde_yaml::from_reader(f).unwrap();
-        return c;
+    fn read(path: &Path, format: Option<Format>) -> Result<Client, ConfigError> {
+        let c: Client = format::read(path, format)?;
+        c.validate()
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        Ok(c)
     }
 
-    pub fn from_bin(filename: String) -> Client {
-        let mut buf = Vec::new();
-        let mut f = File::open(filename).unwrap();
-        f.read_to_end(&mut buf).unwrap();
-        let bytes: &[u8] = &buf;
-        let c: Client = bincode::deserialize(bytes).unwrap();
-        return c;
-    }
-
-    pub fn update_config(&mut self, ips: Vec<String>) {
-        let mut idx = 0;
-        for ip in ips {
-            // Put others ips in the config
-            self.net_map.insert(idx, ip);
-            idx += 1;
+    pub fn update_config(&mut self, ips: Vec<String>) -> Result<(), ParseError> {
+        for (idx, ip) in ips.into_iter().enumerate() {
+            let addr = ip
+                .parse()
+                .map_err(|_| ParseError::InvalidAddress(idx as Replica, ip))?;
+            self.net_map.insert(idx as Replica, addr);
         }
         println!("Talking to servers: {:?}", self.net_map);
+        Ok(())
     }
 }
@@ -0,0 +1,542 @@
+// The binary config format. A file starts with a magic number, the version
+// of the layout and the kind of config, followed by a table of sections and
+// the sections themselves, and ends with a SHA-256 checksum of all that:
+//
+//     magic "RPCF" | version: u16 | kind: u16 | count: u16
+//     count x (tag: u16 | offset: u64 | length: u64)
+//     sections
+//     checksum: [u8; 32]
+//
+// Numbers are little endian, and each section is a bincode value. Readers
+// skip sections they do not know, so sections can be added without a new
+// version. Files without the magic number are raw bincode dumps, as written
+// before the format had a version.
+
+use super::{Client, ConfigError, Dealing, FormatError, Node, Public};
+use bincode::Options;
+use crypto::hash::HashAlgorithm;
+use crypto::secret::{Secret, SecretBytes};
+use crypto::Algorithm;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use types::{Height, Replica};
+
+pub const MAGIC: &[u8; 4] = b"RPCF";
+/// The version of the layout this code writes
+pub const VERSION: u16 = 2;
+
+const HEADER_SIZE: usize = 10;
+const ENTRY_SIZE: usize = 18;
+const CHECKSUM_SIZE: usize = 32;
+
+/// What a binary config holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Node = 1,
+    Client = 2,
+    Public = 3,
+}
+
+impl Kind {
+    fn from_u16(kind: u16) -> Option<Kind> {
+        match kind {
+            1 => Some(Kind::Node),
+            2 => Some(Kind::Client),
+            3 => Some(Kind::Public),
+            _ => None,
+        }
+    }
+}
+
+// Section tags
+const SETTINGS: u16 = 1;
+const PUBLIC: u16 = 2;
+const KEYS: u16 = 3;
+const BEACON: u16 = 4;
+
+/// A config that can be written in the binary format
+pub trait Binary: Sized {
+    const KIND: Kind;
+
+    /// The sections to write, by tag
+    fn to_sections(&self) -> Result<Vec<(u16, Vec<u8>)>, FormatError>;
+
+    /// Builds the config from the sections of a file of `version`
+    fn from_sections(sections: &Sections, version: u16) -> Result<Self, FormatError>;
+
+    /// Reads a raw bincode dump, from before the format had a version
+    fn from_legacy(bytes: &[u8]) -> Result<Self, FormatError>;
+
+    fn to_bin(&self) -> Result<Vec<u8>, FormatError> {
+        Ok(encode(Self::KIND, &self.to_sections()?))
+    }
+
+    fn from_bin(bytes: &[u8]) -> Result<Self, FormatError> {
+        if !is_container(bytes) {
+            println!(
+                "[WARN] The binary config has no version; rewrite it with genconfig --migrate."
+            );
+            return Self::from_legacy(bytes);
+        }
+        let (kind, version, sections) = decode(bytes)?;
+        if kind != Some(Self::KIND) {
+            return Err(FormatError::WrongKind(Self::KIND, kind));
+        }
+        Self::from_sections(&sections, version)
+    }
+}
+
+/// The sections of a file
+pub struct Sections<'a>(Vec<(u16, &'a [u8])>);
+
+impl Sections<'_> {
+    fn get<T: DeserializeOwned>(&self, tag: u16) -> Result<Option<T>, FormatError> {
+        match self.0.iter().find(|(t, _)| *t == tag) {
+            Some((_, bytes)) => Ok(Some(bincode::deserialize(bytes).map_err(FormatError::Bin)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn require<T: DeserializeOwned>(&self, tag: u16, name: &'static str) -> Result<T, FormatError> {
+        self.get(tag)?.ok_or(FormatError::MissingSection(name))
+    }
+}
+
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn encode(kind: Kind, sections: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(kind as u16).to_le_bytes());
+    out.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    let mut offset = (HEADER_SIZE + ENTRY_SIZE * sections.len()) as u64;
+    for (tag, bytes) in sections {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        offset += bytes.len() as u64;
+    }
+    for (_, bytes) in sections {
+        out.extend_from_slice(bytes);
+    }
+    let checksum = Sha256::digest(&out);
+    out.extend_from_slice(&checksum);
+    out
+}
+
+fn decode(bytes: &[u8]) -> Result<(Option<Kind>, u16, Sections), FormatError> {
+    if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE {
+        return Err(FormatError::Truncated);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+    let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+    let u64_at = |i: usize| {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&body[i..i + 8]);
+        u64::from_le_bytes(b)
+    };
+    let version = u16_at(4);
+    if version > VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err(FormatError::BadChecksum);
+    }
+    let count = u16_at(8) as usize;
+    if body.len() < HEADER_SIZE + ENTRY_SIZE * count {
+        return Err(FormatError::Truncated);
+    }
+    let mut sections = Vec::with_capacity(count);
+    for i in 0..count {
+        let entry = HEADER_SIZE + ENTRY_SIZE * i;
+        let (offset, len) = (u64_at(entry + 2), u64_at(entry + 10));
+        let end = offset.checked_add(len).ok_or(FormatError::Truncated)?;
+        if end > body.len() as u64 {
+            return Err(FormatError::Truncated);
+        }
+        sections.push((u16_at(entry), &body[offset as usize..end as usize]));
+    }
+    Ok((Kind::from_u16(u16_at(6)), version, Sections(sections)))
+}
+
+fn section<T: Serialize>(tag: u16, value: &T) -> Result<(u16, Vec<u8>), FormatError> {
+    Ok((tag, bincode::serialize(value).map_err(FormatError::Bin)?))
+}
+
+/// Serialises a borrowed map with its keys in order
+struct Ordered<'a, K, V>(&'a HashMap<K, V>);
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for Ordered<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        super::ordered(self.0, serializer)
+    }
+}
+
+/// The settings of a node, which are neither shared with the other nodes nor
+/// secret
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    #[serde(serialize_with = "super::ordered")]
+    net_map: HashMap<Replica, super::PeerAddr>,
+    delta: u64,
+    id: Replica,
+    num_nodes: usize,
+    num_faults: usize,
+    block_size: usize,
+    client_port: u16,
+    payload: usize,
+    crypto_alg: Algorithm,
+    hash_alg: HashAlgorithm,
+    bind_addr: Option<SocketAddr>,
+    public: Option<PathBuf>,
+}
+
+type Queue = HashMap<Replica, VecDeque<crypto::EVSSShare381>>;
+/// The dealings before version 2, with the share of every replica and the
+/// commitment once for each of the epochs a dealing is used in
+type Shares = Vec<(
+    Vec<VecDeque<crypto::EVSSShare381>>,
+    Vec<crypto::EVSSCommit381>,
+)>;
+
+/// Keeps one share of every replica and one commitment of each old dealing.
+/// The copies are wiped with `shares`.
+fn dealings(shares: Secret<Shares>) -> Secret<Vec<Dealing>> {
+    let dealings = shares
+        .iter()
+        .filter_map(|(shares, commits)| {
+            let shares = shares.iter().filter_map(|q| q.front().cloned()).collect();
+            Some((shares, commits.first()?.clone()))
+        })
+        .collect();
+    Secret::new(dealings)
+}
+
+impl Binary for Node {
+    const KIND: Kind = Kind::Node;
+
+    fn to_sections(&self) -> Result<Vec<(u16, Vec<u8>)>, FormatError> {
+        let settings = Settings {
+            net_map: self.net_map.clone(),
+            delta: self.delta,
+            id: self.id,
+            num_nodes: self.num_nodes,
+            num_faults: self.num_faults,
+            block_size: self.block_size,
+            client_port: self.client_port,
+            payload: self.payload,
+            crypto_alg: self.crypto_alg.clone(),
+            hash_alg: self.hash_alg,
+            bind_addr: self.bind_addr,
+            public: self.public.clone(),
+        };
+        let mut sections = vec![
+            section(SETTINGS, &settings)?,
+            section(PUBLIC, &Public::from_node(self))?,
+        ];
+        // A config whose secrets are in a keystore has no secret sections
+        if !self.secret_key_bytes.is_empty() || self.bi_p.is_some() || !self.rotated_keys.is_empty()
+        {
+            let keys = (&self.secret_key_bytes, &self.rotated_keys, &self.bi_p);
+            sections.push(section(KEYS, &keys)?);
+        }
+        if !self.rand_beacon_queue.is_empty() || !self.rand_beacon_shares.is_empty() {
+            let beacon = (Ordered(&*self.rand_beacon_queue), &*self.rand_beacon_shares);
+            sections.push(section(BEACON, &beacon)?);
+        }
+        Ok(sections)
+    }
+
+    fn from_sections(sections: &Sections, version: u16) -> Result<Self, FormatError> {
+        let s: Settings = sections.require(SETTINGS, "settings")?;
+        let public: Public = sections.require(PUBLIC, "public")?;
+        let mut node = Node::new();
+        node.net_map = s.net_map;
+        node.delta = s.delta;
+        node.id = s.id;
+        node.num_nodes = s.num_nodes;
+        node.num_faults = s.num_faults;
+        node.block_size = s.block_size;
+        node.client_port = s.client_port;
+        node.payload = s.payload;
+        node.crypto_alg = s.crypto_alg;
+        node.hash_alg = s.hash_alg;
+        node.bind_addr = s.bind_addr;
+        node.public = s.public;
+        node.pk_map = public.pk_map;
+        node.bi_pp_map = public.bi_pp_map;
+        node.rand_beacon_parameter = public.rand_beacon_parameter;
+        let keys: Option<(
+            SecretBytes,
+            Vec<(Height, SecretBytes)>,
+            Option<Secret<crypto::EVSSParams381>>,
+        )> = sections.get(KEYS)?;
+        if let Some((secret_key_bytes, rotated_keys, bi_p)) = keys {
+            node.secret_key_bytes = secret_key_bytes;
+            node.rotated_keys = rotated_keys;
+            node.bi_p = bi_p;
+        }
+        if version < 2 {
+            let beacon: Option<(Secret<Queue>, Secret<Shares>)> = sections.get(BEACON)?;
+            if let Some((queue, shares)) = beacon {
+                node.rand_beacon_queue = queue;
+                node.rand_beacon_shares = dealings(shares);
+            }
+        } else {
+            let beacon: Option<(Secret<Queue>, Secret<Vec<Dealing>>)> = sections.get(BEACON)?;
+            if let Some((queue, shares)) = beacon {
+                node.rand_beacon_queue = queue;
+                node.rand_beacon_shares = shares;
+            }
+        }
+        Ok(node)
+    }
+
+    fn from_legacy(bytes: &[u8]) -> Result<Self, FormatError> {
+        let old: LegacyNode = legacy(bytes)?;
+        let mut node = Node::new();
+        node.net_map = old.net_map;
+        node.delta = old.delta;
+        node.id = old.id;
+        node.num_nodes = old.num_nodes;
+        node.num_faults = old.num_faults;
+        node.block_size = old.block_size;
+        node.client_port = old.client_port;
+        node.payload = old.payload;
+        node.crypto_alg = old.crypto_alg;
+        node.pk_map = old.pk_map;
+        node.secret_key_bytes = old.secret_key_bytes;
+        node.bi_pp_map = old.bi_pp_map;
+        node.bi_p = old.bi_p;
+        node.rand_beacon_parameter = old.rand_beacon_parameter;
+        node.rand_beacon_queue = old.rand_beacon_queue;
+        node.rand_beacon_shares = dealings(old.rand_beacon_shares);
+        Ok(node)
+    }
+}
+
+/// A node config as genconfig dumped it before the format had a version
+#[derive(Deserialize)]
+struct LegacyNode {
+    net_map: HashMap<Replica, super::PeerAddr>,
+    delta: u64,
+    id: Replica,
+    num_nodes: usize,
+    num_faults: usize,
+    block_size: usize,
+    client_port: u16,
+    payload: usize,
+    crypto_alg: Algorithm,
+    pk_map: HashMap<Replica, Vec<u8>>,
+    secret_key_bytes: SecretBytes,
+    bi_pp_map: HashMap<Replica, crypto::EVSSPublicParams381>,
+    bi_p: Option<Secret<crypto::EVSSParams381>>,
+    rand_beacon_parameter: Option<crypto::EVSSParams381>,
+    rand_beacon_queue: Secret<Queue>,
+    rand_beacon_shares: Secret<Shares>,
+}
+
+// The client config and the public material have not changed since the
+// raw dumps, so they are a single section of the same bincode value
+
+impl Binary for Client {
+    const KIND: Kind = Kind::Client;
+
+    fn to_sections(&self) -> Result<Vec<(u16, Vec<u8>)>, FormatError> {
+        Ok(vec![section(SETTINGS, self)?])
+    }
+
+    fn from_sections(sections: &Sections, _version: u16) -> Result<Self, FormatError> {
+        sections.require(SETTINGS, "settings")
+    }
+
+    fn from_legacy(bytes: &[u8]) -> Result<Self, FormatError> {
+        legacy(bytes)
+    }
+}
+
+impl Binary for Public {
+    const KIND: Kind = Kind::Public;
+
+    fn to_sections(&self) -> Result<Vec<(u16, Vec<u8>)>, FormatError> {
+        Ok(vec![section(PUBLIC, self)?])
+    }
+
+    fn from_sections(sections: &Sections, _version: u16) -> Result<Self, FormatError> {
+        sections.require(PUBLIC, "public")
+    }
+
+    fn from_legacy(bytes: &[u8]) -> Result<Self, FormatError> {
+        legacy(bytes)
+    }
+}
+
+/// Decodes a raw dump. It has to be used up exactly, which also tells the
+/// kinds of config apart.
+fn legacy<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .map_err(FormatError::Bin)
+}
+
+/// Rewrites a raw dump of a node config, a client config or the public
+/// material in the current format. Returns what it was, or `None` if the file
+/// already has a version.
+pub fn migrate(path: &Path) -> Result<Option<Kind>, ConfigError> {
+    let io = |e| ConfigError::Io(path.to_path_buf(), e);
+    let bytes = std::fs::read(path).map_err(io)?;
+    if is_container(&bytes) {
+        return Ok(None);
+    }
+    let (kind, out) = match Node::from_legacy(&bytes) {
+        Ok(node) => (Kind::Node, node.to_bin()),
+        Err(e) => match (Client::from_legacy(&bytes), Public::from_legacy(&bytes)) {
+            (Ok(client), _) => (Kind::Client, client.to_bin()),
+            (_, Ok(public)) => (Kind::Public, public.to_bin()),
+            _ => return Err(ConfigError::Format(path.to_path_buf(), e)),
+        },
+    };
+    let out = out.map_err(|e| ConfigError::Format(path.to_path_buf(), e))?;
+    std::fs::write(path, out).map_err(io)?;
+    Ok(Some(kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut client = Client::new();
+        client.num_nodes = 1;
+        client.net_map.insert(0, "10.0.0.1:6000".parse().unwrap());
+        let bytes = client.to_bin().unwrap();
+        assert!(is_container(&bytes));
+        assert_eq!(Client::from_bin(&bytes).unwrap(), client);
+        // Old dumps still load
+        let legacy = bincode::serialize(&client).unwrap();
+        assert_eq!(Client::from_bin(&legacy).unwrap(), client);
+
+        let mut node = Node::new();
+        node.id = 2;
+        node.pk_map.insert(0, vec![1, 2]);
+        node.secret_key_bytes = Secret::new(vec![3; 32]);
+        let back = Node::from_bin(&node.to_bin().unwrap()).unwrap();
+        assert_eq!(back.id, 2);
+        assert_eq!(back.pk_map, node.pk_map);
+        assert_eq!(*back.secret_key_bytes, vec![3; 32]);
+    }
+
+    #[test]
+    fn migrate_legacy_node() {
+        use crypto::rand::SeedableRng;
+        use crypto::UniformRand;
+        // A node dumped in the layout of `LegacyNode`, with a dealing in the
+        // old shape: a queue of shares for each replica and a commitment
+        // for each epoch
+        let rng = &mut crypto::rand::rngs::StdRng::seed_from_u64(7);
+        let params = crypto::EVSS381::setup(1, rng).unwrap();
+        let poly = crypto::EVSS381::commit(&params, crypto::F381::rand(rng), rng).unwrap();
+        let shares: Vec<_> = (1..=2u16)
+            .map(|j| {
+                crypto::EVSS381::get_share(crypto::F381::from(j), &params, &poly, rng).unwrap()
+            })
+            .collect();
+        let old_shares: Shares = vec![(
+            shares.iter().map(|s| vec![s.clone(); 3].into()).collect(),
+            vec![poly.get_commit(); 3],
+        )];
+        let mut net_map = HashMap::new();
+        net_map.insert(
+            0 as Replica,
+            "10.0.0.1:7000".parse::<crate::PeerAddr>().unwrap(),
+        );
+        let mut pk_map = HashMap::new();
+        pk_map.insert(0 as Replica, vec![1u8, 2]);
+        let dump = bincode::serialize(&(
+            net_map.clone(),
+            100u64,
+            1 as Replica,
+            2usize,
+            0usize,
+            400usize,
+            6000u16,
+            0usize,
+            Algorithm::ED25519,
+            pk_map.clone(),
+            vec![3u8; 32],
+            HashMap::<Replica, crypto::EVSSPublicParams381>::new(),
+            None::<crypto::EVSSParams381>,
+            None::<crypto::EVSSParams381>,
+            Queue::new(),
+            old_shares,
+        ))
+        .unwrap();
+        assert!(Node::from_legacy(&dump).is_ok());
+
+        let path =
+            std::env::temp_dir().join(format!("randpiper-migrate-{}.dat", std::process::id()));
+        std::fs::write(&path, &dump).unwrap();
+        assert!(matches!(migrate(&path), Ok(Some(Kind::Node))));
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(is_container(&bytes));
+        assert!(matches!(migrate(&path), Ok(None)));
+        std::fs::remove_file(&path).unwrap();
+
+        let node = Node::from_bin(&bytes).unwrap();
+        assert_eq!(node.net_map, net_map);
+        assert_eq!((node.delta, node.id, node.num_nodes), (100, 1, 2));
+        assert_eq!((node.block_size, node.client_port), (400, 6000));
+        assert_eq!(node.pk_map, pk_map);
+        assert_eq!(*node.secret_key_bytes, vec![3; 32]);
+        // One share of every replica and one commitment are left
+        let (kept, commit) = &node.rand_beacon_shares[0];
+        assert_eq!(node.rand_beacon_shares.len(), 1);
+        assert_eq!(
+            bincode::serialize(kept).unwrap(),
+            bincode::serialize(&shares).unwrap()
+        );
+        assert_eq!(
+            bincode::serialize(commit).unwrap(),
+            bincode::serialize(&poly.get_commit()).unwrap()
+        );
+    }
+
+    #[test]
+    fn damaged() {
+        let bytes = Client::new().to_bin().unwrap();
+        assert!(matches!(
+            Client::from_bin(&bytes[..bytes.len() - 1]),
+            Err(FormatError::BadChecksum)
+        ));
+        assert!(matches!(
+            Client::from_bin(&bytes[..12]),
+            Err(FormatError::Truncated)
+        ));
+        let mut flipped = bytes.clone();
+        flipped[HEADER_SIZE + ENTRY_SIZE] ^= 1;
+        assert!(matches!(
+            Client::from_bin(&flipped),
+            Err(FormatError::BadChecksum)
+        ));
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert!(matches!(
+            Client::from_bin(&newer),
+            Err(FormatError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(
+            Node::from_bin(&bytes),
+            Err(FormatError::WrongKind(Kind::Node, Some(Kind::Client)))
+        ));
+    }
+}
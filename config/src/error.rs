@@ -6,12 +6,28 @@ pub enum ParseError {
     IncorrectFaults(usize, usize),
     // r
     InvalidMapEntry(u16),
+    // r, in a map of public keys
+    InvalidPkEntry(u16),
     // pk_size
     InvalidPkSize(usize),
     // sk_size
     InvalidSkSize(usize),
     // feature name that is not implemented
     Unimplemented(&'static str),
+    // our id
+    InvalidId(u16),
+    // r, address
+    InvalidAddress(u16, String),
+    // field, r
+    MissingEntry(&'static str, u16),
+    // field
+    Missing(&'static str),
+    // field, expected, actual
+    InvalidShape(&'static str, usize, usize),
+    // field of a key that does not decode
+    InvalidKey(&'static str),
+    // our public key is not the one of our secret key
+    KeyMismatch,
 }
 
 impl std::fmt::Display for ParseError {
@@ -24,11 +40,25 @@ impl std::fmt::Display for ParseError {
                 write!(f, "n > 2f not satisfied since {} !> 2x{}", n, fault)
             }
             ParseError::InvalidMapEntry(r) => write!(f, "invalid map entry for {} replica", r),
+            ParseError::InvalidPkEntry(r) => write!(f, "invalid key entry for {} replica", r),
             ParseError::InvalidPkSize(s) => write!(f, "invalid public key size ({})", s),
             ParseError::Unimplemented(feature) => {
                 write!(f, "{} feature is not yet implemented", feature)
             }
             ParseError::InvalidSkSize(s) => write!(f, "invalid secret key size ({})", s),
+            ParseError::InvalidId(id) => write!(f, "{} is not a replica", id),
+            ParseError::InvalidAddress(r, ref addr) => {
+                write!(f, "invalid address {:?} for {} replica", addr, r)
+            }
+            ParseError::MissingEntry(_, r) => write!(f, "no entry for {} replica", r),
+            ParseError::Missing(_) => write!(f, "missing"),
+            ParseError::InvalidShape(_, exp, actual) => {
+                write!(f, "expected {} entries, got {}", exp, actual)
+            }
+            ParseError::InvalidKey(_) => write!(f, "the key does not decode"),
+            ParseError::KeyMismatch => {
+                write!(f, "our public key does not match our secret key")
+            }
         }
     }
 }
@@ -39,9 +69,17 @@ impl std::error::Error for ParseError {
             ParseError::InvalidMapLen(_, _) => "invalid map length",
             ParseError::IncorrectFaults(_, _) => "incorrect f and n values",
             ParseError::InvalidMapEntry(_) => "incorrect map entry",
+            ParseError::InvalidPkEntry(_) => "incorrect key entry",
             ParseError::InvalidPkSize(_) => "invalid public key size",
             ParseError::InvalidSkSize(_) => "invalid secret key size",
             ParseError::Unimplemented(_) => "feature unimplemented",
+            ParseError::InvalidId(_) => "invalid id",
+            ParseError::InvalidAddress(_, _) => "invalid address",
+            ParseError::MissingEntry(_, _) => "missing map entry",
+            ParseError::Missing(_) => "missing field",
+            ParseError::InvalidShape(_, _, _) => "invalid number of entries",
+            ParseError::InvalidKey(_) => "invalid key",
+            ParseError::KeyMismatch => "mismatched key pair",
         }
     }
 
@@ -51,3 +89,232 @@ impl std::error::Error for ParseError {
         }
     }
 }
+
+impl ParseError {
+    /// The config field the error is about
+    pub fn field(&self) -> &'static str {
+        match self {
+            ParseError::InvalidMapLen(_, _) | ParseError::InvalidMapEntry(_) => "net_map",
+            ParseError::IncorrectFaults(_, _) => "num_faults",
+            ParseError::InvalidPkEntry(_) | ParseError::InvalidPkSize(_) => "pk_map",
+            ParseError::InvalidSkSize(_) => "secret_key_bytes",
+            ParseError::Unimplemented(_) => "crypto_alg",
+            ParseError::InvalidId(_) => "id",
+            ParseError::InvalidAddress(_, _) => "net_map",
+            ParseError::MissingEntry(field, _)
+            | ParseError::Missing(field)
+            | ParseError::InvalidShape(field, _, _)
+            | ParseError::InvalidKey(field) => field,
+            ParseError::KeyMismatch => "pk_map",
+        }
+    }
+}
+
+/// Every problem found when validating a config
+#[derive(Debug)]
+pub struct Violations(pub Vec<ParseError>);
+
+impl Violations {
+    pub(crate) fn check(errs: Vec<ParseError>) -> Result<(), Violations> {
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(Violations(errs))
+        }
+    }
+}
+
+impl std::fmt::Display for Violations {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} problem(s) in the config:", self.0.len())?;
+        for e in &self.0 {
+            write!(f, "\n  {}: {}", e.field(), e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Violations {}
+
+/// A config file in a format that did not parse
+#[derive(Debug)]
+pub enum FormatError {
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+    Bin(bincode::Error),
+    // TOML has to be text
+    Utf8(std::str::Utf8Error),
+    // binary configs
+    Truncated,
+    BadChecksum,
+    UnsupportedVersion(u16),
+    // expected, found
+    WrongKind(crate::container::Kind, Option<crate::container::Kind>),
+    MissingSection(&'static str),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FormatError::Json(e) => write!(f, "invalid JSON: {}", e),
+            FormatError::Toml(e) => write!(f, "invalid TOML: {}", e),
+            FormatError::Yaml(e) => write!(f, "invalid YAML: {}", e),
+            FormatError::Bin(e) => write!(f, "invalid binary config: {}", e),
+            FormatError::Utf8(e) => write!(f, "invalid TOML: {}", e),
+            FormatError::Truncated => write!(f, "the binary config is truncated"),
+            FormatError::BadChecksum => {
+                write!(f, "the checksum of the binary config does not match")
+            }
+            FormatError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "the binary config has version {}, newer than this build",
+                    v
+                )
+            }
+            FormatError::WrongKind(expected, found) => match found {
+                Some(found) => write!(
+                    f,
+                    "expected a {:?} config, found a {:?} config",
+                    expected, found
+                ),
+                None => write!(f, "expected a {:?} config, found an unknown kind", expected),
+            },
+            FormatError::MissingSection(s) => write!(f, "the binary config has no {} section", s),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Json(e) => Some(e),
+            FormatError::Toml(e) => Some(e),
+            FormatError::Yaml(e) => Some(e),
+            FormatError::Bin(e) => Some(e),
+            FormatError::Utf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An override of a config field from the environment or the command line.
+/// The first field is where the override came from.
+#[derive(Debug)]
+pub enum OverrideError {
+    // not a public field of the config
+    UnknownField(String),
+    InvalidValue(String, serde_yaml::Error),
+    // not of the form field=value
+    Malformed(String),
+    InvalidAddress(String, crate::InvalidPeerAddr),
+}
+
+impl std::fmt::Display for OverrideError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OverrideError::UnknownField(src) => write!(f, "{}: not a public config field", src),
+            OverrideError::InvalidValue(src, e) => write!(f, "{}: invalid value: {}", src, e),
+            OverrideError::Malformed(src) => write!(f, "{}: expected field=value", src),
+            OverrideError::InvalidAddress(src, e) => write!(f, "{}: {}", src, e),
+        }
+    }
+}
+
+impl std::error::Error for OverrideError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OverrideError::InvalidValue(_, e) => Some(e),
+            OverrideError::InvalidAddress(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Failure to load a config file, with the path of the file
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::path::PathBuf, std::io::Error),
+    Format(std::path::PathBuf, FormatError),
+    Invalid(std::path::PathBuf, Violations),
+    Override(OverrideError),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Format(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Override(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::Format(_, e) => Some(e),
+            ConfigError::Invalid(_, e) => Some(e),
+            ConfigError::Override(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(std::io::Error),
+    Format(bincode::Error),
+    // the keystore is encrypted but no passphrase was given
+    NeedPassphrase,
+    WrongPassphrase,
+    // expected, got
+    WrongNode(u16, u16),
+    // failure in deriving the key or encrypting
+    Crypto,
+}
+
+impl std::fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "keystore io error: {}", e),
+            KeystoreError::Format(e) => write!(f, "invalid keystore: {}", e),
+            KeystoreError::NeedPassphrase => {
+                write!(f, "the keystore is encrypted; a passphrase is needed")
+            }
+            KeystoreError::WrongPassphrase => {
+                write!(f, "wrong passphrase or corrupted keystore")
+            }
+            KeystoreError::WrongNode(exp, actual) => write!(
+                f,
+                "keystore belongs to node {}, expected node {}",
+                actual, exp
+            ),
+            KeystoreError::Crypto => write!(f, "failed to encrypt the keystore"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeystoreError::Io(e) => Some(e),
+            KeystoreError::Format(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for KeystoreError {
+    fn from(e: std::io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for KeystoreError {
+    fn from(e: bincode::Error) -> Self {
+        KeystoreError::Format(e)
+    }
+}
@@ -0,0 +1,104 @@
+// Reading config files in any of the formats genconfig writes.
+
+use super::container::Binary;
+use super::{ConfigError, FormatError};
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+    Bin,
+}
+
+impl Format {
+    /// The format a file extension stands for
+    pub fn from_extension(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "dat" | "bin" => Some(Format::Bin),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the contents of a file
+    pub fn detect(bytes: &[u8]) -> Format {
+        // Text configs never hold a NUL, while bincode lengths nearly always do
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) if !bytes.contains(&0) => text,
+            _ => return Format::Bin,
+        };
+        let first = text
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#'));
+        match first {
+            Some(l) if l.starts_with('{') => Format::Json,
+            // A TOML table header
+            Some(l) if l.starts_with('[') => Format::Toml,
+            // `key = value` in TOML, `key: value` in YAML
+            Some(l) => match (l.find('='), l.find(':')) {
+                (Some(eq), Some(colon)) if eq < colon => Format::Toml,
+                (Some(_), None) => Format::Toml,
+                _ => Format::Yaml,
+            },
+            None => Format::Yaml,
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned + Binary>(self, bytes: &[u8]) -> Result<T, FormatError> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(FormatError::Json),
+            Format::Toml => {
+                let text = std::str::from_utf8(bytes).map_err(FormatError::Utf8)?;
+                toml::from_str(text).map_err(FormatError::Toml)
+            }
+            Format::Yaml => serde_yaml::from_slice(bytes).map_err(FormatError::Yaml),
+            Format::Bin => T::from_bin(bytes),
+        }
+    }
+}
+
+/// Reads a config file, in the format of its extension if it has a known
+/// one, and in the format of its contents otherwise
+pub(crate) fn read<T: DeserializeOwned + Binary>(
+    path: &Path,
+    format: Option<Format>,
+) -> Result<T, ConfigError> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut buf))
+        .map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let format = format
+        .or_else(|| Format::from_extension(path))
+        .unwrap_or_else(|| Format::detect(&buf));
+    format
+        .parse(&buf)
+        .map_err(|e| ConfigError::Format(path.to_path_buf(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(Format::detect(b"{\"delta\": 50}"), Format::Json);
+        assert_eq!(Format::detect(b"# node 0\ndelta = 50\n"), Format::Toml);
+        assert_eq!(Format::detect(b"[net_map]\n0 = \"a:1\""), Format::Toml);
+        assert_eq!(Format::detect(b"---\ndelta: 50\n"), Format::Yaml);
+        assert_eq!(Format::detect(b"net_map:\n  0: \"a=1\"\n"), Format::Yaml);
+        assert_eq!(Format::detect(&[4, 0, 0, 0, 0, 0, 0, 0]), Format::Bin);
+        assert_eq!(
+            Format::from_extension(Path::new("nodes-0.yml")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(Format::from_extension(Path::new("nodes-0")), None);
+    }
+}
@@ -0,0 +1,175 @@
+// Key material of a node, kept out of its config so that the config can be
+// shared. The keystore may be encrypted with a passphrase.
+
+use super::KeystoreError;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use crypto::secret::{Secret, SecretBytes, Zeroizing};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::prelude::*;
+use types::{Height, Replica};
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+/// Binds the ciphertext to its use
+const AAD: &[u8] = b"randpiper keystore";
+
+#[derive(Serialize, Deserialize)]
+pub struct Keystore {
+    /// The node the keys belong to
+    pub id: Replica,
+    pub secret_key_bytes: SecretBytes,
+    pub bi_p: Option<Secret<crypto::EVSSParams381>>,
+    #[serde(serialize_with = "super::ordered_secret")]
+    pub rand_beacon_queue: Secret<HashMap<Replica, VecDeque<crypto::EVSSShare381>>>,
+    pub rand_beacon_shares: Secret<Vec<super::Dealing>>,
+    /// Secret keys the node may rotate to. The epoch is the one the rotation
+    /// was proposed for; a key is only used from the epoch of the committed
+    /// rotation to it
+    pub rotated_keys: Vec<(Height, SecretBytes)>,
+}
+
+/// The keystore as it is on disk
+#[derive(Serialize, Deserialize)]
+enum KeystoreFile {
+    Plain(SecretBytes),
+    Encrypted {
+        salt: [u8; SALT_SIZE],
+        nonce: [u8; NONCE_SIZE],
+        ciphertext: Vec<u8>,
+    },
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, KeystoreError> {
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+        .map_err(|_| KeystoreError::Crypto)?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&*key)))
+}
+
+impl Keystore {
+    /// Serialises the keystore, encrypted if there is a passphrase
+    pub fn to_bytes(&self, passphrase: Option<&str>) -> Result<Vec<u8>, KeystoreError> {
+        let plain = SecretBytes::new(bincode::serialize(self)?);
+        let file = match passphrase {
+            None => KeystoreFile::Plain(plain),
+            Some(p) => {
+                let salt: [u8; SALT_SIZE] = crypto::rand::random();
+                let nonce: [u8; NONCE_SIZE] = crypto::rand::random();
+                let payload = Payload {
+                    msg: &plain,
+                    aad: AAD,
+                };
+                let ciphertext = cipher(p, &salt)?
+                    .encrypt(Nonce::from_slice(&nonce), payload)
+                    .map_err(|_| KeystoreError::Crypto)?;
+                KeystoreFile::Encrypted {
+                    salt,
+                    nonce,
+                    ciphertext,
+                }
+            }
+        };
+        Ok(bincode::serialize(&file)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8], passphrase: Option<&str>) -> Result<Self, KeystoreError> {
+        let plain = match bincode::deserialize(bytes)? {
+            KeystoreFile::Plain(plain) => plain,
+            KeystoreFile::Encrypted {
+                salt,
+                nonce,
+                ciphertext,
+            } => {
+                let p = passphrase.ok_or(KeystoreError::NeedPassphrase)?;
+                let payload = Payload {
+                    msg: &ciphertext,
+                    aad: AAD,
+                };
+                let plain = cipher(p, &salt)?
+                    .decrypt(Nonce::from_slice(&nonce), payload)
+                    .map_err(|_| KeystoreError::WrongPassphrase)?;
+                SecretBytes::new(plain)
+            }
+        };
+        Ok(bincode::deserialize(&plain)?)
+    }
+
+    pub fn write(&self, filename: &str, passphrase: Option<&str>) -> Result<(), KeystoreError> {
+        let bytes = self.to_bytes(passphrase)?;
+        let mut f = File::create(filename)?;
+        f.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Drops the signing keys, for a node that signs through a daemon. They
+    /// are wiped here rather than kept until the node exits.
+    pub fn without_signing_keys(mut self) -> Self {
+        self.secret_key_bytes = SecretBytes::default();
+        self.rotated_keys = Vec::new();
+        self
+    }
+
+    pub fn read(filename: &str, passphrase: Option<&str>) -> Result<Self, KeystoreError> {
+        let mut buf = Zeroizing::new(Vec::new());
+        File::open(filename)?.read_to_end(&mut buf)?;
+        Keystore::from_bytes(&buf, passphrase)
+    }
+}
+
+/// Reads a passphrase from the first line of a file
+pub fn read_passphrase(filename: &str) -> Result<Zeroizing<String>, KeystoreError> {
+    let mut buf = Zeroizing::new(String::new());
+    File::open(filename)?.read_to_string(&mut buf)?;
+    Ok(Zeroizing::new(buf.lines().next().unwrap_or("").to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystore() -> Keystore {
+        Keystore {
+            id: 2,
+            secret_key_bytes: Secret::new(vec![7; 64]),
+            bi_p: None,
+            rand_beacon_queue: Secret::default(),
+            rand_beacon_shares: Secret::default(),
+            rotated_keys: vec![(5, Secret::new(vec![8; 64]))],
+        }
+    }
+
+    #[test]
+    fn without_signing_keys() {
+        let keys = keystore().without_signing_keys();
+        assert!(keys.secret_key_bytes.is_empty());
+        assert!(keys.rotated_keys.is_empty());
+        assert_eq!(keys.id, 2);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let plain = keystore().to_bytes(None).unwrap();
+        let ks = Keystore::from_bytes(&plain, None).unwrap();
+        assert_eq!(*ks.secret_key_bytes, vec![7; 64]);
+
+        let encrypted = keystore().to_bytes(Some("passphrase")).unwrap();
+        assert!(!encrypted.windows(64).any(|w| w == &[7; 64][..]));
+        let ks = Keystore::from_bytes(&encrypted, Some("passphrase")).unwrap();
+        assert_eq!(ks.id, 2);
+        assert_eq!(*ks.secret_key_bytes, vec![7; 64]);
+        assert!(matches!(
+            Keystore::from_bytes(&encrypted, Some("wrong")),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            Keystore::from_bytes(&encrypted, None),
+            Err(KeystoreError::NeedPassphrase)
+        ));
+    }
+}
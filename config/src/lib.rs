@@ -15,6 +15,50 @@ pub use node::*;
 mod error;
 pub use error::*;
 
+mod keystore;
+pub use keystore::*;
+
+mod addr;
+pub use addr::*;
+
+mod format;
+pub use format::Format;
+
+pub mod container;
+pub use container::Binary;
+
+mod public;
+pub use public::Public;
+
+mod overrides;
+pub use overrides::{ENV_PREFIX, PUBLIC_FIELDS};
+
 fn is_valid_replica(r: types::Replica, n: usize) -> bool {
     n > r as usize
 }
+
+/// Serialises a map with its keys in order, so that the same config is
+/// always written out the same way
+fn ordered<K, V, S>(map: &std::collections::HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + serde::Serialize,
+    V: serde::Serialize,
+    S: serde::Serializer,
+{
+    use serde::Serialize;
+    let map: std::collections::BTreeMap<_, _> = map.iter().collect();
+    map.serialize(serializer)
+}
+
+fn ordered_secret<K, V, S>(
+    map: &crypto::secret::Secret<std::collections::HashMap<K, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: Ord + serde::Serialize,
+    V: serde::Serialize,
+    S: serde::Serializer,
+    std::collections::HashMap<K, V>: crypto::secret::Wipe,
+{
+    ordered(map, serializer)
+}
@@ -7,20 +7,31 @@
 // Crypto Config:
 //     algorithm_type, pvt_key, map[id]public_key
 
-use super::{is_valid_replica, ParseError};
+use super::format::{self, Format};
+use super::{
+    is_valid_replica, ConfigError, Keystore, KeystoreError, ParseError, PeerAddr, Violations,
+};
+use crypto::hash::HashAlgorithm;
+use crypto::secret::{Secret, SecretBytes};
 use crypto::Algorithm;
 use serde::{Deserialize, Serialize};
-use serde_json::from_reader;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
-use toml::from_str;
-use types::Replica;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use types::{Height, Replica};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A dealing made ahead of time: a share of one polynomial for every
+/// replica, and the commitment to it
+pub type Dealing = (Vec<crypto::EVSSShare381>, crypto::EVSSCommit381);
+
+/// The config of a node. It is not `Clone`, and `Debug` leaves out the
+/// secrets, so that they are neither copied around nor printed.
+#[derive(Serialize, Deserialize)]
 pub struct Node {
     // Node network config
-    pub net_map: HashMap<Replica, String>,
+    #[serde(serialize_with = "super::ordered")]
+    pub net_map: HashMap<Replica, PeerAddr>,
 
     // protocol details
     pub delta: u64,
@@ -33,68 +44,188 @@ pub struct Node {
 
     // Crypto primitives
     pub crypto_alg: Algorithm,
+    #[serde(default, serialize_with = "super::ordered")]
     pub pk_map: HashMap<Replica, Vec<u8>>,
-    pub secret_key_bytes: Vec<u8>,
+    pub secret_key_bytes: SecretBytes,
 
     // Biaccumulator setup
+    #[serde(default, serialize_with = "super::ordered")]
     pub bi_pp_map: HashMap<Replica, crypto::EVSSPublicParams381>,
-    pub bi_p: Option<crypto::EVSSParams381>,
+    pub bi_p: Option<Secret<crypto::EVSSParams381>>,
 
+    #[serde(default)]
     pub rand_beacon_parameter: Option<crypto::EVSSParams381>,
-    pub rand_beacon_queue: HashMap<Replica, std::collections::VecDeque<crypto::EVSSShare381>>,
+    #[serde(serialize_with = "super::ordered_secret")]
+    pub rand_beacon_queue: Secret<HashMap<Replica, std::collections::VecDeque<crypto::EVSSShare381>>>,
+
+    /// Our dealings, one for each epoch we lead, in turn. A dealing is used
+    /// as the polynomial of each of the next n epochs.
+    pub rand_beacon_shares: Secret<Vec<Dealing>>,
+
+    /// Secret keys used from an epoch on, after a key rotation
+    #[serde(default)]
+    pub rotated_keys: Vec<(Height, SecretBytes)>,
+
+    /// Where to listen for the other nodes, if not on every interface at the
+    /// port of our address in `net_map`
+    #[serde(default)]
+    pub bind_addr: Option<SocketAddr>,
+
+    /// A file with the public keys and beacon parameters, which are then left
+    /// out of this config
+    #[serde(default)]
+    pub public: Option<PathBuf>,
 
-    pub rand_beacon_shares: Vec<(Vec<std::collections::VecDeque<crypto::EVSSShare381>>, Vec<crypto::EVSSCommit381>)>,
+    /// The hash function of the protocol. The default only applies to the
+    /// text formats: binary configs are read section by section in
+    /// `container`, and the ones dumped before it go through `LegacyNode`.
+    #[serde(default)]
+    pub hash_alg: HashAlgorithm,
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("net_map", &self.net_map)
+            .field("delta", &self.delta)
+            .field("id", &self.id)
+            .field("num_nodes", &self.num_nodes)
+            .field("num_faults", &self.num_faults)
+            .field("block_size", &self.block_size)
+            .field("client_port", &self.client_port)
+            .field("payload", &self.payload)
+            .field("crypto_alg", &self.crypto_alg)
+            .field("hash_alg", &self.hash_alg)
+            .field("pk_map", &self.pk_map)
+            .field("bind_addr", &self.bind_addr)
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Node {
-    pub fn validate(&self) -> Result<(), ParseError> {
-        if self.net_map.len() != self.num_nodes {
-            return Err(ParseError::InvalidMapLen(
-                self.num_nodes,
-                self.net_map.len(),
-            ));
-        }
-        if 2 * self.num_faults >= self.num_nodes {
-            return Err(ParseError::IncorrectFaults(self.num_faults, self.num_nodes));
-        }
-        for repl in &self.net_map {
-            if !is_valid_replica(*repl.0, self.num_nodes) {
-                return Err(ParseError::InvalidMapEntry(*repl.0));
+    /// Checks everything in the public config. The secrets may still have
+    /// to come from a keystore.
+    pub fn validate_public(&self) -> Result<(), Violations> {
+        let mut errs = Vec::new();
+        self.check_public(&mut errs);
+        Violations::check(errs)
+    }
+
+    /// Checks everything but the secret key, which may be held by a signer
+    /// outside of the node
+    pub fn validate_without_key(&self) -> Result<(), Violations> {
+        let mut errs = Vec::new();
+        self.check_public(&mut errs);
+        self.check_shares(&mut errs);
+        Violations::check(errs)
+    }
+
+    pub fn validate(&self) -> Result<(), Violations> {
+        let mut errs = Vec::new();
+        self.check_public(&mut errs);
+        self.check_shares(&mut errs);
+        self.check_key(&mut errs);
+        Violations::check(errs)
+    }
+
+    fn check_public(&self, errs: &mut Vec<ParseError>) {
+        let n = self.num_nodes;
+        if self.net_map.len() != n {
+            errs.push(ParseError::InvalidMapLen(n, self.net_map.len()));
+        }
+        if 2 * self.num_faults >= n {
+            errs.push(ParseError::IncorrectFaults(self.num_faults, n));
+        }
+        if !is_valid_replica(self.id, n) {
+            errs.push(ParseError::InvalidId(self.id));
+        }
+        for r in self.net_map.keys() {
+            if !is_valid_replica(*r, n) {
+                errs.push(ParseError::InvalidMapEntry(*r));
             }
         }
-        match self.crypto_alg {
-            Algorithm::ED25519 => {
-                for repl in &self.pk_map {
-                    if !is_valid_replica(*repl.0, self.num_nodes) {
-                        return Err(ParseError::InvalidMapEntry(*repl.0));
+        match crypto::scheme::lookup(&self.crypto_alg) {
+            Err(_) => errs.push(ParseError::Unimplemented("signature scheme")),
+            Ok(scheme) => {
+                for (r, pk) in &self.pk_map {
+                    if !is_valid_replica(*r, n) {
+                        errs.push(ParseError::InvalidPkEntry(*r));
+                    } else if pk.len() != scheme.pk_size() {
+                        errs.push(ParseError::InvalidPkSize(pk.len()));
                     }
-                    if repl.1.len() != crypto::ED25519_PK_SIZE {
-                        return Err(ParseError::InvalidPkSize(repl.1.len()));
-                    }
-                }
-                if self.secret_key_bytes.len() != crypto::ED25519_PVT_SIZE {
-                    return Err(ParseError::InvalidSkSize(self.secret_key_bytes.len()));
                 }
             }
-            Algorithm::SECP256K1 => {
-                for repl in &self.pk_map {
-                    if !is_valid_replica(*repl.0, self.num_nodes) {
-                        return Err(ParseError::InvalidMapEntry(*repl.0));
-                    }
-                    if repl.1.len() != crypto::SECP256K1_PK_SIZE {
-                        return Err(ParseError::InvalidPkSize(repl.1.len()));
-                    }
-                }
-                if self.secret_key_bytes.len() != crypto::SECP256K1_PVT_SIZE {
-                    return Err(ParseError::InvalidSkSize(self.secret_key_bytes.len()));
-                }
+        }
+        // The context looks these up for every replica
+        for r in 0..n as Replica {
+            if !self.pk_map.contains_key(&r) {
+                errs.push(ParseError::MissingEntry("pk_map", r));
             }
-            Algorithm::RSA => {
-                // Because unimplemented
-                return Err(ParseError::Unimplemented("RSA"));
+            if !self.bi_pp_map.contains_key(&r) {
+                errs.push(ParseError::MissingEntry("bi_pp_map", r));
             }
         }
-        Ok(())
+        if self.rand_beacon_parameter.is_none() {
+            errs.push(ParseError::Missing("rand_beacon_parameter"));
+        }
+    }
+
+    /// The accumulator parameters and the shares of the random beacon
+    fn check_shares(&self, errs: &mut Vec<ParseError>) {
+        let n = self.num_nodes;
+        if self.bi_p.is_none() {
+            errs.push(ParseError::Missing("bi_p"));
+        }
+        // Every dealer has shares for us until its own dealings come in
+        let queued = n + self.num_faults;
+        for r in 0..n as Replica {
+            match self.rand_beacon_queue.get(&r) {
+                None => errs.push(ParseError::MissingEntry("rand_beacon_queue", r)),
+                Some(q) if q.len() < queued => {
+                    errs.push(ParseError::InvalidShape("rand_beacon_queue", queued, q.len()))
+                }
+                Some(_) => {}
+            }
+        }
+        // A dealing has a share for every replica
+        let bad = self
+            .rand_beacon_shares
+            .iter()
+            .map(|(shares, _)| shares.len())
+            .find(|len| *len != n);
+        if let Some(len) = bad {
+            errs.push(ParseError::InvalidShape("rand_beacon_shares", n, len));
+        }
+    }
+
+    /// The secret key, and that it is the one for our public key
+    fn check_key(&self, errs: &mut Vec<ParseError>) {
+        let scheme = match crypto::scheme::lookup(&self.crypto_alg) {
+            Ok(scheme) => scheme,
+            // Reported with the public config
+            Err(_) => return,
+        };
+        let len = self.secret_key_bytes.len();
+        match scheme.sk_size() {
+            Some(size) if len != size => return errs.push(ParseError::InvalidSkSize(len)),
+            None if len == 0 => return errs.push(ParseError::InvalidSkSize(0)),
+            _ => {}
+        }
+        let sk = match scheme.decode_sk(&self.secret_key_bytes) {
+            Ok(sk) => sk,
+            Err(_) => return errs.push(ParseError::InvalidKey("secret_key_bytes")),
+        };
+        let pk = match self.pk_map.get(&self.id).map(|pk| scheme.decode_pk(pk)) {
+            Some(Ok(pk)) => pk,
+            Some(Err(_)) => return errs.push(ParseError::InvalidKey("pk_map")),
+            // Reported with the public config
+            None => return,
+        };
+        let probe = b"randpiper key check";
+        if !matches!(sk.sign(probe), Ok(sig) if pk.verify(probe, &sig)) {
+            errs.push(ParseError::KeyMismatch);
+        }
     }
 
     pub fn new() -> Node {
@@ -102,84 +233,209 @@ impl Node {
             block_size: 0,
             client_port: 0,
             crypto_alg: Algorithm::ED25519,
+            hash_alg: HashAlgorithm::SHA256,
             delta: 50,
             id: 0,
             net_map: HashMap::new(),
             num_faults: 0,
             num_nodes: 0,
             pk_map: HashMap::new(),
-            secret_key_bytes: Vec::new(),
+            secret_key_bytes: Secret::default(),
             payload: 0,
             bi_pp_map: HashMap::new(),
             bi_p: None,
             rand_beacon_parameter: None,
-            rand_beacon_queue: HashMap::new(),
-            rand_beacon_shares: Vec::new(),
-        }
-    }
-
-    pub fn from_json(filename: String) -> Node {
-        let f = File::open(filename).unwrap();
-        let c: Node = from_reader(f).unwrap();
-        return c;
-    }
-
-    pub fn from_toml(filename: String) -> Node {
-        let mut buf = String::new();
-        let mut f = File::open(filename).unwrap();
-        f.read_to_string(&mut buf).unwrap();
-        let c: Node = from_str(&buf).unwrap();
-        return c;
-    }
-
-    pub fn from_yaml(filename: String) -> Node {
-        let f = File::open(filename).unwrap();
-        let c: Node = serde_yaml::from_reader(f).unwrap();
-        return c;
-    }
-
-    pub fn from_bin(filename: String) -> Node {
-        let mut buf = Vec::new();
-        let mut f = File::open(filename).unwrap();
-        f.read_to_end(&mut buf).unwrap();
-        let bytes: &[u8] = &buf;
-        let c: Node = bincode::deserialize(bytes).unwrap();
-        return c;
-    }
-
-    pub fn update_config(&mut self, ips: Vec<String>) {
-        let mut idx = 0;
-        for ip in ips {
-            // For self ip, put 0.0.0.0 with the same port
-            if idx == self.id {
-                let port: u16 = ip
-                    .split(":")
-                    .last()
-                    .expect("invalid ip found; unable to split at :")
-                    .parse()
-                    .expect("failed to parse the port after :");
-                self.net_map.insert(idx, format!("0.0.0.0:{}", port));
-                idx += 1;
-                continue;
-            }
-            // Put others ips in the config
-            self.net_map.insert(idx, ip);
-            idx += 1;
+            rand_beacon_queue: Secret::default(),
+            rand_beacon_shares: Secret::default(),
+            rotated_keys: Vec::new(),
+            bind_addr: None,
+            public: None,
         }
-        // println!("Talking to servers: {:?}", self.net_map);
     }
 
-    pub fn my_ip(&self) -> String {
-        // Small string, so it is okay to clone
+    /// Moves the secrets out of the config, leaving a config that can be
+    /// shared
+    pub fn take_keys(&mut self) -> Keystore {
+        Keystore {
+            id: self.id,
+            secret_key_bytes: std::mem::take(&mut self.secret_key_bytes),
+            bi_p: self.bi_p.take(),
+            rand_beacon_queue: std::mem::take(&mut self.rand_beacon_queue),
+            rand_beacon_shares: std::mem::take(&mut self.rand_beacon_shares),
+            rotated_keys: std::mem::take(&mut self.rotated_keys),
+        }
+    }
+
+    /// Puts the secrets from a keystore back into the config
+    pub fn set_keys(&mut self, keys: Keystore) -> Result<(), KeystoreError> {
+        if keys.id != self.id {
+            return Err(KeystoreError::WrongNode(self.id, keys.id));
+        }
+        self.secret_key_bytes = keys.secret_key_bytes;
+        self.bi_p = keys.bi_p;
+        self.rand_beacon_queue = keys.rand_beacon_queue;
+        self.rand_beacon_shares = keys.rand_beacon_shares;
+        self.rotated_keys = keys.rotated_keys;
+        Ok(())
+    }
+
+    /// Loads a config in the format of its extension (`.json`, `.toml`,
+    /// `.yaml` or `.yml`, `.dat`), or of its contents if the extension is
+    /// unknown. Everything but the secrets is validated, as those may still
+    /// have to come from a keystore.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Node, ConfigError> {
+        Node::read(path.as_ref(), None)
+    }
+
+    /// Loads a config in `format`, whatever its extension
+    pub fn load_as<P: AsRef<Path>>(path: P, format: Format) -> Result<Node, ConfigError> {
+        Node::read(path.as_ref(), Some(format))
+    }
+
+    fn read(path: &Path, format: Option<Format>) -> Result<Node, ConfigError> {
+        let mut c: Node = format::read(path, format)?;
+        c.resolve_public(path)?;
+        c.validate_public()
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        Ok(c)
+    }
+
+    /// Replaces the addresses of the nodes with `ips`, in the order of the
+    /// replicas
+    pub fn update_config(&mut self, ips: Vec<String>) -> Result<(), ParseError> {
+        for (idx, ip) in ips.into_iter().enumerate() {
+            let addr = ip
+                .parse()
+                .map_err(|_| ParseError::InvalidAddress(idx as Replica, ip))?;
+            self.net_map.insert(idx as Replica, addr);
+        }
+        Ok(())
+    }
+
+    pub fn my_ip(&self) -> &PeerAddr {
         self.net_map
             .get(&self.id)
             .expect("Failed to obtain IP for self. Incorrect config file.")
-            .clone()
+    }
+
+    /// The address we listen on for the other nodes
+    pub fn bind_addr(&self) -> SocketAddr {
+        self.bind_addr.unwrap_or_else(|| self.my_ip().bind_addr())
+    }
+
+    /// The addresses to hand to the network: ours to listen on, and the
+    /// others to connect to. Hostnames are passed on as they are, so that
+    /// they are resolved again whenever a connection is made.
+    pub fn network_map(&self) -> HashMap<Replica, String> {
+        self.net_map
+            .iter()
+            .map(|(r, addr)| {
+                if *r == self.id {
+                    (*r, self.bind_addr().to_string())
+                } else {
+                    (*r, addr.to_string())
+                }
+            })
+            .collect()
+    }
+
+    /// Resolves the addresses of the other nodes once, and returns those that
+    /// do not resolve yet
+    pub fn unresolved_peers(&self) -> Vec<(Replica, std::io::Error)> {
+        self.net_map
+            .iter()
+            .filter(|(r, _)| **r != self.id)
+            .filter_map(|(r, addr)| addr.resolve().err().map(|e| (*r, e)))
+            .collect()
     }
 
     /// Returns the address at which a server should listen to incoming client
     /// connections
     pub fn client_ip(&self) -> String {
-        format!("0.0.0.0:{}", self.client_port)
+        SocketAddr::new(self.bind_addr().ip(), self.client_port).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_leaves_out_secrets() {
+        let mut node = Node::new();
+        // A byte run that nothing public in the config prints
+        node.secret_key_bytes = Secret::new([0xde, 0xad, 0xbe, 0xef].repeat(8));
+        node.rotated_keys.push((5, Secret::new([0xde, 0xad, 0xbe, 0xee].repeat(8))));
+        let s = format!("{:?}", node);
+        assert!(!s.contains("222, 173, 190") && !s.contains("0xde, 0xad, 0xbe"));
+        assert!(!s.contains("secret_key_bytes") && !s.contains("rotated_keys"));
+    }
+
+    #[test]
+    fn addresses() {
+        let mut node = Node::new();
+        node.id = 1;
+        node.client_port = 9001;
+        let ips = vec!["10.0.0.1:4000".to_string(), "[fd00::2]:4001".to_string()];
+        node.update_config(ips).unwrap();
+        assert_eq!(node.network_map()[&0], "10.0.0.1:4000");
+        assert_eq!(node.network_map()[&1], "[::]:4001");
+        assert_eq!(node.client_ip(), "[::]:9001");
+
+        node.bind_addr = Some("10.0.0.2:5000".parse().unwrap());
+        assert_eq!(node.network_map()[&1], "10.0.0.2:5000");
+        assert_eq!(node.client_ip(), "10.0.0.2:9001");
+
+        let ips = vec!["node-0:4000".to_string(), "node-1".to_string()];
+        assert!(matches!(
+            node.update_config(ips),
+            Err(ParseError::InvalidAddress(1, _))
+        ));
+    }
+
+    #[test]
+    fn validate_lists_every_violation() {
+        let mut node = Node::new();
+        node.num_nodes = 2;
+        node.id = 3;
+        node.net_map.insert(0, "127.0.0.1:5000".parse().unwrap());
+        let errs = node.validate_public().unwrap_err().0;
+        let fields: Vec<_> = errs.iter().map(|e| e.field()).collect();
+        assert!(fields.contains(&"num_faults"));
+        assert!(fields.contains(&"id"));
+        assert!(fields.contains(&"net_map"));
+        assert!(fields.contains(&"pk_map"));
+        assert!(fields.contains(&"bi_pp_map"));
+        assert!(fields.contains(&"rand_beacon_parameter"));
+        assert!(!fields.contains(&"bi_p"));
+        let errs = node.validate().unwrap_err().0;
+        assert!(errs.iter().any(|e| e.field() == "bi_p"));
+        assert!(errs.iter().any(|e| e.field() == "secret_key_bytes"));
+    }
+
+    #[test]
+    fn share_shapes() {
+        use crypto::rand::SeedableRng;
+        use crypto::UniformRand;
+        use std::collections::VecDeque;
+        let mut node = Node::new();
+        node.num_nodes = 2;
+        node.rand_beacon_queue.insert(0, VecDeque::new());
+        // A dealing without a share for every replica
+        let rng = &mut crypto::rand::rngs::StdRng::seed_from_u64(7);
+        let params = crypto::EVSS381::setup(1, rng).unwrap();
+        let poly = crypto::EVSS381::commit(&params, crypto::F381::rand(rng), rng).unwrap();
+        node.rand_beacon_shares.push((Vec::new(), poly.get_commit()));
+        let errs = node.validate().unwrap_err().0;
+        let shapes = |field: &str| {
+            errs.iter()
+                .filter(|e| matches!(e, ParseError::InvalidShape(f, _, _) if *f == field))
+                .count()
+        };
+        assert_eq!(shapes("rand_beacon_queue"), 1);
+        assert_eq!(shapes("rand_beacon_shares"), 1);
+        assert!(errs
+            .iter()
+            .any(|e| matches!(e, ParseError::MissingEntry("rand_beacon_queue", 1))));
     }
 }
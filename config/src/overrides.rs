@@ -0,0 +1,253 @@
+// Layering over the config file: environment variables first, then
+// `--set field=value` flags. Values are YAML, so that maps such as `pk_map`
+// can be given as `{0: [..], 1: [..]}`, while scalars are written as is.
+
+use super::{ConfigError, Node, OverrideError, PeerAddr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::Path;
+use types::Replica;
+
+/// Environment variables are named after the field, e.g. `RANDPIPER_DELTA`
+pub const ENV_PREFIX: &str = "RANDPIPER_";
+
+/// The fields of `Node` that can be overridden. The secrets and the beacon
+/// setup can only come from a config file or a keystore.
+pub const PUBLIC_FIELDS: &[&str] = &[
+    "net_map",
+    "delta",
+    "id",
+    "num_nodes",
+    "num_faults",
+    "block_size",
+    "client_port",
+    "payload",
+    "crypto_alg",
+    "hash_alg",
+    "pk_map",
+    "bi_pp_map",
+    "bind_addr",
+];
+
+fn parse<T: for<'de> Deserialize<'de>>(source: &str, value: &str) -> Result<T, OverrideError> {
+    serde_yaml::from_str(value).map_err(|e| OverrideError::InvalidValue(source.to_string(), e))
+}
+
+/// `net_map` may also be a comma separated list of addresses, in the order
+/// of the replicas
+fn parse_net_map(source: &str, value: &str) -> Result<HashMap<Replica, PeerAddr>, OverrideError> {
+    if value.trim_start().starts_with('{') {
+        return parse(source, value);
+    }
+    value
+        .split(',')
+        .map(str::trim)
+        .enumerate()
+        .map(|(i, addr)| match addr.parse() {
+            Ok(addr) => Ok((i as Replica, addr)),
+            Err(e) => Err(OverrideError::InvalidAddress(source.to_string(), e)),
+        })
+        .collect()
+}
+
+/// The public part of a node config, as printed by `--print-config`
+#[derive(Serialize)]
+struct PublicNode<'a> {
+    net_map: &'a HashMap<Replica, PeerAddr>,
+    delta: u64,
+    id: Replica,
+    num_nodes: usize,
+    num_faults: usize,
+    block_size: usize,
+    client_port: u16,
+    payload: usize,
+    crypto_alg: &'a crypto::Algorithm,
+    hash_alg: crypto::hash::HashAlgorithm,
+    pk_map: &'a HashMap<Replica, Vec<u8>>,
+    bi_pp_map: &'a HashMap<Replica, crypto::EVSSPublicParams381>,
+    bind_addr: Option<SocketAddr>,
+}
+
+impl Node {
+    /// Sets a public field from its YAML form. `source` names where the value
+    /// came from, for the errors.
+    pub fn set_field(
+        &mut self,
+        source: &str,
+        field: &str,
+        value: &str,
+    ) -> Result<(), OverrideError> {
+        match field {
+            "net_map" => self.net_map = parse_net_map(source, value)?,
+            "delta" => self.delta = parse(source, value)?,
+            "id" => self.id = parse(source, value)?,
+            "num_nodes" => self.num_nodes = parse(source, value)?,
+            "num_faults" => self.num_faults = parse(source, value)?,
+            "block_size" => self.block_size = parse(source, value)?,
+            "client_port" => self.client_port = parse(source, value)?,
+            "payload" => self.payload = parse(source, value)?,
+            "crypto_alg" => self.crypto_alg = parse(source, value)?,
+            "hash_alg" => self.hash_alg = parse(source, value)?,
+            "pk_map" => self.pk_map = parse(source, value)?,
+            "bi_pp_map" => self.bi_pp_map = parse(source, value)?,
+            "bind_addr" => self.bind_addr = parse(source, value)?,
+            _ => return Err(OverrideError::UnknownField(source.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Applies the `RANDPIPER_*` variables among `vars`
+    pub fn apply_env<I>(&mut self, vars: I) -> Result<(), OverrideError>
+    where
+        I: IntoIterator<Item = (OsString, OsString)>,
+    {
+        for (name, value) in vars {
+            let (name, value) = match (name.into_string(), value.into_string()) {
+                (Ok(name), Ok(value)) => (name, value),
+                (Ok(name), Err(_)) if name.starts_with(ENV_PREFIX) => {
+                    log::warn!("Ignoring {}, whose value is not UTF-8.", name);
+                    continue;
+                }
+                // No config field is named outside of UTF-8
+                _ => continue,
+            };
+            let field = match name.strip_prefix(ENV_PREFIX) {
+                Some(field) => field.to_lowercase(),
+                None => continue,
+            };
+            if !PUBLIC_FIELDS.contains(&field.as_str()) {
+                // The environment is shared with everything else
+                log::warn!("Ignoring {}, which is not a config field.", name);
+                continue;
+            }
+            self.set_field(&name, &field, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Applies `field=value` overrides
+    pub fn apply_sets<'a, I>(&mut self, sets: I) -> Result<(), OverrideError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        for set in sets {
+            let source = format!("--set {}", set);
+            let (field, value) = set
+                .split_once('=')
+                .ok_or_else(|| OverrideError::Malformed(source.clone()))?;
+            self.set_field(&source, field.trim(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a config file with the environment and `field=value` overrides
+    /// on top, and validates the result like `load`
+    pub fn load_layered<'a, P, E, S>(path: P, env: E, sets: S) -> Result<Node, ConfigError>
+    where
+        P: AsRef<Path>,
+        E: IntoIterator<Item = (OsString, OsString)>,
+        S: IntoIterator<Item = &'a str>,
+    {
+        let path = path.as_ref();
+        let mut c: Node = super::format::read(path, None)?;
+        c.resolve_public(path)?;
+        c.apply_env(env).map_err(ConfigError::Override)?;
+        c.apply_sets(sets).map_err(ConfigError::Override)?;
+        c.validate_public()
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        Ok(c)
+    }
+
+    /// The config without the secrets, as YAML
+    pub fn public_yaml(&self) -> String {
+        let public = PublicNode {
+            net_map: &self.net_map,
+            delta: self.delta,
+            id: self.id,
+            num_nodes: self.num_nodes,
+            num_faults: self.num_faults,
+            block_size: self.block_size,
+            client_port: self.client_port,
+            payload: self.payload,
+            crypto_alg: &self.crypto_alg,
+            hash_alg: self.hash_alg,
+            pk_map: &self.pk_map,
+            bi_pp_map: &self.bi_pp_map,
+            bind_addr: self.bind_addr,
+        };
+        serde_yaml::to_string(&public).expect("failed to serialise the config")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::secret::Secret;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        vars.iter().map(|(k, v)| (k.into(), v.into())).collect()
+    }
+
+    #[test]
+    fn layers() {
+        let mut node = Node::new();
+        node.delta = 50;
+        node.apply_env(env(&[
+            ("RANDPIPER_DELTA", "100"),
+            ("RANDPIPER_CLIENT_PORT", "9000"),
+            ("RANDPIPER_NET_MAP", "10.0.0.1:4000, node-1.randpiper:4000"),
+            ("RANDPIPER_CRYPTO_ALG", "SECP256K1"),
+            ("HOME", "/root"),
+        ]))
+        .unwrap();
+        assert_eq!(node.delta, 100);
+        assert_eq!(node.client_port, 9000);
+        assert_eq!(node.net_map[&1].to_string(), "node-1.randpiper:4000");
+        assert_eq!(node.crypto_alg, crypto::Algorithm::SECP256K1);
+
+        // Variables that are not UTF-8 are skipped
+        use std::os::unix::ffi::OsStringExt;
+        let mut vars = env(&[("RANDPIPER_PAYLOAD", "8")]);
+        vars.push(("RANDPIPER_DELTA".into(), OsString::from_vec(vec![0xff])));
+        vars.push((OsString::from_vec(vec![0xff]), "1".into()));
+        node.apply_env(vars).unwrap();
+        assert_eq!(node.delta, 100);
+        assert_eq!(node.payload, 8);
+
+        // Flags come after the environment
+        node.apply_sets(vec![
+            "delta=200",
+            "pk_map={0: [1, 2]}",
+            "bind_addr=10.0.0.5:4000",
+        ])
+        .unwrap();
+        assert_eq!(node.delta, 200);
+        assert_eq!(node.pk_map[&0], vec![1, 2]);
+        assert_eq!(node.bind_addr, Some("10.0.0.5:4000".parse().unwrap()));
+
+        assert!(matches!(
+            node.apply_sets(vec!["secret_key_bytes=[1]"]),
+            Err(OverrideError::UnknownField(_))
+        ));
+        assert!(matches!(
+            node.apply_env(env(&[("RANDPIPER_DELTA", "soon")])),
+            Err(OverrideError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            node.apply_sets(vec!["net_map=10.0.0.1"]),
+            Err(OverrideError::InvalidAddress(_, _))
+        ));
+    }
+
+    #[test]
+    fn public_yaml_has_no_secrets() {
+        let mut node = Node::new();
+        node.secret_key_bytes = Secret::new(vec![0xab; 4]);
+        let yaml = node.public_yaml();
+        assert!(yaml.contains("delta"));
+        assert!(!yaml.contains("secret_key_bytes") && !yaml.contains("171"));
+        assert!(!yaml.contains("rand_beacon_parameter"));
+    }
+}
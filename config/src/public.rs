@@ -0,0 +1,131 @@
+// The public material that is the same for every node: the public keys and
+// the beacon parameters. For a large network it dwarfs the rest of a node
+// config, so genconfig writes it once and every node config refers to it.
+
+use super::format;
+use super::{ConfigError, Node};
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use types::Replica;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Public {
+    #[serde(serialize_with = "super::ordered")]
+    pub pk_map: HashMap<Replica, Vec<u8>>,
+    #[serde(serialize_with = "super::ordered")]
+    pub bi_pp_map: HashMap<Replica, crypto::EVSSPublicParams381>,
+    pub rand_beacon_parameter: Option<crypto::EVSSParams381>,
+}
+
+impl Public {
+    /// Copies the shared material out of a node config
+    pub fn from_node(node: &Node) -> Public {
+        Public {
+            pk_map: node.pk_map.clone(),
+            bi_pp_map: node.bi_pp_map.clone(),
+            rand_beacon_parameter: node.rand_beacon_parameter.clone(),
+        }
+    }
+
+    /// The digest of what every node of a membership must agree on: the
+    /// number of nodes and faults, the algorithms and the shared material.
+    /// It is hashed with SHA-256, so that nodes that disagree on `hash_alg`
+    /// still tell it apart.
+    pub fn digest(
+        &self,
+        num_nodes: usize,
+        num_faults: usize,
+        crypto_alg: &crypto::Algorithm,
+        hash_alg: HashAlgorithm,
+    ) -> Hash {
+        ser_and_hash_in(
+            HashAlgorithm::SHA256,
+            Domain::Config,
+            &(num_nodes, num_faults, crypto_alg, hash_alg, self),
+        )
+    }
+}
+
+impl Node {
+    /// Drops the shared material from the config, which reads it from `path`
+    /// instead. A relative path is taken from the directory of the config.
+    pub fn refer_to_public<P: Into<PathBuf>>(&mut self, path: P) {
+        self.pk_map.clear();
+        self.bi_pp_map.clear();
+        self.rand_beacon_parameter = None;
+        self.public = Some(path.into());
+    }
+
+    /// Reads the shared material the config refers to, if any. `path` is the
+    /// path of the config itself.
+    pub(crate) fn resolve_public(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let public = match &self.public {
+            Some(public) => path.parent().unwrap_or_else(|| Path::new("")).join(public),
+            None => return Ok(()),
+        };
+        let public: Public = format::read(&public, None)?;
+        self.pk_map = public.pk_map;
+        self.bi_pp_map = public.bi_pp_map;
+        self.rand_beacon_parameter = public.rand_beacon_parameter;
+        Ok(())
+    }
+
+    /// The digest of the settings every node of the network must agree on
+    pub fn digest(&self) -> Hash {
+        Public::from_node(self).digest(
+            self.num_nodes,
+            self.num_faults,
+            &self.crypto_alg,
+            self.hash_alg,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let dir = std::env::temp_dir().join(format!("randpiper-public-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut node = Node::new();
+        node.pk_map.insert(0, vec![1, 2, 3]);
+        std::fs::write(
+            dir.join("public.json"),
+            serde_json::to_vec(&Public::from_node(&node)).unwrap(),
+        )
+        .unwrap();
+
+        node.refer_to_public("public.json");
+        assert!(node.pk_map.is_empty());
+        node.resolve_public(&dir.join("nodes-0.json")).unwrap();
+        assert_eq!(node.pk_map[&0], vec![1, 2, 3]);
+
+        node.refer_to_public("missing.json");
+        assert!(matches!(
+            node.resolve_public(&dir.join("nodes-0.json")),
+            Err(ConfigError::Io(_, _))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn digest() {
+        let mut a = Node::new();
+        a.pk_map.insert(0, vec![1, 2, 3]);
+        let mut b = Node::new();
+        b.pk_map.insert(0, vec![1, 2, 3]);
+        // Only the shared settings count
+        b.id = 3;
+        b.delta = 1000;
+        assert_eq!(a.digest(), b.digest());
+        b.num_faults += 1;
+        assert_ne!(a.digest(), b.digest());
+        b.num_faults = a.num_faults;
+        b.pk_map.insert(1, vec![4]);
+        assert_ne!(a.digest(), b.digest());
+    }
+}
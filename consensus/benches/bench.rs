@@ -7,10 +7,12 @@ use crypto::rand::{rngs::StdRng, SeedableRng};
 use crypto::*;
 use serde::Serialize;
 use std::collections::HashMap;
-use types::{Block, Certificate, Content, DataWithAcc, Propose, Replica, SignedData, Vote};
-use util::io::to_bytes;
+use types::{
+    Accumulator, Block, Certificate, Content, DataWithAcc, Propose, Replica, SignedData, Vote,
+};
 
 const SEED: u64 = 42;
+const ALG: hash::HashAlgorithm = hash::HashAlgorithm::SHA256;
 static TEST_POINTS: [usize; 7] = [3, 10, 20, 30, 50, 75, 100];
 const BENCH_COUNT: usize = 10;
 
@@ -19,42 +21,47 @@ fn tree_get_dummy_acc<T: Serialize>(
     cx_num_faults: Replica,
     data: &T,
 ) -> (Vec<Vec<u8>>, DataWithAcc) {
-    let shards = accumulator::to_shards(
-        &to_bytes(data),
-        cx_num_nodes as usize,
-        cx_num_faults as usize,
-    );
-    let size = accumulator::get_size(cx_num_nodes) as usize;
-    let mut tree = vec![Vec::new(); (1 << size) + 1];
-    for i in 0..cx_num_nodes as usize {
-        tree[1 << size - 1 | i] = hash::ser_and_hash(&shards[i]).to_vec();
-    }
-    for i in 0..(1 << size - 1) - 1 {
-        let index = (1 << size - 1) - 1 - i;
-        tree[index] =
-            hash::ser_and_hash(&(tree[index << 1].clone(), tree[index << 1 | 1].clone())).to_vec();
-    }
+    let (shards, tree) = accumulator::build_tree(ALG, data, cx_num_nodes, cx_num_faults);
     (
         shards,
         DataWithAcc {
             sign: Vec::new(),
-            tree: tree,
-            size: size as Replica,
+            acc: Accumulator::Merkle(tree),
         },
     )
 }
 
-fn tree_check_dummy_share(sign: SignedData) {
-    let mut change = sign.index;
-    change >>= 1;
-    for i in 0..sign.chain.len() - 1 {
-        if change & 1 == 0 {
-            hash::ser_and_hash(&(sign.chain[i].1.clone(), sign.chain[i + 1].0.clone())).to_vec();
-        } else {
-            hash::ser_and_hash(&(sign.chain[i + 1].0.clone(), sign.chain[i].1.clone())).to_vec();
-        }
-        change >>= 1;
-    }
+fn bi_get_dummy_acc<T: Serialize>(
+    params: &EVSSParams381,
+    cx_num_nodes: Replica,
+    cx_num_faults: Replica,
+    data: &T,
+) -> (Vec<Vec<u8>>, DataWithAcc) {
+    let (shards, acc) = accumulator::build_bi(ALG, params, data, cx_num_nodes, cx_num_faults);
+    (
+        shards,
+        DataWithAcc {
+            sign: Vec::new(),
+            acc,
+        },
+    )
+}
+
+fn check_dummy_share(
+    shard: &[u8],
+    n: Replica,
+    num_nodes: Replica,
+    pp: &EVSSPublicParams381,
+    sign: SignedData,
+) {
+    assert!(accumulator::check_witness(
+        ALG,
+        shard,
+        n,
+        num_nodes,
+        pp,
+        &sign.witness
+    ));
 }
 
 fn generate_propose() -> HashMap<usize, Propose> {
@@ -75,10 +82,12 @@ fn generate_propose() -> HashMap<usize, Propose> {
         let content = Content {
             acks: certificate.votes.clone(),
             commits: vec![poly.get_commit(); *test],
+            reconfig: None,
+            rotations: Vec::new(),
         };
         let mut block = Block::new();
         block.body.data = content;
-        block.update_hash();
+        block.update_hash(ALG);
         let propose = Propose {
             new_block: block,
             certificate: certificate,
@@ -106,9 +115,14 @@ pub fn tree_propose_to_shards(c: &mut Criterion) {
 pub fn tree_shards_to_propose(c: &mut Criterion) {
     let propose_map = generate_propose();
     let mut group = c.benchmark_group("tree_shards_to_propose");
+    let rng = &mut StdRng::seed_from_u64(SEED);
     BenchmarkGroup::sampling_mode(&mut group, criterion::SamplingMode::Flat);
     for n in &TEST_POINTS {
         let data = propose_map.get(n).unwrap();
+        // Unused by the Merkle tree
+        let pp = Biaccumulator381::setup(*n, rng)
+            .unwrap()
+            .get_public_params();
         let acc = tree_get_dummy_acc(*n as u16, ((n - 1) / 2) as u16, &data);
         let shards = acc.0.clone();
         let mut received: Vec<_> = shards.iter().cloned().map(Some).collect();
@@ -121,9 +135,15 @@ pub fn tree_shards_to_propose(c: &mut Criterion) {
                 || received.clone(),
                 |d| {
                     for i in 0..n {
-                        tree_check_dummy_share(accumulator::get_sign(&acc.1, i as Replica));
+                        check_dummy_share(
+                            &shards[i],
+                            i as Replica,
+                            n as Replica,
+                            &pp,
+                            accumulator::get_sign(&acc.1, i as Replica),
+                        );
                     }
-                    accumulator::from_shards(d, n, (n - 1) / 2);
+                    accumulator::from_shards(d, n, (n - 1) / 2).unwrap();
                 },
                 criterion::BatchSize::SmallInput,
             );
@@ -135,35 +155,14 @@ pub fn tree_shards_to_propose(c: &mut Criterion) {
 pub fn bi_propose_to_shards(c: &mut Criterion) {
     let propose_map = generate_propose();
     let mut group = c.benchmark_group("bi_propose_to_shards");
-    let rng = &mut rand::rngs::StdRng::from_entropy();
+    let rng = &mut StdRng::seed_from_u64(SEED);
     BenchmarkGroup::sampling_mode(&mut group, criterion::SamplingMode::Flat);
     for n in &TEST_POINTS {
         let data = propose_map.get(&n).unwrap();
-        let params = crypto::Biaccumulator381::setup(*n, rng).unwrap();
+        let params = Biaccumulator381::setup(*n, rng).unwrap();
         group.throughput(Throughput::Bytes(*n as u64));
         group.bench_with_input(BenchmarkId::from_parameter(*n), n, |b, &n| {
-            b.iter(|| {
-                let shards = accumulator::to_shards(&to_bytes(&data), n, (n - 1) / 2);
-                let mut values = Vec::with_capacity(n as usize);
-                for shard in shards.iter() {
-                    values.push(F381::from_be_bytes_mod_order(&hash::ser_and_hash(&shard)));
-                }
-                let poly =
-                    Biaccumulator381::commit(&params, &values[..], &mut StdRng::from_entropy())
-                        .unwrap();
-                let mut acc = Vec::with_capacity(n as usize);
-                for value in values.iter() {
-                    acc.push(
-                        Biaccumulator381::create_witness(
-                            *value,
-                            &params,
-                            &poly,
-                            &mut StdRng::from_entropy(),
-                        )
-                        .unwrap(),
-                    );
-                }
-            });
+            b.iter(|| bi_get_dummy_acc(&params, n as u16, ((n - 1) / 2) as u16, &data))
         });
     }
     group.finish();
@@ -172,50 +171,33 @@ pub fn bi_propose_to_shards(c: &mut Criterion) {
 pub fn bi_shards_to_propose(c: &mut Criterion) {
     let propose_map = generate_propose();
     let mut group = c.benchmark_group("bi_shards_to_propose");
-    let rng = &mut rand::rngs::StdRng::from_entropy();
+    let rng = &mut StdRng::seed_from_u64(SEED);
     BenchmarkGroup::sampling_mode(&mut group, criterion::SamplingMode::Flat);
     for n in &TEST_POINTS {
         let data = propose_map.get(&n).unwrap();
-        let params = crypto::Biaccumulator381::setup(*n, rng).unwrap();
-        let acc = tree_get_dummy_acc(*n as u16, ((n - 1) / 2) as u16, &data);
+        let params = Biaccumulator381::setup(*n, rng).unwrap();
+        let pp = params.get_public_params();
+        let acc = bi_get_dummy_acc(&params, *n as u16, ((n - 1) / 2) as u16, &data);
         let shards = acc.0.clone();
         let mut received: Vec<_> = shards.iter().cloned().map(Some).collect();
         for i in 0..(n - 1) / 2 {
             received[i] = None;
         }
-        let mut values = Vec::with_capacity(*n as usize);
-        for shard in shards.iter() {
-            values.push(F381::from_be_bytes_mod_order(&hash::ser_and_hash(&shard)));
-        }
-        let poly =
-            Biaccumulator381::commit(&params, &values[..], &mut StdRng::from_entropy()).unwrap();
-        let mut acc = Vec::with_capacity(*n as usize);
-        for value in values.iter() {
-            acc.push(
-                Biaccumulator381::create_witness(
-                    *value,
-                    &params,
-                    &poly,
-                    &mut StdRng::from_entropy(),
-                )
-                .unwrap(),
-            );
-        }
         group.throughput(Throughput::Bytes(*n as u64));
         group.bench_with_input(BenchmarkId::from_parameter(*n), n, |b, &n| {
             b.iter_batched(
                 || received.clone(),
                 |d| {
-                    for wit in acc.iter() {
-                        crypto::EVSS381::check(
-                            &params.get_public_params(),
-                            &poly.get_commit(),
-                            &wit,
-                            rng,
-                        )
-                        .unwrap();
+                    for i in 0..n {
+                        check_dummy_share(
+                            &shards[i],
+                            i as Replica,
+                            n as Replica,
+                            &pp,
+                            accumulator::get_sign(&acc.1, i as Replica),
+                        );
                     }
-                    accumulator::from_shards(d, n, (n - 1) / 2);
+                    accumulator::from_shards(d, n, (n - 1) / 2).unwrap();
                 },
                 criterion::BatchSize::SmallInput,
             );
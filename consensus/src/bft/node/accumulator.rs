@@ -1,45 +1,58 @@
 use super::context::Context;
+use super::signer::{SignKind, SignerError};
+use crate::erasure::{self, ErasureError};
+use crypto::hash::{Domain, HashAlgorithm};
+use crypto::merkle::MerkleTree;
+use crypto::rand::{rngs::StdRng, SeedableRng};
 use crypto::*;
-use crypto_lib::PublicKey;
-use reed_solomon_erasure::galois_8::ReedSolomon;
+use crypto::scheme::VerifyingKey;
+use num_traits::Zero;
 use serde::{Deserialize, Serialize};
-use types::{DataWithAcc, Replica, SignedData};
+use std::str::FromStr;
+use types::{
+    sign_digest, Accumulator, BadEncoding, DataWithAcc, Height, Replica, SignedData, Witness,
+};
 use util::io::to_bytes;
 
-pub fn to_shards(data: &[u8], num_nodes: usize, num_faults: usize) -> Vec<Vec<u8>> {
-    let num_data_shards = num_nodes - num_faults;
-    let shard_size = (data.len() + num_data_shards - 1) / num_data_shards;
-    let mut data_with_suffix = data.to_vec();
-    let suffix_size = shard_size * num_data_shards - data.len();
-    for _ in 0..suffix_size {
-        data_with_suffix.push(suffix_size as u8)
-    }
-    let mut result = Vec::with_capacity(num_nodes);
-    for shard in 0..num_data_shards {
-        result.push(data_with_suffix[shard * shard_size..(shard + 1) * shard_size].to_vec());
-    }
-    for _shard in 0..num_faults {
-        result.push(vec![0; shard_size]);
-    }
-    let r = ReedSolomon::new(num_data_shards, num_faults).unwrap();
-    r.encode(&mut result).unwrap();
-    result
-}
-
-pub fn from_shards(mut data: Vec<Option<Vec<u8>>>, num_nodes: usize, num_faults: usize) -> Vec<u8> {
-    let num_data_shards = num_nodes - num_faults;
-    let r = ReedSolomon::new(num_data_shards, num_faults).unwrap();
-    r.reconstruct(&mut data).unwrap();
-    let mut result = Vec::with_capacity(num_data_shards * data[0].as_ref().unwrap().len());
-    for shard in 0..num_data_shards {
-        result.append(&mut data[shard].clone().unwrap());
+/// How the shards of delivered data are committed to. All nodes must use the
+/// same type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccumulatorType {
+    /// Merkle trees, with witnesses logarithmic in the number of nodes
+    Merkle,
+    /// Biaccumulators, with constant size witnesses
+    Bi,
+}
+
+impl FromStr for AccumulatorType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merkle" => Ok(AccumulatorType::Merkle),
+            "bi" => Ok(AccumulatorType::Bi),
+            _ => Err("no match"),
+        }
     }
-    result.truncate(result.len() - *result.last().unwrap() as usize);
-    result
+}
+
+/// Panics if the parameters were not validated with the config
+pub fn to_shards(data: &[u8], num_nodes: usize, num_faults: usize) -> Vec<Vec<u8>> {
+    erasure::encode(data, num_nodes, num_faults).expect("invalid erasure coding parameters")
+}
+
+pub fn from_shards(
+    data: Vec<Option<Vec<u8>>>,
+    num_nodes: usize,
+    num_faults: usize,
+) -> Result<Vec<u8>, ErasureError> {
+    erasure::decode(data, num_nodes, num_faults)
 }
 
 #[cfg(test)]
 mod tests {
+    const ALG: crypto::hash::HashAlgorithm = crypto::hash::HashAlgorithm::SHA256;
+
     #[test]
     fn shards() {
         const SIZE: usize = 1024 * 1024;
@@ -50,153 +63,410 @@ mod tests {
         let shards = super::to_shards(&array, 4, 1);
         let mut received: Vec<_> = shards.iter().cloned().map(Some).collect();
         received[0] = None;
-        let reconstructed = super::from_shards(received, 4, 1);
+        let reconstructed = super::from_shards(received, 4, 1).unwrap();
         assert_eq!(array.to_vec(), reconstructed);
     }
-}
 
-pub fn get_size(num_nodes: Replica) -> Replica {
-    let mut n: Replica = 1;
-    while 1 << n < num_nodes {
-        n += 1;
+    #[test]
+    fn bad_encoding() {
+        use super::*;
+        let scheme = scheme::lookup(&Algorithm::ED25519).unwrap();
+        let (pk, sk) = scheme.generate().unwrap();
+        let pk = scheme.decode_pk(&pk).unwrap();
+        let kp = scheme.decode_sk(&sk).unwrap();
+        let pp = Biaccumulator381::setup(4, &mut StdRng::from_entropy())
+            .unwrap()
+            .get_public_params();
+        // The dealer signs a tree over shards that are not a codeword
+        let mut shards = to_shards(&[1, 2, 3, 4, 5], 4, 1);
+        shards[3][0] ^= 1;
+        let tree = MerkleTree::build(ALG, &shards);
+        let acc = DataWithAcc {
+            sign: kp
+                .sign(&sign_digest(SignKind::Commit, 0, &tree.root()))
+                .unwrap(),
+            acc: Accumulator::Merkle(tree),
+        };
+        let mut gatherer = ShareGatherer::new(4, SignKind::Commit, ALG);
+        for n in 1..4 {
+            gatherer.add_share(
+                shards[n].clone(),
+                n as Replica,
+                0,
+                &pp,
+                &*pk,
+                get_sign(&acc, n as Replica),
+            );
+        }
+        let mut proof = match gatherer.reconstruct(4, 1) {
+            Reconstructed::BadEncoding(shards) => BadEncoding {
+                dealer: 0,
+                kind: SignKind::Commit,
+                shards,
+            },
+            _ => panic!("the encoding should be found bad"),
+        };
+        assert!(verify_bad_encoding(ALG, &proof, 0, 4, 1, &pp, &*pk));
+        // The shards were not signed as a proposal
+        proof.kind = SignKind::Propose;
+        assert!(!verify_bad_encoding(ALG, &proof, 0, 4, 1, &pp, &*pk));
     }
-    n + 1
-}
-
-pub fn get_acc<T: Serialize>(cx: &Context, data: &T) -> (Vec<Vec<u8>>, DataWithAcc) {
-    let shards = to_shards(
-        &to_bytes(data),
-        cx.num_nodes as usize,
-        cx.num_faults as usize,
-    );
-    let size = get_size(cx.num_nodes) as usize;
-    let mut tree = vec![Vec::new(); (1 << size) + 1];
-    for i in 0..cx.num_nodes as usize {
-        tree[1 << size - 1 | i] = hash::ser_and_hash(&shards[i]).to_vec();
+
+    #[test]
+    fn bad_bi_encoding() {
+        use super::*;
+        let scheme = scheme::lookup(&Algorithm::ED25519).unwrap();
+        let (pk, sk) = scheme.generate().unwrap();
+        let pk = scheme.decode_pk(&pk).unwrap();
+        let kp = scheme.decode_sk(&sk).unwrap();
+        let params = Biaccumulator381::setup(4, &mut StdRng::from_entropy()).unwrap();
+        let pp = params.get_public_params();
+        // Only the shard of node 0, which we never see, is off
+        let mut shards = to_shards(&[1, 2, 3, 4, 5], 4, 1);
+        shards[0][0] ^= 1;
+        let acc = bi_accumulator(ALG, &params, &shards);
+        let acc = DataWithAcc {
+            sign: kp
+                .sign(&sign_digest(SignKind::Commit, 0, &acc.digest(ALG)))
+                .unwrap(),
+            acc,
+        };
+        let mut gatherer = ShareGatherer::new(4, SignKind::Commit, ALG);
+        for n in 1..4 {
+            gatherer.add_share(
+                shards[n].clone(),
+                n as Replica,
+                0,
+                &pp,
+                &*pk,
+                get_sign(&acc, n as Replica),
+            );
+        }
+        let proof = match gatherer.reconstruct(4, 1) {
+            Reconstructed::BadEncoding(shards) => BadEncoding {
+                dealer: 0,
+                kind: SignKind::Commit,
+                shards,
+            },
+            _ => panic!("the encoding should be found bad"),
+        };
+        assert!(verify_bad_encoding(ALG, &proof, 0, 4, 1, &pp, &*pk));
     }
-    for i in 0..(1 << size - 1) - 1 {
-        let index = (1 << size - 1) - 1 - i;
-        tree[index] =
-            hash::ser_and_hash(&(tree[index << 1].clone(), tree[index << 1 | 1].clone())).to_vec();
+}
+
+/// Splits `data` into shards and builds the Merkle tree over them
+pub fn build_tree<T: Serialize>(
+    alg: HashAlgorithm,
+    data: &T,
+    num_nodes: Replica,
+    num_faults: Replica,
+) -> (Vec<Vec<u8>>, MerkleTree) {
+    let shards = to_shards(&to_bytes(data), num_nodes as usize, num_faults as usize);
+    let tree = MerkleTree::build(alg, &shards);
+    (shards, tree)
+}
+
+/// The biaccumulator element for the `n`-th shard
+pub fn bi_value(alg: HashAlgorithm, n: Replica, shard: &[u8]) -> F381 {
+    F381::from_be_bytes_mod_order(&hash::ser_and_hash_in(
+        alg,
+        Domain::Accumulator,
+        &(n, shard),
+    ))
+}
+
+/// Hashes the biaccumulator elements of all the shards
+pub fn bi_shards(alg: HashAlgorithm, shards: &[Vec<u8>]) -> hash::Hash {
+    let hashes: Vec<_> = shards
+        .iter()
+        .enumerate()
+        .map(|(n, shard)| hash::ser_and_hash_in(alg, Domain::Accumulator, &(n as Replica, shard)))
+        .collect();
+    hash::ser_and_hash_in(alg, Domain::Accumulator, &hashes)
+}
+
+/// Splits `data` into shards and accumulates them with the biaccumulator
+/// `params`
+pub fn build_bi<T: Serialize>(
+    alg: HashAlgorithm,
+    params: &EVSSParams381,
+    data: &T,
+    num_nodes: Replica,
+    num_faults: Replica,
+) -> (Vec<Vec<u8>>, Accumulator) {
+    let shards = to_shards(&to_bytes(data), num_nodes as usize, num_faults as usize);
+    let acc = bi_accumulator(alg, params, &shards);
+    (shards, acc)
+}
+
+/// Accumulates `shards` with the biaccumulator `params`
+pub fn bi_accumulator(
+    alg: HashAlgorithm,
+    params: &EVSSParams381,
+    shards: &[Vec<u8>],
+) -> Accumulator {
+    let rng = &mut StdRng::from_entropy();
+    let values: Vec<F381> = shards
+        .iter()
+        .enumerate()
+        .map(|(i, shard)| bi_value(alg, i as Replica, shard))
+        .collect();
+    let poly = Biaccumulator381::commit(params, &values[..], rng).unwrap();
+    let witnesses = values
+        .iter()
+        .map(|v| Biaccumulator381::create_witness(*v, params, &poly, rng).unwrap())
+        .collect();
+    Accumulator::Bi {
+        commit: poly.get_commit(),
+        shards: bi_shards(alg, shards),
+        witnesses,
     }
-    (
-        shards,
-        DataWithAcc {
-            sign: cx
-                .my_secret_key
-                .sign(&hash::ser_and_hash(&tree[1]))
-                .unwrap(),
-            tree: tree,
-            size: size as Replica,
-        },
-    )
+}
+
+/// Shards `data` and signs the accumulator as a message of `kind`
+pub fn get_acc<T: Serialize>(
+    cx: &Context,
+    kind: SignKind,
+    data: &T,
+) -> Result<(Vec<Vec<u8>>, DataWithAcc), SignerError> {
+    let (shards, acc) = match cx.accumulator_type {
+        AccumulatorType::Merkle => {
+            let (shards, tree) = build_tree(cx.hash_alg, data, cx.num_nodes, cx.num_faults);
+            (shards, Accumulator::Merkle(tree))
+        }
+        AccumulatorType::Bi => build_bi(
+            cx.hash_alg,
+            &cx.accumulator_params,
+            data,
+            cx.num_nodes,
+            cx.num_faults,
+        ),
+    };
+    let sign = cx.signer.sign(kind, cx.epoch, &acc.digest(cx.hash_alg))?;
+    Ok((shards, DataWithAcc { sign, acc }))
 }
 
 pub fn get_sign(acc: &DataWithAcc, n: Replica) -> SignedData {
-    let mut vec = Vec::with_capacity(acc.size as usize - 1);
-    let mut p = (1 << acc.size - 1 | n) as usize;
-    for _ in 0..acc.size - 1 {
-        vec.push((acc.tree[p ^ 1].clone(), acc.tree[p >> 1].clone()));
-        p >>= 1;
-    }
+    let witness = match &acc.acc {
+        Accumulator::Merkle(tree) => Witness::Merkle {
+            root: tree.root(),
+            proof: tree.prove(n as usize),
+        },
+        Accumulator::Bi {
+            commit,
+            shards,
+            witnesses,
+        } => Witness::Bi {
+            commit: commit.clone(),
+            shards: *shards,
+            witness: witnesses[n as usize].clone(),
+        },
+    };
     SignedData {
         sign: acc.sign.clone(),
-        start: acc.tree[(1 << acc.size - 1 | n) as usize].clone(),
-        index: n,
-        chain: vec,
+        witness,
+    }
+}
+
+/// Checks that `sh` is the `n`-th of `num_nodes` shards committed to by the
+/// witness. `pp` are the biaccumulator parameters of the sender.
+pub fn check_witness(
+    alg: HashAlgorithm,
+    sh: &[u8],
+    n: Replica,
+    num_nodes: Replica,
+    pp: &EVSSPublicParams381,
+    witness: &Witness,
+) -> bool {
+    match witness {
+        Witness::Merkle { root, proof } => {
+            proof.num_leaves == num_nodes as usize && proof.verify(alg, root, n as usize, sh)
+        }
+        // Membership is an opening of the accumulated polynomial to zero
+        Witness::Bi {
+            commit, witness, ..
+        } => {
+            witness.point == bi_value(alg, n, sh)
+                && witness.value.is_zero()
+                && Biaccumulator381::check(pp, commit, witness, &mut StdRng::from_entropy())
+                    .unwrap_or(false)
+        }
+    }
+}
+
+/// Decodes the shards and checks them against the accumulator they were
+/// signed under: encoding the data again has to give back every shard, and
+/// the same Merkle root or hash of the biaccumulator elements.
+pub fn check_encoding(
+    alg: HashAlgorithm,
+    shards: Vec<Option<Vec<u8>>>,
+    num_nodes: Replica,
+    num_faults: Replica,
+    witness: &Witness,
+) -> Result<Vec<u8>, ErasureError> {
+    let (data, encoded) = erasure::decode_checked(shards, num_nodes as usize, num_faults as usize)?;
+    let consistent = match witness {
+        Witness::Merkle { root, .. } => MerkleTree::build(alg, &encoded).root() == *root,
+        Witness::Bi { shards, .. } => bi_shards(alg, &encoded) == *shards,
+    };
+    if !consistent {
+        return Err(ErasureError::Inconsistent);
+    }
+    Ok(data)
+}
+
+/// Checks that `proof` holds shards validly signed by its dealer in `epoch`,
+/// as a proposal or as commitments, that do not decode to what the dealer
+/// accumulated
+pub fn verify_bad_encoding(
+    alg: HashAlgorithm,
+    proof: &BadEncoding,
+    epoch: Height,
+    num_nodes: Replica,
+    num_faults: Replica,
+    pp: &EVSSPublicParams381,
+    pk: &dyn VerifyingKey,
+) -> bool {
+    if proof.kind != SignKind::Propose && proof.kind != SignKind::Commit {
+        return false;
+    }
+    let mut shards = vec![None; num_nodes as usize];
+    let mut digest = None;
+    for (n, sh, sign) in proof.shards.iter() {
+        if *n >= num_nodes
+            || shards[*n as usize].is_some()
+            || !check_witness(alg, sh, *n, num_nodes, pp, &sign.witness)
+        {
+            return false;
+        }
+        let d = sign.witness.digest(alg);
+        if *digest.get_or_insert(d) != d
+            || !pk.verify(&sign_digest(proof.kind, epoch, &d), &sign.sign)
+        {
+            return false;
+        }
+        shards[*n as usize] = Some(sh.clone());
+    }
+    let witness = match proof.shards.first() {
+        Some((_, _, sign)) => &sign.witness,
+        None => return false,
+    };
+    match check_encoding(alg, shards, num_nodes, num_faults, witness) {
+        Ok(_) | Err(ErasureError::TooFewShards { .. }) => false,
+        Err(_) => true,
     }
 }
 
+/// What the shards gathered so far decode to
+pub enum Reconstructed {
+    Data(Vec<u8>),
+    /// Too few shards have arrived
+    Wait,
+    /// The dealer encoded the data inconsistently. Every shard is signed by
+    /// the dealer, so the shards prove it.
+    BadEncoding(Vec<(Replica, Vec<u8>, SignedData)>),
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShareGatherer {
     pub size: Replica,
-    pub reference: Option<(Vec<u8>, Vec<u8>)>,
+    pub reference: Option<hash::Hash>,
     pub shard: Vec<Option<Vec<u8>>>,
+    pub sign: Vec<Option<SignedData>>,
     pub shard_num: Replica,
+    /// What the shards are signed as
+    pub kind: SignKind,
+    pub alg: HashAlgorithm,
 }
 
 impl ShareGatherer {
-    pub fn new(num_nodes: Replica) -> Self {
+    pub fn new(num_nodes: Replica, kind: SignKind, alg: HashAlgorithm) -> Self {
         ShareGatherer {
             size: num_nodes,
             reference: None,
             shard: vec![None; num_nodes as usize],
+            sign: vec![None; num_nodes as usize],
             shard_num: 0,
+            kind,
+            alg,
         }
     }
 
     pub fn clear(&mut self) {
         self.reference = None;
         self.shard = vec![None; self.size as usize];
+        self.sign = vec![None; self.size as usize];
         self.shard_num = 0;
     }
-
+    /// Adds the `n`-th shard, signed by the sender in `epoch`
     pub fn add_share(
         &mut self,
         sh: Vec<u8>,
         n: Replica,
-        _pp: &EVSSPublicParams381,
-        pk: &PublicKey,
+        epoch: Height,
+        pp: &EVSSPublicParams381,
+        pk: &dyn VerifyingKey,
         sign: SignedData,
     ) {
-        if self.shard[n as usize].is_some() {
+        if n >= self.size || self.shard[n as usize].is_some() {
             return;
         }
-        // The hash should match with the sign.
-        if hash::ser_and_hash(&sh).to_vec() != sign.start {
-            println!("[WARN] The hash of the shard does not match.");
+        if !check_witness(self.alg, &sh, n, self.size, pp, &sign.witness) {
+            println!("[WARN] Accumulator value does not match.");
             debug_assert!(false);
             return;
         }
-        if !pk.verify(
-            &hash::ser_and_hash(&sign.chain.last().unwrap().1),
-            &sign.sign,
-        ) {
+        let digest = sign.witness.digest(self.alg);
+        if !pk.verify(&sign_digest(self.kind, epoch, &digest), &sign.sign) {
             println!("[WARN] The signature of the shard does not match.");
             debug_assert!(false);
             return;
         }
-        if self.reference.is_none() {
-            self.reference = Some((
-                hash::ser_and_hash(&sign.chain.last().unwrap().1).to_vec(),
-                sign.sign.clone(),
-            ));
-        } else {
-            if self.reference.as_ref().unwrap().1 != sign.sign {
-                println!("[WARN] Equivocation detected.");
-                debug_assert!(false);
-                // TODO: Broadcast the blame.
-                return;
-            }
-        }
-        // The share should match with the accumulator.
-        let mut change = sign.index;
-        change >>= 1;
-        for i in 0..sign.chain.len() - 1 {
-            let h = if change & 1 == 0 {
-                hash::ser_and_hash(&(sign.chain[i].1.clone(), sign.chain[i + 1].0.clone())).to_vec()
-            } else {
-                hash::ser_and_hash(&(sign.chain[i + 1].0.clone(), sign.chain[i].1.clone())).to_vec()
-            };
-            if h != sign.chain[i + 1].1 {
-                println!("[WARN] Accumulator value does not match.");
-                debug_assert!(false);
-                return;
+        match &self.reference {
+            None => self.reference = Some(digest),
+            Some(reference) => {
+                if *reference != digest {
+                    println!("[WARN] Equivocation detected.");
+                    debug_assert!(false);
+                    // TODO: Broadcast the blame.
+                    return;
+                }
             }
-            change >>= 1;
         }
         self.shard[n as usize] = Some(sh);
+        self.sign[n as usize] = Some(sign);
         self.shard_num += 1;
     }
 
-    pub fn reconstruct(&mut self, num_nodes: Replica, num_faults: Replica) -> Option<Vec<u8>> {
-        if self.shard_num < num_nodes - num_faults {
-            return None;
-        }
-        Some(from_shards(
+    pub fn reconstruct(&mut self, num_nodes: Replica, num_faults: Replica) -> Reconstructed {
+        let witness = match self.sign.iter().flatten().next() {
+            Some(sign) => sign.witness.clone(),
+            None => return Reconstructed::Wait,
+        };
+        match check_encoding(
+            self.alg,
             self.shard.clone(),
-            num_nodes as usize,
-            num_faults as usize,
-        ))
+            num_nodes,
+            num_faults,
+            &witness,
+        ) {
+            Ok(data) => Reconstructed::Data(data),
+            Err(ErasureError::TooFewShards { .. }) => Reconstructed::Wait,
+            Err(e) => {
+                println!("[WARN] Bad encoding: {}.", e);
+                Reconstructed::BadEncoding(
+                    (0..self.size)
+                        .filter(|n| self.shard[*n as usize].is_some())
+                        .map(|n| {
+                            (
+                                n,
+                                self.shard[n as usize].clone().unwrap(),
+                                self.sign[n as usize].clone().unwrap(),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+        }
     }
 }
@@ -1,16 +1,22 @@
-use super::accumulator::ShareGatherer;
+use super::accumulator::{AccumulatorType, ShareGatherer};
+use super::keymap::KeyMap;
+use super::leader::{Committed, LeaderSchedule, RoundRobin};
+use super::signer::{SignKind, Signer};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // use crossfire::mpsc::{SharedSenderFRecvB, TxFuture};
-use crypto_lib::{ed25519, secp256k1, Keypair, PublicKey};
+use crypto::scheme::VerifyingKey;
+use crypto::secret::Secret;
 use tokio::sync::mpsc::UnboundedSender;
+use util::peers::{NetSender, Peers};
 // use crate::Sender;
 use config::Node;
+use crypto::hash::{Hash, HashAlgorithm};
 use std::sync::Arc;
 use types::{
-    Block, Certificate, Height, Propose, ProtocolMsg, Replica, DataWithAcc, Storage, Vote,
-    GENESIS_BLOCK,
+    Block, Certificate, Height, KeyRotation, Propose, Reconfig, Replica, DataWithAcc,
+    SignedKeyRotation, SignedReconfig, Storage, Vote, GENESIS_BLOCK,
 };
 
 // type Sender<T> = TxFuture<T, SharedFutureBoth>;
@@ -19,9 +25,16 @@ pub struct Context {
     pub num_nodes: u16,
     pub num_faults: u16,
     pub myid: Replica,
-    pub pub_key_map: HashMap<Replica, PublicKey>,
-    pub my_secret_key: Keypair,
-    pub net_send: UnboundedSender<(Replica, Arc<ProtocolMsg>)>,
+    pub crypto_alg: crypto::Algorithm,
+    pub hash_alg: HashAlgorithm,
+    /// The keys of the replicas, by the epoch the messages are signed in
+    pub pub_key_map: KeyMap,
+    pub signer: Box<dyn Signer>,
+    pub net_send: NetSender,
+    /// The id the network layer sends to all the nodes
+    pub broadcast: Replica,
+    /// The membership the network layer connects to
+    pub peers: Peers,
     pub cli_send: UnboundedSender<Block>,
     pub is_client_apollo_enabled: bool,
 
@@ -29,6 +42,9 @@ pub struct Context {
     pub height: Height,
     pub epoch: Height,
     pub last_leader: Replica,
+    pub leader_schedule: Box<dyn LeaderSchedule>,
+    /// The block committed this epoch, for the leader schedule
+    pub committed: Option<Committed>,
     pub last_seen_block: Arc<Block>,
     pub last_committed_block_ht: Height,
     pub payload: usize,
@@ -47,9 +63,13 @@ pub struct Context {
 
     pub received_commit: Option<Vec<crypto::EVSSCommit381>>,
     pub received_commit_sign: Option<DataWithAcc>,
+    /// Hash of the commitments dealt to us through a Commit, by dealer and
+    /// the epoch the dealer is to propose them in
+    pub dealt_commits: HashMap<(Replica, Height), Hash>,
 
     pub accumulator_pub_params_map: HashMap<Replica, crypto::EVSSPublicParams381>,
-    pub accumulator_params: crypto::EVSSParams381,
+    pub accumulator_params: Secret<crypto::EVSSParams381>,
+    pub accumulator_type: AccumulatorType,
 
     pub propose_share_sent: bool,
     pub vote_cert_share_sent: bool,
@@ -58,24 +78,40 @@ pub struct Context {
     pub propose_gatherer: ShareGatherer,
     pub vote_cert_gatherer: ShareGatherer,
     pub commit_gatherer: ShareGatherer,
-    
+    /// Whether the commitments of the next leader were decoded, or proven
+    /// badly encoded
+    pub commit_decoded: bool,
+    /// The dealers proven to have encoded something badly this epoch
+    pub bad_dealers: HashSet<Replica>,
+
     pub rand_beacon_parameter: crypto::EVSSParams381,
-    pub rand_beacon_queue: HashMap<Replica, std::collections::VecDeque<crypto::EVSSShare381>>,
+    /// The shares dealt to us, by dealer
+    pub rand_beacon_queue: Secret<HashMap<Replica, std::collections::VecDeque<crypto::EVSSShare381>>>,
 
     pub reconstruct_queue: std::collections::VecDeque<(crypto::EVSSShare381, Height)>,
 
-    pub shards: Vec<std::collections::VecDeque<crypto::EVSSShare381>>,
+    /// The shares we deal for our next epoch, by receiver
+    pub shards: Secret<Vec<std::collections::VecDeque<crypto::EVSSShare381>>>,
     pub commits: Vec<crypto::EVSSCommit381>,
 
-    pub rand_beacon_shares: Vec<(Vec<std::collections::VecDeque<crypto::EVSSShare381>>, Vec<crypto::EVSSCommit381>)>,
+    pub rand_beacon_shares: Secret<Vec<config::Dealing>>,
+
+    /// A reconfiguration received from a client, to be proposed when we lead
+    pub pending_reconfig: Option<SignedReconfig>,
+    /// A committed reconfiguration waiting for its epoch
+    pub scheduled_reconfig: Option<Reconfig>,
+    /// Key rotations received from clients, to be proposed when we lead
+    pub pending_rotations: Vec<SignedKeyRotation>,
 }
 
 const EXTRA_SPACE: usize = 100;
 
 impl Context {
+    /// The secrets are moved out of `config` rather than copied
     pub fn new(
-        config: &Node,
-        net_send: UnboundedSender<(Replica, Arc<ProtocolMsg>)>,
+        config: &mut Node,
+        signer: Box<dyn Signer>,
+        net_send: NetSender,
         cli_send: UnboundedSender<Block>,
     ) -> Self {
         let genesis_block = Arc::new(GENESIS_BLOCK);
@@ -83,24 +119,19 @@ impl Context {
             num_nodes: config.num_nodes as u16,
             num_faults: config.num_faults as u16,
             myid: config.id,
-            my_secret_key: match config.crypto_alg {
-                crypto::Algorithm::ED25519 => {
-                    let mut sk_copy = config.secret_key_bytes.clone();
-                    let kp = ed25519::Keypair::decode(&mut sk_copy)
-                        .expect("Failed to decode the secret key from the config");
-                    Keypair::Ed25519(kp)
-                }
-                crypto::Algorithm::SECP256K1 => {
-                    let sk_copy = config.secret_key_bytes.clone();
-                    let sk = secp256k1::SecretKey::from_bytes(sk_copy)
-                        .expect("Failed to decode the secret key from the config");
-                    let kp = secp256k1::Keypair::from(sk);
-                    Keypair::Secp256k1(kp)
-                }
-                _ => panic!("Unimplemented algorithm"),
-            },
-            pub_key_map: HashMap::with_capacity(config.num_nodes),
+            crypto_alg: config.crypto_alg.clone(),
+            hash_alg: config.hash_alg,
+            signer,
+            pub_key_map: KeyMap::new(),
             net_send: net_send,
+            broadcast: config.num_nodes as Replica,
+            peers: Peers {
+                num_nodes: config.num_nodes as Replica,
+                addrs: config.network_map(),
+                keys: config.pk_map.clone(),
+                digest: config.digest(),
+                epoch: 0,
+            },
             cli_send: cli_send,
             storage: Storage::new(EXTRA_SPACE * config.block_size),
             /// The height and next leader are both 1 because the genesis block
@@ -108,6 +139,8 @@ impl Context {
             height: 0,
             epoch: 0,
             last_leader: 0,
+            leader_schedule: Box::new(RoundRobin::new(config.num_nodes as Replica)),
+            committed: None,
             last_seen_block: Arc::clone(&genesis_block),
             last_committed_block_ht: 0,
             is_client_apollo_enabled: false,
@@ -126,28 +159,36 @@ impl Context {
             received_certificate_sign: None,
 
             accumulator_pub_params_map: config.bi_pp_map.clone(),
-            accumulator_params: config.bi_p.clone().unwrap(),
+            accumulator_params: config.bi_p.take().unwrap(),
+            accumulator_type: AccumulatorType::Merkle,
 
             received_commit: None,
             received_commit_sign: None,
+            dealt_commits: HashMap::with_capacity(config.num_nodes),
 
             propose_share_sent: false,
             vote_cert_share_sent: false,
             commit_share_sent: false,
 
-            propose_gatherer: ShareGatherer::new(config.num_nodes as u16),
-            vote_cert_gatherer: ShareGatherer::new(config.num_nodes as u16),
-            commit_gatherer: ShareGatherer::new(config.num_nodes as u16),
+            propose_gatherer: ShareGatherer::new(config.num_nodes as u16, SignKind::Propose, config.hash_alg),
+            vote_cert_gatherer: ShareGatherer::new(config.num_nodes as u16, SignKind::Certificate, config.hash_alg),
+            commit_gatherer: ShareGatherer::new(config.num_nodes as u16, SignKind::Commit, config.hash_alg),
+            commit_decoded: false,
+            bad_dealers: HashSet::new(),
 
             rand_beacon_parameter: config.rand_beacon_parameter.clone().unwrap(),
-            rand_beacon_queue: config.rand_beacon_queue.clone(),
+            rand_beacon_queue: std::mem::take(&mut config.rand_beacon_queue),
 
             reconstruct_queue: std::collections::VecDeque::with_capacity(config.num_nodes * 2),
 
-            shards: vec![std::collections::VecDeque::with_capacity(config.num_nodes); config.num_nodes],
+            shards: Secret::new(vec![std::collections::VecDeque::with_capacity(config.num_nodes); config.num_nodes]),
             commits: Vec::with_capacity(config.num_nodes),
 
-            rand_beacon_shares: config.rand_beacon_shares.clone(),
+            rand_beacon_shares: std::mem::take(&mut config.rand_beacon_shares),
+
+            pending_reconfig: None,
+            scheduled_reconfig: None,
+            pending_rotations: Vec::new(),
         };
         c.storage
             .committed_blocks_by_hash
@@ -155,30 +196,102 @@ impl Context {
         c.storage
             .committed_blocks_by_ht
             .insert(0, Arc::clone(&genesis_block));
-        for (id, mut pk_data) in &config.pk_map {
-            let pk = match config.crypto_alg {
-                crypto::Algorithm::ED25519 => {
-                    let kp = ed25519::PublicKey::decode(&mut pk_data)
-                        .expect("Failed to decode the secret key from the config");
-                    PublicKey::Ed25519(kp)
-                }
-                crypto::Algorithm::SECP256K1 => {
-                    let sk = secp256k1::PublicKey::decode(&pk_data)
-                        .expect("Failed to decode the secret key from the config");
-                    PublicKey::Secp256k1(sk)
-                }
-                _ => panic!("Unimplemented algorithm"),
-            };
-            c.pub_key_map.insert(*id, pk);
+        for (id, pk_data) in &config.pk_map {
+            c.pub_key_map
+                .insert(*id, 0, decode_pk(&config.crypto_alg, pk_data));
         }
         c
     }
 
-    pub fn next_leader(&self) -> Replica {
-        self.next_of(self.last_leader)
+    /// Switches to the membership of `r` at an epoch boundary. Everything
+    /// dealt or gathered for the old membership is dropped, so the beacon is
+    /// empty until dealings under the new parameters are reconstructed.
+    pub fn reconfigure(&mut self, r: &Reconfig) {
+        let n = r.num_nodes;
+        // Keys of the old membership still verify what was signed before
+        for id in 0..self.num_nodes {
+            if !r.pk_map.contains_key(&id) {
+                self.pub_key_map.remove(id, r.epoch);
+            }
+        }
+        for (id, pk_data) in r.pk_map.iter() {
+            self.pub_key_map
+                .insert(*id, r.epoch, decode_pk(&self.crypto_alg, pk_data));
+        }
+        self.num_nodes = n as u16;
+        self.num_faults = r.num_faults as u16;
+        self.broadcast = n as Replica;
+        let public = config::Public {
+            pk_map: r.pk_map.iter().map(|(id, pk)| (*id, pk.clone())).collect(),
+            bi_pp_map: r.bi_pp_map.iter().map(|(id, pp)| (*id, pp.clone())).collect(),
+            rand_beacon_parameter: Some(r.rand_beacon_parameter.clone()),
+        };
+        // A node that joins says hello with the digest of a config for the
+        // new membership
+        let digest = public.digest(n, r.num_faults, &self.crypto_alg, self.hash_alg);
+        self.peers = Peers {
+            num_nodes: n as Replica,
+            addrs: r.net_map.iter().map(|(id, addr)| (*id, addr.clone())).collect(),
+            keys: public.pk_map,
+            digest,
+            epoch: self.peers.epoch.max(r.epoch),
+        };
+        // Before anything is sent to the new membership
+        let _ = self.net_send.update(self.peers.clone());
+        self.accumulator_pub_params_map = r
+            .bi_pp_map
+            .iter()
+            .map(|(id, pp)| (*id, pp.clone()))
+            .collect();
+        self.rand_beacon_parameter = r.rand_beacon_parameter.clone();
+        self.rand_beacon_queue = Secret::new(
+            (0..n as Replica)
+                .map(|id| (id, std::collections::VecDeque::with_capacity(n)))
+                .collect(),
+        );
+        self.reconstruct_queue.clear();
+        // The pre-generated dealings are for the old membership
+        self.rand_beacon_shares = Secret::default();
+        self.shards = Secret::new(vec![std::collections::VecDeque::with_capacity(n); n]);
+        self.commits.clear();
+        self.received_ack.clear();
+        self.dealt_commits.clear();
+        self.received_commit = None;
+        self.received_commit_sign = None;
+        self.propose_gatherer = ShareGatherer::new(n as u16, SignKind::Propose, self.hash_alg);
+        self.vote_cert_gatherer =
+            ShareGatherer::new(n as u16, SignKind::Certificate, self.hash_alg);
+        self.commit_gatherer = ShareGatherer::new(n as u16, SignKind::Commit, self.hash_alg);
+        self.leader_schedule.resize(n as u16, r.epoch);
+    }
+
+    /// Schedules the new key of a committed rotation, and signs with it from
+    /// then on if it is ours
+    pub fn rotate_key(&mut self, rotation: &KeyRotation) {
+        let pk = decode_pk(&self.crypto_alg, &rotation.new_pk);
+        self.pub_key_map.insert(rotation.replica, rotation.epoch, pk);
+        if rotation.replica == self.myid {
+            if let Err(e) = self.signer.rotate(rotation.epoch, &rotation.new_pk) {
+                println!(
+                    "[WARN] Cannot sign with the rotated key from epoch {}: {}",
+                    rotation.epoch, e
+                );
+            }
+        }
+        // The links of the node are opened with the new key from now on, and
+        // the hellos signed with it
+        self.peers.keys.insert(rotation.replica, rotation.new_pk.clone());
+        self.peers.epoch = self.peers.epoch.max(rotation.epoch);
+        let _ = self.net_send.update(self.peers.clone());
     }
 
-    pub fn next_of(&self, prev: Replica) -> Replica {
-        (prev + 1) % self.num_nodes
+    pub fn next_leader(&self) -> Replica {
+        self.leader_schedule.leader(self.epoch + 1)
     }
 }
+
+fn decode_pk(alg: &crypto::Algorithm, pk_data: &[u8]) -> Box<dyn VerifyingKey> {
+    crypto::scheme::lookup(alg)
+        .and_then(|s| s.decode_pk(pk_data))
+        .expect("Failed to decode the public key")
+}
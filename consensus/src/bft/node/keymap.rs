@@ -0,0 +1,108 @@
+use crypto::scheme::VerifyingKey;
+use std::collections::HashMap;
+use types::{sign_digest, Height, Replica, SignKind};
+
+/// The public keys of the replicas over the epochs. A key takes over from
+/// the epoch it is inserted for, and older keys are kept so that messages
+/// signed before a rotation still verify.
+#[derive(Default)]
+pub struct KeyMap {
+    /// The keys of every replica, ordered by the epoch they start at. `None`
+    /// marks a replica that left the membership.
+    keys: HashMap<Replica, Vec<(Height, Option<Box<dyn VerifyingKey>>)>>,
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        KeyMap::default()
+    }
+
+    /// Uses `key` for the messages `r` signs from `epoch` on
+    pub fn insert(&mut self, r: Replica, epoch: Height, key: Box<dyn VerifyingKey>) {
+        self.set(r, epoch, Some(key));
+    }
+
+    /// `r` has no key from `epoch` on
+    pub fn remove(&mut self, r: Replica, epoch: Height) {
+        self.set(r, epoch, None);
+    }
+
+    fn set(&mut self, r: Replica, epoch: Height, key: Option<Box<dyn VerifyingKey>>) {
+        let keys = self.keys.entry(r).or_insert_with(Vec::new);
+        match keys.binary_search_by_key(&epoch, |(e, _)| *e) {
+            Ok(i) => keys[i].1 = key,
+            Err(i) => keys.insert(i, (epoch, key)),
+        }
+    }
+
+    /// The key `r` signs with in `epoch`
+    pub fn get(&self, r: &Replica, epoch: Height) -> Option<&dyn VerifyingKey> {
+        let keys = self.keys.get(r)?;
+        let i = match keys.binary_search_by_key(&epoch, |(e, _)| *e) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        keys[i].1.as_deref()
+    }
+
+    /// Checks that `sig` is by `r` with its key for `epoch`, on `msg` as a
+    /// message of `kind` signed in `epoch`
+    pub fn verify(
+        &self,
+        r: &Replica,
+        kind: SignKind,
+        epoch: Height,
+        msg: &[u8],
+        sig: &[u8],
+    ) -> bool {
+        let digest = sign_digest(kind, epoch, msg);
+        matches!(self.get(r, epoch), Some(pk) if pk.verify(&digest, sig))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::scheme::{lookup, SigningKey};
+    use crypto::Algorithm;
+
+    fn keypair() -> (Box<dyn VerifyingKey>, Box<dyn SigningKey>) {
+        let scheme = lookup(&Algorithm::ED25519).unwrap();
+        let (pk, sk) = scheme.generate().unwrap();
+        (
+            scheme.decode_pk(&pk).unwrap(),
+            scheme.decode_sk(&sk).unwrap(),
+        )
+    }
+
+    #[test]
+    fn rotation() {
+        let (old_pk, old_sk) = keypair();
+        let (new_pk, new_sk) = keypair();
+        let mut map = KeyMap::new();
+        map.insert(0, 0, old_pk);
+        map.insert(0, 5, new_pk);
+        let sign = |sk: &dyn SigningKey, epoch| {
+            sk.sign(&sign_digest(SignKind::Vote, epoch, b"block"))
+                .unwrap()
+        };
+        let old_sig = sign(old_sk.as_ref(), 4);
+        let new_sig = sign(new_sk.as_ref(), 4);
+        // A certificate from before the rotation still verifies
+        assert!(map.verify(&0, SignKind::Vote, 4, b"block", &old_sig));
+        assert!(!map.verify(&0, SignKind::Vote, 4, b"block", &new_sig));
+        assert!(!map.verify(&0, SignKind::Ack, 4, b"block", &old_sig));
+        assert!(!map.verify(&1, SignKind::Vote, 4, b"block", &old_sig));
+        // A signature only verifies for the epoch it was made for
+        let new_sig = sign(new_sk.as_ref(), 5);
+        assert!(map.verify(&0, SignKind::Vote, 5, b"block", &new_sig));
+        assert!(!map.verify(&0, SignKind::Vote, 6, b"block", &new_sig));
+        assert!(!map.verify(&0, SignKind::Vote, 9, b"block", &sign(old_sk.as_ref(), 9)));
+
+        map.remove(0, 7);
+        assert!(map.get(&0, 7).is_none());
+        assert!(map.verify(&0, SignKind::Vote, 6, b"block", &sign(new_sk.as_ref(), 6)));
+        assert!(!map.verify(&0, SignKind::Vote, 7, b"block", &sign(new_sk.as_ref(), 7)));
+    }
+}
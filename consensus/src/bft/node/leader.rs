@@ -0,0 +1,180 @@
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use std::collections::HashMap;
+use types::{Height, Replica};
+
+/// The block committed in an epoch. Every node that commits in the epoch
+/// commits the same block, backed by a certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Committed {
+    pub hash: Hash,
+    pub author: Replica,
+    /// Whether the block carries the commitments for the next epoch
+    pub dealt: bool,
+}
+
+/// Decides who leads every epoch.
+///
+/// The leader of an epoch deals its commitments during the epoch before, so
+/// the leader of `e + 2` has to be fixed by the end of `e`. Every node must
+/// feed the schedule the same inputs to agree on the leaders, so it only
+/// learns of committed blocks, and not of anything a node reconstructed on
+/// its own such as the beacon.
+pub trait LeaderSchedule: Send {
+    fn leader(&self, epoch: Height) -> Replica;
+
+    /// Called at the end of `epoch` with the block committed in it, if any
+    fn end_epoch(&mut self, _epoch: Height, _committed: Option<&Committed>) {}
+
+    /// Called when a reconfiguration changes the number of nodes from
+    /// `epoch` on
+    fn resize(&mut self, num_nodes: Replica, epoch: Height);
+}
+
+/// Every node leads in turn
+pub struct RoundRobin {
+    pub num_nodes: Replica,
+}
+
+impl RoundRobin {
+    pub fn new(num_nodes: Replica) -> Self {
+        RoundRobin { num_nodes }
+    }
+}
+
+impl LeaderSchedule for RoundRobin {
+    fn leader(&self, epoch: Height) -> Replica {
+        (epoch % self.num_nodes as Height) as Replica
+    }
+
+    fn resize(&mut self, num_nodes: Replica, _epoch: Height) {
+        self.num_nodes = num_nodes;
+    }
+}
+
+/// Picks the leader of `e + 2` at random from the block committed in `e`,
+/// leaving out the leader of `e + 1` and the nodes blamed in the last
+/// `penalty` epochs. A leader whose committed block carries no commitments is
+/// blamed, except in the first epoch of a membership, when it had nothing to
+/// deal. Falls back to the next node in turn when nothing was committed.
+pub struct BeaconSchedule {
+    pub num_nodes: Replica,
+    pub penalty: Height,
+    alg: HashAlgorithm,
+    leaders: HashMap<Height, Replica>,
+    blamed: HashMap<Replica, Height>,
+    /// The first epoch of the current membership
+    start: Height,
+}
+
+impl BeaconSchedule {
+    pub fn new(num_nodes: Replica, penalty: Height, alg: HashAlgorithm) -> Self {
+        BeaconSchedule {
+            num_nodes,
+            penalty,
+            alg,
+            leaders: HashMap::new(),
+            blamed: HashMap::new(),
+            start: 0,
+        }
+    }
+
+    fn is_penalised(&self, r: Replica, epoch: Height) -> bool {
+        match self.blamed.get(&r) {
+            Some(e) => e + self.penalty > epoch,
+            None => false,
+        }
+    }
+}
+
+impl LeaderSchedule for BeaconSchedule {
+    fn leader(&self, epoch: Height) -> Replica {
+        match self.leaders.get(&epoch) {
+            Some(l) => *l,
+            // Nothing is known before the first beacon
+            None => (epoch % self.num_nodes as Height) as Replica,
+        }
+    }
+
+    fn end_epoch(&mut self, epoch: Height, committed: Option<&Committed>) {
+        if let Some(block) = committed {
+            if !block.dealt && epoch != self.start {
+                self.blamed.insert(block.author, epoch);
+            }
+        }
+        let prev = self.leader(epoch + 1);
+        let fallback = (prev + 1) % self.num_nodes;
+        let candidates: Vec<Replica> = (0..self.num_nodes)
+            .filter(|r| *r != prev && !self.is_penalised(*r, epoch))
+            .collect();
+        let next = match committed {
+            Some(block) if !candidates.is_empty() => {
+                let hash = ser_and_hash_in(self.alg, Domain::Leader, &(&block.hash, epoch + 2));
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&hash[..8]);
+                let idx = u64::from_le_bytes(bytes) % candidates.len() as u64;
+                candidates[idx as usize]
+            }
+            _ => fallback,
+        };
+        self.leaders.insert(epoch + 2, next);
+        self.leaders.retain(|e, _| *e > epoch);
+    }
+
+    fn resize(&mut self, num_nodes: Replica, epoch: Height) {
+        self.num_nodes = num_nodes;
+        self.start = epoch;
+        self.leaders.retain(|_, l| *l < num_nodes);
+        self.blamed.retain(|r, _| *r < num_nodes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin() {
+        let s = RoundRobin::new(4);
+        let leaders: Vec<_> = (0..6).map(|e| s.leader(e)).collect();
+        assert_eq!(leaders, vec![0, 1, 2, 3, 0, 1]);
+    }
+
+    fn block(e: Height, author: Replica, dealt: bool) -> Committed {
+        Committed {
+            hash: ser_and_hash_in(HashAlgorithm::SHA256, Domain::Block, &e),
+            author,
+            dealt,
+        }
+    }
+
+    #[test]
+    fn beacon_schedule() {
+        let alg = HashAlgorithm::SHA256;
+        let mut a = BeaconSchedule::new(7, 5, alg);
+        let mut b = BeaconSchedule::new(7, 5, alg);
+        // Nothing committed yet
+        a.end_epoch(0, None);
+        b.end_epoch(0, None);
+        assert_eq!(a.leader(2), 2);
+        for e in 1..50 {
+            // Node 3 never deals
+            let committed = block(e, 3, false);
+            a.end_epoch(e, Some(&committed));
+            b.end_epoch(e, Some(&committed));
+            assert_eq!(a.leader(e + 2), b.leader(e + 2));
+            assert_ne!(a.leader(e + 2), a.leader(e + 1));
+            assert_ne!(a.leader(e + 2), 3);
+        }
+    }
+
+    #[test]
+    fn no_blame_after_reconfiguration() {
+        let mut s = BeaconSchedule::new(4, 5, HashAlgorithm::SHA256);
+        s.resize(4, 10);
+        // The first leader of the membership had nothing to deal
+        s.end_epoch(10, Some(&block(10, 2, false)));
+        assert!(!s.is_penalised(2, 11));
+        s.end_epoch(11, Some(&block(11, 1, false)));
+        assert!(s.is_penalised(1, 12));
+    }
+}
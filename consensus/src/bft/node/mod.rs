@@ -1,6 +1,9 @@
 pub mod accumulator;
 pub mod context;
+pub mod keymap;
+pub mod leader;
 pub mod reactor;
+pub mod signer;
 
 pub use reactor::*;
 // pub use proposal::*;
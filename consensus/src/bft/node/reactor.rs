@@ -1,19 +1,26 @@
-use super::accumulator::{get_acc, get_sign, to_shards};
+use super::accumulator::{
+    get_acc, get_sign, to_shards, verify_bad_encoding, AccumulatorType, Reconstructed,
+};
 use super::context::Context;
+use super::leader::{Committed, LeaderSchedule};
+use super::signer::{SignKind, Signer};
+use crypto::secret::{Secret, Wipe};
 use config::Node;
-use crypto::hash::EMPTY_HASH;
+use crypto::hash::{Hash, EMPTY_HASH};
 use crypto::rand::{SeedableRng};
-use crypto::{CanonicalSerialize};
+use crypto::{CanonicalSerialize, UniformRand};
 use num_traits::Zero;
 use std::time::Duration;
 use std::{convert::TryInto, sync::Arc};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::time;
 use types::{
-    commit_from_bytes, Block, Certificate, Content, Propose, ProtocolMsg, Replica,
+    ack_hash, commits_hash, try_commit_from_bytes, BadEncoding, Block, Certificate, Content,
+    DataWithAcc, Height, Propose, ProtocolMsg, Replica, SignedKeyRotation, SignedReconfig,
     Transaction, Vote,
 };
 use util::io::to_bytes;
+use util::peers::NetSender;
 
 #[derive(PartialEq, Debug)]
 enum Phase {
@@ -48,8 +55,9 @@ fn deliver_propose(cx: &mut Context, myid: Replica) {
     cx.propose_gatherer.add_share(
         shards[myid as usize].clone(),
         myid,
+        cx.epoch,
         cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(),
-        cx.pub_key_map.get(&cx.last_leader).unwrap(),
+        cx.pub_key_map.get(&cx.last_leader, cx.epoch).unwrap(),
         get_sign(cx.received_propose_sign.as_ref().unwrap(), myid),
     );
     for i in 0..cx.num_nodes {
@@ -69,7 +77,7 @@ fn deliver_propose(cx: &mut Context, myid: Replica) {
     if !cx.propose_share_sent {
         cx.net_send
             .send((
-                cx.num_nodes,
+                cx.broadcast,
                 Arc::new(ProtocolMsg::DeliverPropose(
                     shards[myid as usize].clone(),
                     myid,
@@ -90,8 +98,9 @@ fn deliver_vote_cert(cx: &mut Context, myid: Replica) {
     cx.vote_cert_gatherer.add_share(
         shards[myid as usize].clone(),
         myid,
+        cx.epoch,
         cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(),
-        cx.pub_key_map.get(&cx.last_leader).unwrap(),
+        cx.pub_key_map.get(&cx.last_leader, cx.epoch).unwrap(),
         get_sign(cx.received_certificate_sign.as_ref().unwrap(), myid),
     );
     for i in 0..cx.num_nodes {
@@ -111,7 +120,7 @@ fn deliver_vote_cert(cx: &mut Context, myid: Replica) {
     if !cx.vote_cert_share_sent {
         cx.net_send
             .send((
-                cx.num_nodes,
+                cx.broadcast,
                 Arc::new(ProtocolMsg::DeliverVoteCert(
                     shards[myid as usize].clone(),
                     myid,
@@ -132,10 +141,11 @@ fn deliver_commit(cx: &mut Context, myid: Replica) {
     cx.commit_gatherer.add_share(
         shards[myid as usize].clone(),
         myid,
+        cx.epoch,
         cx.accumulator_pub_params_map
             .get(&cx.next_leader())
             .unwrap(),
-        cx.pub_key_map.get(&cx.next_leader()).unwrap(),
+        cx.pub_key_map.get(&cx.next_leader(), cx.epoch).unwrap(),
         get_sign(cx.received_commit_sign.as_ref().unwrap(), myid),
     );
     for i in 0..cx.num_nodes {
@@ -155,7 +165,7 @@ fn deliver_commit(cx: &mut Context, myid: Replica) {
     if !cx.commit_share_sent {
         cx.net_send
             .send((
-                cx.num_nodes,
+                cx.broadcast,
                 Arc::new(ProtocolMsg::DeliverCommit(
                     shards[myid as usize].clone(),
                     myid,
@@ -167,18 +177,242 @@ fn deliver_commit(cx: &mut Context, myid: Replica) {
     }
 }
 
+/// A reconfiguration needs the approval of f+1 current replicas, and must
+/// leave time for the leaders of the next two epochs to be scheduled.
+fn check_reconfig(cx: &Context, r: &SignedReconfig, epoch: Height) -> bool {
+    if !r.reconfig.is_well_formed() {
+        println!("[WARN] The reconfiguration is malformed.");
+        return false;
+    }
+    if r.reconfig.epoch < epoch + 2 {
+        println!("[WARN] The reconfiguration for epoch {} is too late.", r.reconfig.epoch);
+        return false;
+    }
+    let hash = r.reconfig.hash(cx.hash_alg);
+    let mut origins = std::collections::HashSet::with_capacity(r.votes.len());
+    // Approvals are signed with the key itself rather than through a signer,
+    // on a hash of their own domain
+    for vote in r.votes.iter() {
+        if vote.msg != hash
+            || !matches!(cx.pub_key_map.get(&vote.origin, epoch), Some(pk) if pk.verify(&hash, &vote.auth))
+        {
+            println!("[WARN] Cannot verify the approval of the reconfiguration by {}.", vote.origin);
+            return false;
+        }
+        origins.insert(vote.origin);
+    }
+    if origins.len() < (cx.num_faults + 1) as usize {
+        println!("[WARN] Only {} approvals for the reconfiguration.", origins.len());
+        return false;
+    }
+    true
+}
+
+/// A key rotation must be signed by both the current and the new key of the
+/// replica, and leave time for the leaders of the next two epochs to be
+/// scheduled.
+fn check_rotation(cx: &Context, r: &SignedKeyRotation, epoch: Height) -> bool {
+    let rotation = &r.rotation;
+    if rotation.epoch < epoch + 2 {
+        println!("[WARN] The key rotation for epoch {} is too late.", rotation.epoch);
+        return false;
+    }
+    let hash = rotation.hash(cx.hash_alg);
+    if !matches!(cx.pub_key_map.get(&rotation.replica, epoch), Some(pk) if pk.verify(&hash, &r.old_sig)) {
+        println!("[WARN] Cannot verify the key rotation of {} with its current key.", rotation.replica);
+        return false;
+    }
+    match crypto::scheme::lookup(&cx.crypto_alg).and_then(|s| s.decode_pk(&rotation.new_pk)) {
+        Ok(pk) if pk.verify(&hash, &r.new_sig) => true,
+        Ok(_) => {
+            println!("[WARN] Cannot verify the key rotation of {} with its new key.", rotation.replica);
+            false
+        }
+        Err(e) => {
+            println!("[WARN] The new key of {} is invalid: {}", rotation.replica, e);
+            false
+        }
+    }
+}
+
+/// Whether `p` proves that its dealer encoded a proposal or commitments badly
+/// this epoch
+pub fn check_bad_encoding(cx: &Context, p: &BadEncoding) -> bool {
+    match (cx.accumulator_pub_params_map.get(&p.dealer), cx.pub_key_map.get(&p.dealer, cx.epoch)) {
+        (Some(pp), Some(pk)) => verify_bad_encoding(cx.hash_alg, p, cx.epoch, cx.num_nodes, cx.num_faults, pp, pk),
+        _ => false,
+    }
+}
+
+/// The commitments for the next epoch can only come from its leader, signed
+/// for this epoch.
+pub fn check_commit(cx: &Context, from: Replica, z: &DataWithAcc) -> bool {
+    let dealer = cx.next_leader();
+    if from != dealer {
+        println!("[WARN] Received commitments from {}, but the next leader is {}.", from, dealer);
+        return false;
+    }
+    if !cx.pub_key_map.verify(&dealer, SignKind::Commit, cx.epoch, &z.acc.digest(cx.hash_alg), &z.sign) {
+        println!("[WARN] Cannot verify the commitments of {}.", dealer);
+        return false;
+    }
+    true
+}
+
+/// Called with every block we commit. Reconfigurations and key rotations
+/// take effect only through here, so that every node applies the same ones.
+fn on_commit(cx: &mut Context, block: &Block) {
+    for r in block.body.data.rotations.iter() {
+        println!("Key rotation of {} scheduled for epoch {}.", r.rotation.replica, r.rotation.epoch);
+        cx.rotate_key(&r.rotation);
+        cx.pending_rotations.retain(|p| p.rotation != r.rotation);
+    }
+    if let Some(r) = &block.body.data.reconfig {
+        println!("Reconfiguration to {} nodes scheduled for epoch {}.", r.reconfig.num_nodes, r.reconfig.epoch);
+        cx.scheduled_reconfig = Some(r.reconfig.clone());
+        if cx.pending_reconfig.as_ref().map_or(false, |p| p.reconfig.hash(cx.hash_alg) == r.reconfig.hash(cx.hash_alg)) {
+            cx.pending_reconfig = None;
+        }
+    }
+}
+
+/// Reconstructs the beacon of the epoch from the shares we gathered, and
+/// fixes the leader two epochs ahead from the block committed in it. The
+/// beacon is only reported: a node that gathered too few shares outputs
+/// none, so it cannot feed the schedule.
+pub fn end_epoch(cx: &mut Context) -> Hash {
+    let mut vec = Vec::with_capacity(cx.num_nodes as usize);
+    while !cx.reconstruct_queue.is_empty() && cx.reconstruct_queue.front().unwrap().1 < cx.epoch {
+        cx.reconstruct_queue.pop_front();
+    }
+    while !cx.reconstruct_queue.is_empty() && cx.reconstruct_queue.front().unwrap().1 == cx.epoch {
+        vec.push(cx.reconstruct_queue.pop_front().unwrap().0);
+    }
+    let mut hash = EMPTY_HASH;
+    if vec.len() >= (cx.num_nodes - cx.num_faults) as usize {
+        let mut buf = Vec::new();
+        crypto::EVSS381::reconstruct(&vec).serialize(&mut buf).unwrap();
+        hash = crypto::hash::hash_in(cx.hash_alg, crypto::hash::Domain::Beacon, &buf);
+    }
+    println!("Rand Beacon: {:x?}", hash);
+    let committed = cx.committed.take();
+    cx.leader_schedule.end_epoch(cx.epoch, committed.as_ref());
+    hash
+}
+
+/// The shares and commitments we deal for the next epoch we lead: a
+/// polynomial for each of the next n epochs, with a share of each for every
+/// replica. A pre-generated dealing stands for all n of them. Falls back to
+/// a fresh dealing when there is no pre-generated one for the membership.
+pub fn next_dealing(cx: &Context) -> (Vec<std::collections::VecDeque<crypto::EVSSShare381>>, Vec<crypto::EVSSCommit381>) {
+    let n = cx.num_nodes as usize;
+    if !cx.rand_beacon_shares.is_empty() {
+        let (shares, commit) = &cx.rand_beacon_shares[cx.epoch as usize % cx.rand_beacon_shares.len()];
+        return (shares.iter().map(|s| vec![s.clone(); n].into()).collect(), vec![commit.clone(); n]);
+    }
+    let rng = &mut crypto::rand::rngs::StdRng::from_entropy();
+    let polys: Vec<_> = (0..n)
+        .map(|_| crypto::EVSS381::commit(&cx.rand_beacon_parameter, crypto::F381::rand(rng), rng).unwrap())
+        .collect();
+    let shares = (0..n)
+        .map(|j| {
+            let point = crypto::F381::from((j + 1) as u16);
+            polys
+                .iter()
+                .map(|poly| crypto::EVSS381::get_share(point, &cx.rand_beacon_parameter, poly, rng).unwrap())
+                .collect()
+        })
+        .collect();
+    (shares, polys.iter().map(|poly| poly.get_commit()).collect())
+}
+
+/// Acts on a bad encoding by `dealer` in this epoch: we neither vote for
+/// its proposal nor ack its commitments, nor accept them when it proposes.
+/// The blame follows once it commits a block without commitments.
+fn reject_dealer(cx: &mut Context, dealer: Replica) {
+    cx.bad_dealers.insert(dealer);
+    if dealer == cx.next_leader() {
+        cx.commit_decoded = true;
+        cx.dealt_commits.remove(&(dealer, cx.epoch + 1));
+    }
+}
+
+/// A leader may only propose commitments that a quorum has acknowledged, and
+/// they must be the ones it dealt to us.
+fn check_content(cx: &Context, p: &Propose) -> bool {
+    let data = &p.new_block.body.data;
+    if let Some(r) = &data.reconfig {
+        if !check_reconfig(cx, r, p.epoch) {
+            return false;
+        }
+    }
+    if !data.rotations.iter().all(|r| check_rotation(cx, r, p.epoch)) {
+        return false;
+    }
+    if data.commits.is_empty() {
+        if !data.acks.is_empty() {
+            println!("[WARN] Acks proposed without commitments.");
+            return false;
+        }
+        return true;
+    }
+    if data.commits.len() != cx.num_nodes as usize {
+        println!("[WARN] Proposed {} commitments for {} nodes.", data.commits.len(), cx.num_nodes);
+        return false;
+    }
+    let commit_hash = ack_hash(cx.hash_alg, p.epoch, &data.commits);
+    let mut origins = std::collections::HashSet::with_capacity(data.acks.len());
+    for cert in data.acks.iter() {
+        if cert.msg != commit_hash {
+            println!("[WARN] The hash of the certification does not match commit.");
+            return false;
+        }
+        // The acks were signed in the epoch before
+        if !cx.pub_key_map.verify(&cert.origin, SignKind::Ack, p.epoch.saturating_sub(1), &cert.msg, &cert.auth) {
+            println!("[WARN] The auth of the certification does not match commit.");
+            return false;
+        }
+        if !origins.insert(cert.origin) {
+            println!("[WARN] Duplicate ack from {} in the proposal.", cert.origin);
+            return false;
+        }
+    }
+    if origins.len() < (cx.num_faults + 1) as usize {
+        println!("[WARN] Only {} acks for the proposed commitments.", origins.len());
+        return false;
+    }
+    match cx.dealt_commits.get(&(p.new_block.header.author, p.epoch)) {
+        Some(h) if *h != commits_hash(cx.hash_alg, &data.commits) => {
+            println!("[WARN] The proposed commitments differ from the dealt ones.");
+            false
+        }
+        Some(_) => true,
+        None => {
+            // Without our shares we could not take part in the beacon. We do
+            // not vote then; the f+1 votes the block needs come from others.
+            println!("[WARN] No commitments were dealt to us by {} for epoch {}.", p.new_block.header.author, p.epoch);
+            false
+        }
+    }
+}
+
 pub async fn reactor(
-    config: &Node,
+    mut config: Node,
     is_client_apollo_enabled: bool,
-    net_send: UnboundedSender<(Replica, Arc<ProtocolMsg>)>,
+    leader_schedule: Box<dyn LeaderSchedule>,
+    accumulator_type: AccumulatorType,
+    signer: Box<dyn Signer>,
+    net_send: NetSender,
     mut net_recv: UnboundedReceiver<(Replica, ProtocolMsg)>,
     _cli_send: UnboundedSender<Arc<Block>>,
     mut cli_recv: UnboundedReceiver<Transaction>,
 ) {
     // Optimization to improve latency when the payloads are high
     let (send, mut _recv) = unbounded_channel();
-    let mut cx = Context::new(config, net_send, send);
+    let mut cx = Context::new(&mut config, signer, net_send, send);
     cx.is_client_apollo_enabled = is_client_apollo_enabled;
+    cx.leader_schedule = leader_schedule;
+    cx.accumulator_type = accumulator_type;
     let myid = config.id;
     let delta = config.delta;
     // A little time to boot everything up
@@ -193,9 +427,9 @@ pub async fn reactor(
                 // Received a protocol message
                 if let None = pmsg_opt {
                     log::error!(target:"node", "Protocol message channel closed");
-                    std::process::exit(0);
+                    return;
                 }
-                let (_, pmsg) = pmsg_opt.unwrap();
+                let (sender, pmsg) = pmsg_opt.unwrap();
                 let s = pmsg.to_string();
                 println!("{}: Received {:?}.", myid, s);
                 let time_before = time::Instant::now();
@@ -203,13 +437,12 @@ pub async fn reactor(
                     ProtocolMsg::Certificate(p) => {
                         if myid == cx.last_leader && phase == Phase::Propose {
                             // Check that the certificate is valid.
-                            for vote in p.votes.iter() {
-                                if !cx.pub_key_map.get(&vote.origin).unwrap().verify(&vote.msg, &vote.auth) {
-                                    println!("[WARN] Cannot verify the certificate.")
-                                }
+                            let is_valid = p.votes.iter().all(|vote| cx.pub_key_map.verify(&vote.origin, SignKind::Vote, p.epoch, &vote.msg, &vote.auth));
+                            if !is_valid {
+                                println!("[WARN] Cannot verify the certificate.");
                             }
                             let hash = if p.votes.len() == 0 { EMPTY_HASH.to_vec() } else { p.votes[0].msg.clone() };
-                            if let Some(block) = cx.storage.committed_blocks_by_hash.get(&TryInto::<[u8; 32]>::try_into(hash).unwrap()) {
+                            if let Some(block) = cx.storage.committed_blocks_by_hash.get(&TryInto::<[u8; 32]>::try_into(hash).unwrap()).filter(|_| is_valid) {
                                 if block.header.height > cx.highest_height {
                                     cx.highest_cert = p;
                                     cx.highest_height = block.header.height;
@@ -219,28 +452,26 @@ pub async fn reactor(
                     },
                     ProtocolMsg::Propose(mut p, z) => {
                         let mut is_valid = true;
-                        p.new_block.update_hash();
+                        p.new_block.update_hash(cx.hash_alg);
                         let hash = p.new_block.hash.to_vec();
+                        // The votes sign the epoch they were cast in, so they only
+                        // verify at the epoch the certificate claims if it is theirs.
+                        if p.certificate.epoch > p.epoch {
+                            println!("[WARN] The certificate is from a later epoch.");
+                            is_valid = false;
+                        }
                         for cert in p.certificate.votes.iter() {
                             if cert.msg != hash {
                                 println!("[WARN] The hash of the certification does not match block.");
                                 is_valid = false;
                             }
-                            if !cx.pub_key_map.get(&cert.origin).unwrap().verify(&cert.msg, &cert.auth) {
+                            if !cx.pub_key_map.verify(&cert.origin, SignKind::Vote, p.certificate.epoch, &cert.msg, &cert.auth) {
                                 println!("[WARN] The auth of the certification does not match block.");
                                 is_valid = false;
                             }
                         }
-                        let commit_hash = crypto::hash::ser_and_hash(&p.new_block.body.data.commits);
-                        for cert in p.new_block.body.data.acks.iter() {
-                            if cert.msg != commit_hash {
-                                println!("[WARN] The hash of the certification does not match commit.");
-                                is_valid = false;
-                            }
-                            if !cx.pub_key_map.get(&cert.origin).unwrap().verify(&cert.msg, &cert.auth) {
-                                println!("[WARN] The auth of the certification does not match commit.");
-                                is_valid = false;
-                            }
+                        if !check_content(&cx, &p) {
+                            is_valid = false;
                         }
                         if is_valid {
                             cx.received_propose = Some(p);
@@ -248,18 +479,38 @@ pub async fn reactor(
                         }
                     },
                     ProtocolMsg::Vote(p) => {
-                        cx.received_vote.push(p);
-                        if cx.received_vote.len() == (cx.num_faults + 1) as usize {
-                            let certificate = Certificate {
-                                votes: cx.received_vote.clone(),
-                            };
-                            let sign = get_acc(&cx, &certificate).1;
-                            cx.net_send.send((cx.num_nodes, Arc::new(ProtocolMsg::VoteCert(certificate.clone(), sign.clone())))).unwrap();
-                            cx.received_certificate = Some(certificate);
-                            cx.received_certificate_sign = Some(sign);
-                            deliver_vote_cert(&mut cx, myid);
-                            phase = Phase::Commit;
-                            phase_end.as_mut().reset(time::Instant::now() + Duration::from_millis(delta * 2));
+                        let hash = cx.received_propose.as_ref().map(|propose| propose.new_block.hash.to_vec());
+                        let is_valid = if myid != cx.last_leader || hash.as_ref() != Some(&p.msg) {
+                            println!("[WARN] The vote from {} is not for our proposal.", p.origin);
+                            false
+                        } else if cx.received_vote.iter().any(|v| v.origin == p.origin) {
+                            println!("[WARN] Duplicate vote from {}.", p.origin);
+                            false
+                        } else if !cx.pub_key_map.verify(&p.origin, SignKind::Vote, cx.epoch, &p.msg, &p.auth) {
+                            println!("[WARN] Cannot verify the vote from {}.", p.origin);
+                            false
+                        } else {
+                            true
+                        };
+                        if is_valid {
+                            cx.received_vote.push(p);
+                            if cx.received_vote.len() == (cx.num_faults + 1) as usize {
+                                let certificate = Certificate {
+                                    votes: cx.received_vote.clone(),
+                                    epoch: cx.epoch,
+                                };
+                                match get_acc(&cx, SignKind::Certificate, &certificate) {
+                                    Ok((_, sign)) => {
+                                        cx.net_send.send((cx.broadcast, Arc::new(ProtocolMsg::VoteCert(certificate.clone(), sign.clone())))).unwrap();
+                                        cx.received_certificate = Some(certificate);
+                                        cx.received_certificate_sign = Some(sign);
+                                        deliver_vote_cert(&mut cx, myid);
+                                        phase = Phase::Commit;
+                                        phase_end.as_mut().reset(time::Instant::now() + Duration::from_millis(delta * 2));
+                                    }
+                                    Err(e) => println!("[WARN] Cannot sign the certificate: {}", e),
+                                }
+                            }
                         }
                     },
                     ProtocolMsg::VoteCert(c, z) => {
@@ -273,7 +524,7 @@ pub async fn reactor(
                         if !cx.propose_share_sent && n == myid {
                             cx.net_send
                                 .send((
-                                    cx.num_nodes,
+                                    cx.broadcast,
                                     Arc::new(ProtocolMsg::DeliverPropose(
                                         sh.clone(),
                                         myid,
@@ -283,13 +534,13 @@ pub async fn reactor(
                                 .unwrap();
                             cx.propose_share_sent = true;
                         }
-                        cx.propose_gatherer.add_share(sh, n, cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(), cx.pub_key_map.get(&cx.last_leader).unwrap(), z);
+                        cx.propose_gatherer.add_share(sh, n, cx.epoch, cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(), cx.pub_key_map.get(&cx.last_leader, cx.epoch).unwrap(), z);
                     }
                     ProtocolMsg::DeliverVoteCert(sh, n, z) => {
                         if !cx.vote_cert_share_sent && n == myid {
                             cx.net_send
                                 .send((
-                                    cx.num_nodes,
+                                    cx.broadcast,
                                     Arc::new(ProtocolMsg::DeliverVoteCert(
                                         sh.clone(),
                                         myid,
@@ -299,7 +550,7 @@ pub async fn reactor(
                                 .unwrap();
                             cx.vote_cert_share_sent = true;
                         }
-                        cx.vote_cert_gatherer.add_share(sh, n, cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(), cx.pub_key_map.get(&cx.last_leader).unwrap(), z);
+                        cx.vote_cert_gatherer.add_share(sh, n, cx.epoch, cx.accumulator_pub_params_map.get(&cx.last_leader).unwrap(), cx.pub_key_map.get(&cx.last_leader, cx.epoch).unwrap(), z);
                     }
                     ProtocolMsg::Reconstruct(sh, e) => {
                         let last = cx.reconstruct_queue.back();
@@ -308,13 +559,18 @@ pub async fn reactor(
                         }
                     }
                     ProtocolMsg::Commit(mut sh, c, z) => {
-                        let mut is_valid = true;
+                        let mut is_valid = check_commit(&cx, sender, &z);
                         let rng = &mut crypto::rand::rngs::StdRng::from_entropy();
+                        if c.len() != cx.num_nodes as usize || sh.len() < cx.num_nodes as usize {
+                            is_valid = false;
+                        }
                         for i in 0..cx.num_nodes as usize {
                             is_valid = is_valid && crypto::EVSS381::check(&cx.rand_beacon_parameter.get_public_params(), &c[i], &sh[i], rng).unwrap();
                         }
                         if is_valid {
-                            cx.rand_beacon_queue.get_mut(&cx.next_leader()).unwrap().append(&mut sh);
+                            let dealer = cx.next_leader();
+                            cx.dealt_commits.insert((dealer, cx.epoch + 1), commits_hash(cx.hash_alg, &c));
+                            cx.rand_beacon_queue.get_mut(&dealer).unwrap().append(&mut sh);
                             cx.received_commit = Some(c);
                             cx.received_commit_sign = Some(z);
                         } else {
@@ -325,7 +581,7 @@ pub async fn reactor(
                         if !cx.commit_share_sent && n == myid {
                             cx.net_send
                                 .send((
-                                    cx.num_nodes,
+                                    cx.broadcast,
                                     Arc::new(ProtocolMsg::DeliverCommit(
                                         sh.clone(),
                                         myid,
@@ -335,28 +591,86 @@ pub async fn reactor(
                                 .unwrap();
                             cx.commit_share_sent = true;
                         }
-                        cx.commit_gatherer.add_share(sh, n, cx.accumulator_pub_params_map.get(&cx.next_leader()).unwrap(), cx.pub_key_map.get(&cx.next_leader()).unwrap(), z);
-                        if cx.commit_gatherer.shard_num == cx.num_nodes - cx.num_faults {
-                            let reconstructed_commit = commit_from_bytes(&cx.commit_gatherer.reconstruct(cx.num_nodes, cx.num_faults).unwrap());
-                            let vote = Vote {
-                                msg: crypto::hash::ser_and_hash(&reconstructed_commit).to_vec(),
-                                origin: myid,
-                                auth: cx.my_secret_key.sign(&crypto::hash::ser_and_hash(&reconstructed_commit)).unwrap(),
-                            };
-                            if myid != cx.next_leader() {
-                                cx.net_send.send((cx.next_leader(), Arc::new(ProtocolMsg::Ack(vote)))).unwrap();
+                        cx.commit_gatherer.add_share(sh, n, cx.epoch, cx.accumulator_pub_params_map.get(&cx.next_leader()).unwrap(), cx.pub_key_map.get(&cx.next_leader(), cx.epoch).unwrap(), z);
+                        // Decoding is tried again with every shard until it settles
+                        if !cx.commit_decoded && cx.commit_gatherer.shard_num >= cx.num_nodes - cx.num_faults {
+                            match cx.commit_gatherer.reconstruct(cx.num_nodes, cx.num_faults) {
+                                Reconstructed::Data(bytes) => match try_commit_from_bytes(&bytes) {
+                                    Some(reconstructed_commit) => {
+                                        // The next leader proposes these commitments in the next epoch.
+                                        let hash = ack_hash(cx.hash_alg, cx.epoch + 1, &reconstructed_commit);
+                                        match cx.signer.sign(SignKind::Ack, cx.epoch, &hash) {
+                                            Ok(auth) => {
+                                                let vote = Vote {
+                                                    msg: hash.to_vec(),
+                                                    origin: myid,
+                                                    auth,
+                                                };
+                                                if myid != cx.next_leader() {
+                                                    cx.net_send.send((cx.next_leader(), Arc::new(ProtocolMsg::Ack(vote)))).unwrap();
+                                                } else if hash == ack_hash(cx.hash_alg, cx.epoch + 1, &cx.commits) {
+                                                    cx.received_ack.push(vote);
+                                                }
+                                            }
+                                            Err(e) => println!("[WARN] Cannot sign the ack: {}", e),
+                                        }
+                                        cx.commit_decoded = true;
+                                    }
+                                    None => {
+                                        println!("[WARN] Cannot decode the commitments.");
+                                        cx.commit_decoded = true;
+                                    }
+                                },
+                                Reconstructed::Wait => {}
+                                Reconstructed::BadEncoding(shards) => {
+                                    let dealer = cx.next_leader();
+                                    reject_dealer(&mut cx, dealer);
+                                    let proof = BadEncoding { dealer, kind: cx.commit_gatherer.kind, shards };
+                                    cx.net_send.send((cx.broadcast, Arc::new(ProtocolMsg::BadEncoding(proof)))).unwrap();
+                                }
                             }
                         }
                     }
+                    ProtocolMsg::BadEncoding(p) => {
+                        if check_bad_encoding(&cx, &p) {
+                            println!("[WARN] {} dealt a bad encoding.", p.dealer);
+                            reject_dealer(&mut cx, p.dealer);
+                        } else {
+                            println!("[WARN] Cannot verify the bad encoding proof against {}.", p.dealer);
+                        }
+                    }
                     ProtocolMsg::Ack(v) => {
-                        cx.received_ack.push(v);
+                        // Only the next leader collects acks, and only for what it dealt.
+                        if myid != cx.next_leader() {
+                            println!("[WARN] Received an ack while not being the next leader.");
+                        } else if v.msg != ack_hash(cx.hash_alg, cx.epoch + 1, &cx.commits) {
+                            println!("[WARN] The ack does not match the commitments.");
+                        } else if cx.received_ack.iter().any(|a| a.origin == v.origin) {
+                            println!("[WARN] Duplicate ack from {}.", v.origin);
+                        } else if !cx.pub_key_map.verify(&v.origin, SignKind::Ack, cx.epoch, &v.msg, &v.auth) {
+                            println!("[WARN] Cannot verify the ack from {}.", v.origin);
+                        } else {
+                            cx.received_ack.push(v);
+                        }
                     }
                 };
                 let time_after = time::Instant::now();
                 println!("{}: Message {:?} took {} ms.", myid, s, (time_after - time_before).as_millis());
             },
-            _tx_opt = cli_recv.recv() => {
+            tx_opt = cli_recv.recv() => {
                 // We received a message from the client
+                if let Some(r) = tx_opt.as_ref().and_then(SignedReconfig::from_tx) {
+                    if check_reconfig(&cx, &r, cx.epoch) {
+                        println!("{}: Received a reconfiguration for epoch {}.", myid, r.reconfig.epoch);
+                        cx.pending_reconfig = Some(r);
+                    }
+                }
+                if let Some(r) = tx_opt.as_ref().and_then(SignedKeyRotation::from_tx) {
+                    if check_rotation(&cx, &r, cx.epoch) {
+                        println!("{}: Received a key rotation of {} for epoch {}.", myid, r.rotation.replica, r.rotation.epoch);
+                        cx.pending_rotations.push(r);
+                    }
+                }
             },
             _ = &mut phase_end => {
                 let s = phase.to_string();
@@ -373,22 +687,45 @@ pub async fn reactor(
                         new_block.header.author = myid;
                         new_block.header.height = cx.highest_height + 1;
                         // TODO: Maybe add something to body?
-                        let content = Content {
-                            commits: cx.commits.clone(),
-                            acks: cx.received_ack.clone(),
+                        // Our commitments can only be proposed once a quorum has acknowledged them.
+                        let mut content = if cx.received_ack.len() >= (cx.num_faults + 1) as usize
+                            && cx.commits.len() == cx.num_nodes as usize
+                        {
+                            Content {
+                                commits: cx.commits.clone(),
+                                acks: cx.received_ack.clone(),
+                                reconfig: None,
+                                rotations: Vec::new(),
+                            }
+                        } else {
+                            // The blame follows once the block commits, as it does on every node.
+                            println!("[WARN] Only {} acks received, proposing without commitments.", cx.received_ack.len());
+                            Content::new()
                         };
+                        // What we propose stays pending until it commits
+                        cx.pending_reconfig = cx.pending_reconfig.take().filter(|r| check_reconfig(&cx, r, cx.epoch));
+                        if cx.scheduled_reconfig.is_none() {
+                            content.reconfig = cx.pending_reconfig.clone();
+                        }
+                        let rotations = std::mem::take(&mut cx.pending_rotations);
+                        cx.pending_rotations = rotations.into_iter().filter(|r| check_rotation(&cx, r, cx.epoch)).collect();
+                        content.rotations = cx.pending_rotations.clone();
                         new_block.body.data = content;
                         cx.received_ack.clear();
-                        new_block.update_hash();
+                        new_block.update_hash(cx.hash_alg);
                         let propose = Propose {
                             new_block: new_block,
                             certificate: cx.highest_cert.clone(),
                             epoch: cx.epoch,
                         };
-                        let sign = get_acc(&cx, &propose).1;
-                        cx.net_send.send((cx.num_nodes, Arc::new(ProtocolMsg::Propose(propose.clone(), sign.clone())))).unwrap();
-                        cx.received_propose = Some(propose);
-                        cx.received_propose_sign = Some(sign);
+                        match get_acc(&cx, SignKind::Propose, &propose) {
+                            Ok((_, sign)) => {
+                                cx.net_send.send((cx.broadcast, Arc::new(ProtocolMsg::Propose(propose.clone(), sign.clone())))).unwrap();
+                                cx.received_propose = Some(propose);
+                                cx.received_propose_sign = Some(sign);
+                            }
+                            Err(e) => println!("[WARN] Cannot sign the proposal: {}", e),
+                        }
                         phase = Phase::DeliverCommit;
                         phase_end.as_mut().reset(begin + Duration::from_millis(delta * 11 * (cx.epoch - 1) + delta * 8));
                     }
@@ -410,27 +747,66 @@ pub async fn reactor(
                         }
                     }
                     Phase::Vote => {
-                        let propose = Propose::from_bytes(&cx.propose_gatherer.reconstruct(cx.num_nodes, cx.num_faults).unwrap()[..]);
-                        let mut block = propose.new_block;
-                        block.update_hash();
-                        let vote = Vote {
-                            msg: block.hash.to_vec(),
-                            origin: myid,
-                            auth: cx.my_secret_key.sign(&block.hash).unwrap(),
-                        };
-                        cx.net_send.send((cx.last_leader, Arc::new(ProtocolMsg::Vote(vote)))).unwrap();
+                        match cx.propose_gatherer.reconstruct(cx.num_nodes, cx.num_faults) {
+                            _ if cx.bad_dealers.contains(&cx.last_leader) => {
+                                println!("[WARN] Not voting for the proposal of {}, which was encoded badly.", cx.last_leader);
+                            }
+                            Reconstructed::Data(bytes) => match Propose::try_from_bytes(&bytes[..]) {
+                                Some(propose) => {
+                                    let mut block = propose.new_block;
+                                    block.update_hash(cx.hash_alg);
+                                    match cx.signer.sign(SignKind::Vote, cx.epoch, &block.hash) {
+                                        Ok(auth) => {
+                                            let vote = Vote {
+                                                msg: block.hash.to_vec(),
+                                                origin: myid,
+                                                auth,
+                                            };
+                                            cx.net_send.send((cx.last_leader, Arc::new(ProtocolMsg::Vote(vote)))).unwrap();
+                                        }
+                                        Err(e) => println!("[WARN] Cannot sign the vote: {}", e),
+                                    }
+                                }
+                                None => println!("[WARN] Cannot decode the proposal."),
+                            },
+                            // Do not vote for what we could not see.
+                            Reconstructed::Wait => println!("[WARN] Too few shards of the proposal."),
+                            Reconstructed::BadEncoding(shards) => {
+                                let dealer = cx.last_leader;
+                                reject_dealer(&mut cx, dealer);
+                                let proof = BadEncoding { dealer, kind: cx.propose_gatherer.kind, shards };
+                                cx.net_send.send((cx.broadcast, Arc::new(ProtocolMsg::BadEncoding(proof)))).unwrap();
+                            }
+                        }
                         phase = Phase::End;
                         phase_end.as_mut().reset(begin + Duration::from_millis(delta * 11 * cx.epoch));
                     }
                     Phase::Commit => {
-                        let propose = Propose::from_bytes(&cx.propose_gatherer.reconstruct(cx.num_nodes, cx.num_faults).unwrap()[..]);
-                        let new_block = Arc::new(propose.new_block);
-                        cx.storage
-                            .committed_blocks_by_hash
-                            .insert(new_block.hash.clone(), Arc::clone(&new_block));
-                        cx.storage
-                            .committed_blocks_by_ht
-                            .insert(new_block.header.height, Arc::clone(&new_block));
+                        // A certified proposal decodes for every honest node, unless f+1
+                        // signatures were forged. Nothing is committed then.
+                        let propose = match cx.propose_gatherer.reconstruct(cx.num_nodes, cx.num_faults) {
+                            Reconstructed::Data(bytes) => Propose::try_from_bytes(&bytes[..]),
+                            _ => None,
+                        };
+                        match propose {
+                            Some(propose) => {
+                                // Every node commits the same block, so the schedule stays agreed upon.
+                                cx.committed = Some(Committed {
+                                    hash: propose.new_block.hash,
+                                    author: propose.new_block.header.author,
+                                    dealt: !propose.new_block.body.data.commits.is_empty(),
+                                });
+                                on_commit(&mut cx, &propose.new_block);
+                                let new_block = Arc::new(propose.new_block);
+                                cx.storage
+                                    .committed_blocks_by_hash
+                                    .insert(new_block.hash.clone(), Arc::clone(&new_block));
+                                cx.storage
+                                    .committed_blocks_by_ht
+                                    .insert(new_block.header.height, Arc::clone(&new_block));
+                            }
+                            None => println!("[WARN] Cannot reconstruct the certified proposal."),
+                        }
                         cx.received_propose = None;
                         cx.received_propose_sign = None;
                         cx.received_certificate = None;
@@ -439,28 +815,27 @@ pub async fn reactor(
                         phase_end.as_mut().reset(begin + Duration::from_millis(delta * 11 * cx.epoch));
                     }
                     Phase::End => {
-                        let mut vec = Vec::with_capacity(cx.num_nodes as usize);
-                        while !cx.reconstruct_queue.is_empty() && cx.reconstruct_queue.front().unwrap().1 < cx.epoch {
-                            cx.reconstruct_queue.pop_front();
-                        }
-                        while !cx.reconstruct_queue.is_empty() && cx.reconstruct_queue.front().unwrap().1 == cx.epoch {
-                            vec.push(cx.reconstruct_queue.pop_front().unwrap().0);
-                        }
-                        let mut hash = [0 as u8; 32];
-                        if vec.len() >= (cx.num_nodes - cx.num_faults) as usize {
-                            let mut buf = Vec::new();
-                            crypto::EVSS381::reconstruct(&vec).serialize(&mut buf).unwrap();
-                            hash = crypto::hash::ser_and_hash(&buf);
+                        end_epoch(&mut cx);
+                        if cx.scheduled_reconfig.as_ref().map_or(false, |r| r.epoch == cx.epoch + 1) {
+                            let r = cx.scheduled_reconfig.take().unwrap();
+                            cx.reconfigure(&r);
+                            println!("{}: Reconfigured to {} nodes, {} faults: {:?}", myid, r.num_nodes, r.num_faults, r.net_map);
+                            if myid as usize >= r.num_nodes {
+                                log::info!(target:"consensus", "Removed from the membership");
+                                return;
+                            }
                         }
-                        println!("Rand Beacon: {:x?}", hash);
                         cx.last_leader = cx.next_leader();
                         cx.epoch += 1;
+                        let epoch = cx.epoch;
+                        cx.dealt_commits.retain(|(_, e), _| *e >= epoch);
                         println!("{}: cx.epoch {}. Leader is {}.", myid, cx.epoch, cx.last_leader);
                         cx.propose_gatherer.clear();
                         cx.vote_cert_gatherer.clear();
                         cx.commit_gatherer.clear();
+                        cx.commit_decoded = false;
+                        cx.bad_dealers.clear();
                         cx.received_vote.clear();
-                        cx.received_ack.clear();
                         cx.propose_share_sent = false;
                         cx.vote_cert_share_sent = false;
                         cx.commit_share_sent = false;
@@ -471,17 +846,24 @@ pub async fn reactor(
                             phase = Phase::DeliverPropose;
                             phase_end.as_mut().reset(begin + Duration::from_millis(delta * 11 * (cx.epoch - 1) + delta * 7));
                             if myid == cx.next_leader() {
-                                cx.shards = cx.rand_beacon_shares[cx.epoch as usize % 100].0.clone();
-                                cx.commits = cx.rand_beacon_shares[cx.epoch as usize % 100].1.clone();
-                                let sign = get_acc(&cx, &cx.commits).1;
-                                cx.rand_beacon_queue.get_mut(&myid).unwrap().append(&mut cx.shards[myid as usize].clone());
-                                for i in 0..cx.num_nodes {
-                                    if myid != i {
-                                        cx.net_send.send((i, Arc::new(ProtocolMsg::Commit(cx.shards[i as usize].clone(), cx.commits.clone(), sign.clone())))).unwrap();
+                                let (shards, commits) = next_dealing(&cx);
+                                cx.shards = Secret::new(shards);
+                                cx.commits = commits;
+                                // Acks for the new commitments are gathered until we propose.
+                                cx.received_ack.clear();
+                                match get_acc(&cx, SignKind::Commit, &cx.commits) {
+                                    Ok((_, sign)) => {
+                                        cx.rand_beacon_queue.get_mut(&myid).unwrap().append(&mut cx.shards[myid as usize]);
+                                        for i in 0..cx.num_nodes {
+                                            if myid != i {
+                                                cx.net_send.send((i, Arc::new(ProtocolMsg::Commit(cx.shards[i as usize].clone(), cx.commits.clone(), sign.clone())))).unwrap();
+                                            }
+                                        }
+                                        cx.received_commit = Some(cx.commits.clone());
+                                        cx.received_commit_sign = Some(sign);
                                     }
+                                    Err(e) => println!("[WARN] Cannot sign the commitments: {}", e),
                                 }
-                                cx.received_commit = Some(cx.commits.clone());
-                                cx.received_commit_sign = Some(sign);
                             }
                         } else {
                             phase = Phase::Propose;
@@ -500,14 +882,15 @@ pub async fn reactor(
                         for i in 0..cx.num_nodes {
                             let shard = cx.rand_beacon_queue.get_mut(&(i as Replica)).unwrap().pop_front();
                             if shard.is_some() {
-                                let u = shard.unwrap();
+                                let mut u = shard.unwrap();
                                 sum.point += u.point;
                                 sum.value += u.value;
                                 sum.challenge += u.challenge;
                                 sum.proof.w += &u.proof.w;
+                                u.wipe();
                             }
                         }
-                        cx.net_send.send((cx.num_nodes, Arc::new(ProtocolMsg::Reconstruct(sum, cx.epoch)))).unwrap();
+                        cx.net_send.send((cx.broadcast, Arc::new(ProtocolMsg::Reconstruct(sum, cx.epoch)))).unwrap();
                     }
                 };
                 let time_after = time::Instant::now();
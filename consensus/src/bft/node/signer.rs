@@ -0,0 +1,540 @@
+//! Signing with the key of the node, either in-process or through a signing
+//! daemon on a Unix socket.
+//!
+//! The daemon keeps the key out of the node and refuses to sign two different
+//! messages of the same kind for one epoch, or anything for an epoch older
+//! than one it already signed for. So even a compromised node cannot make its
+//! key equivocate. Either way the key signs the `sign_digest` of the kind,
+//! epoch and message, so a signature cannot be passed off as one of another
+//! kind or epoch than the one the guard let through.
+
+use crypto::scheme::SigningKey;
+use crypto::secret::{SecretBox, SecretBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use types::{sign_digest, Height};
+
+pub use types::SignKind;
+
+/// How long the node waits for the daemon
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The largest frame either side accepts
+const MAX_FRAME: usize = 1 << 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignRequest {
+    pub kind: SignKind,
+    pub epoch: Height,
+    pub msg: Vec<u8>,
+}
+
+/// Why the daemon did not sign
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// A different message of this kind was signed for the epoch
+    Conflict,
+    /// A message of this kind was signed for the later epoch `last`
+    Stale { last: Height },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum SignResponse {
+    Signature(Vec<u8>),
+    Rotated,
+    Refused(Refusal),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub enum SignerError {
+    Refused(SignKind, Height, Refusal),
+    Io(std::io::Error),
+    Format(bincode::Error),
+    /// The key could not sign
+    Failed(String),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::Refused(kind, epoch, Refusal::Conflict) => {
+                write!(f, "refused to sign a second {:?} for epoch {}", kind, epoch)
+            }
+            SignerError::Refused(kind, epoch, Refusal::Stale { last }) => write!(
+                f,
+                "refused to sign a {:?} for epoch {} after epoch {}",
+                kind, epoch, last
+            ),
+            SignerError::Io(e) => write!(f, "signer io error: {}", e),
+            SignerError::Format(e) => write!(f, "invalid signer message: {}", e),
+            SignerError::Failed(e) => write!(f, "failed to sign: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+impl From<std::io::Error> for SignerError {
+    fn from(e: std::io::Error) -> Self {
+        SignerError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SignerError {
+    fn from(e: bincode::Error) -> Self {
+        SignerError::Format(e)
+    }
+}
+
+pub trait Signer: Send {
+    fn sign(&self, kind: SignKind, epoch: Height, msg: &[u8]) -> Result<Vec<u8>, SignerError>;
+
+    /// Signs with the key that goes with `new_pk` from `epoch` on, once a
+    /// rotation to it is committed
+    fn rotate(&mut self, epoch: Height, new_pk: &[u8]) -> Result<(), SignerError>;
+}
+
+pub fn decode_keypair(alg: &crypto::Algorithm, secret_key_bytes: &[u8]) -> Box<dyn SigningKey> {
+    crypto::scheme::lookup(alg)
+        .and_then(|s| s.decode_sk(secret_key_bytes))
+        .expect("Failed to decode the secret key from the config")
+}
+
+/// Signs with a key held by the node itself. The decoded keys are
+/// overwritten once they are dropped.
+pub struct LocalSigner {
+    alg: crypto::Algorithm,
+    /// The keys ordered by the epoch they are used from
+    keys: Vec<(Height, SecretBox<dyn SigningKey>)>,
+    /// Keys the node may rotate to, which are only used once a rotation to
+    /// them is committed
+    spare: Vec<SecretBox<dyn SigningKey>>,
+}
+
+impl LocalSigner {
+    pub fn new(alg: &crypto::Algorithm, key: Box<dyn SigningKey>) -> Self {
+        LocalSigner {
+            alg: alg.clone(),
+            keys: vec![(0, SecretBox::new(key))],
+            spare: Vec::new(),
+        }
+    }
+
+    /// Decodes the key of the node and the keys it may rotate to. The epochs
+    /// the keystore has for them are not used: a key takes over from the
+    /// epoch of the committed rotation to it.
+    pub fn from_keys(
+        alg: &crypto::Algorithm,
+        secret_key_bytes: &[u8],
+        rotated_keys: &[(Height, SecretBytes)],
+    ) -> Self {
+        let mut signer = LocalSigner::new(alg, decode_keypair(alg, secret_key_bytes));
+        signer.spare = rotated_keys
+            .iter()
+            .map(|(_, key)| SecretBox::new(decode_keypair(alg, key)))
+            .collect();
+        signer
+    }
+
+    /// Signs with `key` from `epoch` on
+    pub fn with_key_from(mut self, epoch: Height, key: Box<dyn SigningKey>) -> Self {
+        self.use_from(epoch, SecretBox::new(key));
+        self
+    }
+
+    fn use_from(&mut self, epoch: Height, key: SecretBox<dyn SigningKey>) {
+        let i = self.keys.iter().take_while(|(e, _)| *e < epoch).count();
+        self.keys.truncate(i);
+        self.keys.push((epoch, key));
+    }
+
+    fn key(&self, epoch: Height) -> &dyn SigningKey {
+        let i = self.keys.iter().take_while(|(e, _)| *e <= epoch).count();
+        &*self.keys[i.max(1) - 1].1
+    }
+}
+
+impl Signer for LocalSigner {
+    fn sign(&self, kind: SignKind, epoch: Height, msg: &[u8]) -> Result<Vec<u8>, SignerError> {
+        self.key(epoch)
+            .sign(&sign_digest(kind, epoch, msg))
+            .map_err(|e| SignerError::Failed(e.to_string()))
+    }
+
+    fn rotate(&mut self, epoch: Height, new_pk: &[u8]) -> Result<(), SignerError> {
+        let pk = crypto::scheme::lookup(&self.alg)
+            .and_then(|s| s.decode_pk(new_pk))
+            .map_err(|e| SignerError::Failed(e.to_string()))?;
+        // The key that goes with `pk` is the one whose signatures it verifies
+        let probe = sign_digest(SignKind::Rotate, epoch, new_pk);
+        let i = self
+            .spare
+            .iter()
+            .position(|key| matches!(key.sign(&probe), Ok(sig) if pk.verify(&probe, &sig)))
+            .ok_or_else(|| SignerError::Failed("no key for the rotation".to_string()))?;
+        let key = self.spare.remove(i);
+        self.use_from(epoch, key);
+        Ok(())
+    }
+}
+
+fn write_frame<T: Serialize>(stream: &mut UnixStream, msg: &T) -> Result<(), SignerError> {
+    let bytes = bincode::serialize(msg)?;
+    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    stream.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_frame<T: for<'de> Deserialize<'de>>(stream: &mut UnixStream) -> Result<T, SignerError> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(SignerError::Failed(format!("frame of {} bytes", len)));
+    }
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf)?;
+    Ok(bincode::deserialize(&buf)?)
+}
+
+/// Runs `f`, which blocks on the daemon or on a lock. On a multi-threaded
+/// runtime the other tasks of the worker move to another thread meanwhile.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(h) if h.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+/// Signs through a signing daemon listening on a Unix socket
+pub struct RemoteSigner {
+    path: PathBuf,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl RemoteSigner {
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self, SignerError> {
+        let path = path.as_ref().to_path_buf();
+        let stream = RemoteSigner::open(&path)?;
+        Ok(RemoteSigner {
+            path,
+            stream: Mutex::new(Some(stream)),
+        })
+    }
+
+    fn open(path: &Path) -> Result<UnixStream, SignerError> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(stream)
+    }
+
+    fn request(stream: &mut UnixStream, req: &SignRequest) -> Result<SignResponse, SignerError> {
+        write_frame(stream, req)?;
+        read_frame(stream)
+    }
+}
+
+impl RemoteSigner {
+    fn call(&self, kind: SignKind, epoch: Height, msg: &[u8]) -> Result<SignResponse, SignerError> {
+        let req = SignRequest {
+            kind,
+            epoch,
+            msg: msg.to_vec(),
+        };
+        let resp = blocking(|| {
+            let mut stream = self.stream.lock().unwrap();
+            if stream.is_none() {
+                *stream = Some(RemoteSigner::open(&self.path)?);
+            }
+            let resp = RemoteSigner::request(stream.as_mut().unwrap(), &req);
+            // A broken connection is opened again on the next request
+            if resp.is_err() {
+                *stream = None;
+            }
+            resp
+        });
+        match resp? {
+            SignResponse::Refused(r) => Err(SignerError::Refused(kind, epoch, r)),
+            SignResponse::Failed(e) => Err(SignerError::Failed(e)),
+            resp => Ok(resp),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn sign(&self, kind: SignKind, epoch: Height, msg: &[u8]) -> Result<Vec<u8>, SignerError> {
+        match self.call(kind, epoch, msg)? {
+            SignResponse::Signature(sig) => Ok(sig),
+            resp => Err(SignerError::Failed(format!(
+                "unexpected response {:?}",
+                resp
+            ))),
+        }
+    }
+
+    fn rotate(&mut self, epoch: Height, new_pk: &[u8]) -> Result<(), SignerError> {
+        match self.call(SignKind::Rotate, epoch, new_pk)? {
+            SignResponse::Rotated => Ok(()),
+            resp => Err(SignerError::Failed(format!(
+                "unexpected response {:?}",
+                resp
+            ))),
+        }
+    }
+}
+
+/// A signer the reactor shares with the transport, which signs the hello of
+/// every link it dials
+#[derive(Clone)]
+pub struct SharedSigner(Arc<Mutex<Box<dyn Signer>>>);
+
+impl SharedSigner {
+    pub fn new(signer: Box<dyn Signer>) -> Self {
+        SharedSigner(Arc::new(Mutex::new(signer)))
+    }
+}
+
+impl Signer for SharedSigner {
+    fn sign(&self, kind: SignKind, epoch: Height, msg: &[u8]) -> Result<Vec<u8>, SignerError> {
+        blocking(|| self.0.lock().unwrap().sign(kind, epoch, msg))
+    }
+
+    fn rotate(&mut self, epoch: Height, new_pk: &[u8]) -> Result<(), SignerError> {
+        blocking(|| self.0.lock().unwrap().rotate(epoch, new_pk))
+    }
+}
+
+/// The last message the daemon signed of every kind
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SignGuard {
+    last: HashMap<SignKind, (Height, Vec<u8>)>,
+}
+
+impl SignGuard {
+    pub fn load(path: &Path) -> Result<Self, SignerError> {
+        if !path.exists() {
+            return Ok(SignGuard::default());
+        }
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        Ok(bincode::deserialize(&buf)?)
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), SignerError> {
+        // Write a new file and move it over the old one, so that a crash
+        // never leaves a partial state behind
+        let tmp = path.with_extension("tmp");
+        let mut f = File::create(&tmp)?;
+        f.write_all(&bincode::serialize(self)?)?;
+        f.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Records the request if it does not conflict with what was signed
+    /// before. Signing the same message again is allowed. A hello commits to
+    /// nothing and is signed for every link, so it is not recorded.
+    pub fn check(&mut self, req: &SignRequest) -> Result<(), Refusal> {
+        if req.kind == SignKind::Hello {
+            return Ok(());
+        }
+        if let Some((epoch, msg)) = self.last.get(&req.kind) {
+            if *epoch > req.epoch {
+                return Err(Refusal::Stale { last: *epoch });
+            }
+            if *epoch == req.epoch && *msg != req.msg {
+                return Err(Refusal::Conflict);
+            }
+        }
+        self.last.insert(req.kind, (req.epoch, req.msg.clone()));
+        Ok(())
+    }
+}
+
+/// Runs the signing daemon. The state of the guard is written to `state`
+/// before every signature leaves the daemon.
+pub fn serve(
+    listener: UnixListener,
+    mut key: LocalSigner,
+    mut guard: SignGuard,
+    state: Option<&Path>,
+) -> Result<(), SignerError> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        loop {
+            let req: SignRequest = match read_frame(&mut stream) {
+                Ok(req) => req,
+                // The node went away
+                Err(SignerError::Io(_)) => break,
+                Err(e) => {
+                    println!("[WARN] Invalid sign request: {}", e);
+                    break;
+                }
+            };
+            let resp = match guard.check(&req) {
+                Err(r) => {
+                    println!(
+                        "[WARN] Refused to sign a {:?} for epoch {}: {:?}",
+                        req.kind, req.epoch, r
+                    );
+                    SignResponse::Refused(r)
+                }
+                Ok(()) => {
+                    if let Some(path) = state {
+                        guard.store(path)?;
+                    }
+                    let result = match req.kind {
+                        SignKind::Rotate => key
+                            .rotate(req.epoch, &req.msg)
+                            .map(|()| SignResponse::Rotated),
+                        kind => key
+                            .sign(kind, req.epoch, &req.msg)
+                            .map(SignResponse::Signature),
+                    };
+                    match result {
+                        Ok(resp) => resp,
+                        Err(SignerError::Failed(e)) => SignResponse::Failed(e),
+                        Err(e) => SignResponse::Failed(e.to_string()),
+                    }
+                }
+            };
+            if write_frame(&mut stream, &resp).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(kind: SignKind, epoch: Height, msg: &[u8]) -> SignRequest {
+        SignRequest {
+            kind,
+            epoch,
+            msg: msg.to_vec(),
+        }
+    }
+
+    #[test]
+    fn guard() {
+        let mut g = SignGuard::default();
+        assert!(g.check(&req(SignKind::Vote, 3, b"a")).is_ok());
+        assert!(g.check(&req(SignKind::Vote, 3, b"a")).is_ok());
+        assert_eq!(
+            g.check(&req(SignKind::Vote, 3, b"b")),
+            Err(Refusal::Conflict)
+        );
+        assert_eq!(
+            g.check(&req(SignKind::Vote, 2, b"c")),
+            Err(Refusal::Stale { last: 3 })
+        );
+        // Kinds are independent
+        assert!(g.check(&req(SignKind::Propose, 3, b"b")).is_ok());
+        assert!(g.check(&req(SignKind::Vote, 4, b"b")).is_ok());
+        // Hellos are never refused, nor kept in the state
+        assert!(g.check(&req(SignKind::Hello, 0, b"a")).is_ok());
+        assert!(g.check(&req(SignKind::Hello, 0, b"b")).is_ok());
+        assert!(!g.last.contains_key(&SignKind::Hello));
+    }
+
+    #[test]
+    fn remote() {
+        let path = std::env::temp_dir().join(format!("signer-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let scheme = crypto::scheme::lookup(&crypto::Algorithm::ED25519).unwrap();
+        let (pk, sk) = scheme.generate().unwrap();
+        let pk = scheme.decode_pk(&pk).unwrap();
+        let key = scheme.decode_sk(&sk).unwrap();
+        let key = LocalSigner::new(&crypto::Algorithm::ED25519, key);
+        std::thread::spawn(move || serve(listener, key, SignGuard::default(), None));
+
+        let signer = RemoteSigner::connect(&path).unwrap();
+        let sig = signer.sign(SignKind::Vote, 1, b"block").unwrap();
+        assert!(pk.verify(&sign_digest(SignKind::Vote, 1, b"block"), &sig));
+        // The signature is bound to the kind and epoch the guard checked
+        assert!(!pk.verify(&sign_digest(SignKind::Ack, 1, b"block"), &sig));
+        assert!(!pk.verify(&sign_digest(SignKind::Vote, 2, b"block"), &sig));
+        assert!(!pk.verify(b"block", &sig));
+        assert!(signer.sign(SignKind::Vote, 1, b"block").is_ok());
+        assert!(matches!(
+            signer.sign(SignKind::Vote, 1, b"other"),
+            Err(SignerError::Refused(SignKind::Vote, 1, Refusal::Conflict))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn shared_in_runtime() {
+        let scheme = crypto::scheme::lookup(&crypto::Algorithm::ED25519).unwrap();
+        let (pk, sk) = scheme.generate().unwrap();
+        let pk = scheme.decode_pk(&pk).unwrap();
+        let key = LocalSigner::new(&crypto::Algorithm::ED25519, scheme.decode_sk(&sk).unwrap());
+        let signer = SharedSigner::new(Box::new(key));
+        // Signing works from the tasks of both kinds of runtime
+        let multi = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+        let single = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for rt in &[multi, single] {
+            let signer = signer.clone();
+            let sig = rt
+                .block_on(rt.spawn(async move { signer.sign(SignKind::Vote, 1, b"block") }))
+                .unwrap()
+                .unwrap();
+            assert!(pk.verify(&sign_digest(SignKind::Vote, 1, b"block"), &sig));
+        }
+    }
+
+    #[test]
+    fn rotated_key() {
+        let scheme = crypto::scheme::lookup(&crypto::Algorithm::ED25519).unwrap();
+        let (old_pk, old_sk) = scheme.generate().unwrap();
+        let (new_pk, new_sk) = scheme.generate().unwrap();
+        let signer = LocalSigner::new(
+            &crypto::Algorithm::ED25519,
+            scheme.decode_sk(&old_sk).unwrap(),
+        )
+        .with_key_from(5, scheme.decode_sk(&new_sk).unwrap());
+        let old_pk = scheme.decode_pk(&old_pk).unwrap();
+        let new_pk = scheme.decode_pk(&new_pk).unwrap();
+        let sig = signer.sign(SignKind::Vote, 4, b"block").unwrap();
+        assert!(old_pk.verify(&sign_digest(SignKind::Vote, 4, b"block"), &sig));
+        let sig = signer.sign(SignKind::Vote, 5, b"block").unwrap();
+        assert!(new_pk.verify(&sign_digest(SignKind::Vote, 5, b"block"), &sig));
+    }
+
+    #[test]
+    fn committed_rotation() {
+        let alg = crypto::Algorithm::ED25519;
+        let scheme = crypto::scheme::lookup(&alg).unwrap();
+        let (old_pk, old_sk) = scheme.generate().unwrap();
+        let (new_pk, new_sk) = scheme.generate().unwrap();
+        let (other_pk, _) = scheme.generate().unwrap();
+        // The keystore asks for epoch 3, but only the committed rotation counts
+        let mut signer = LocalSigner::from_keys(&alg, &old_sk, &[(3, SecretBytes::new(new_sk))]);
+        let old = scheme.decode_pk(&old_pk).unwrap();
+        let new = scheme.decode_pk(&new_pk).unwrap();
+        let sig = signer.sign(SignKind::Vote, 4, b"block").unwrap();
+        assert!(old.verify(&sign_digest(SignKind::Vote, 4, b"block"), &sig));
+
+        assert!(signer.rotate(6, &other_pk).is_err());
+        signer.rotate(6, &new_pk).unwrap();
+        let sig = signer.sign(SignKind::Vote, 5, b"block").unwrap();
+        assert!(old.verify(&sign_digest(SignKind::Vote, 5, b"block"), &sig));
+        let sig = signer.sign(SignKind::Vote, 6, b"block").unwrap();
+        assert!(new.verify(&sign_digest(SignKind::Vote, 6, b"block"), &sig));
+    }
+}
@@ -0,0 +1,296 @@
+//! Reed–Solomon erasure coding for delivering data in shards.
+//!
+//! `n` nodes get one shard each, and any `n - f` shards are enough to get the
+//! data back. The length of the data is written in front of it, so the
+//! padding can be of any size. GF(2^8) allows at most 256 shards, so larger
+//! systems are coded over GF(2^16).
+
+use reed_solomon_erasure::{galois_16, galois_8};
+use std::fmt;
+
+/// The size of the length header in front of the data
+const HEADER_SIZE: usize = 8;
+/// The most shards GF(2^8) supports
+const GF8_MAX_SHARDS: usize = 256;
+/// The most shards GF(2^16) supports
+const GF16_MAX_SHARDS: usize = 65536;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErasureError {
+    /// There has to be at least one data shard, and not too many shards
+    InvalidParams {
+        num_nodes: usize,
+        num_faults: usize,
+    },
+    /// There has to be a slot for every node
+    WrongShardCount {
+        expected: usize,
+        got: usize,
+    },
+    TooFewShards {
+        needed: usize,
+        got: usize,
+    },
+    /// The shards have different or invalid sizes
+    ShardSize,
+    /// The length header does not fit the decoded data
+    BadHeader,
+    /// The shards are not all part of the same encoding
+    Inconsistent,
+    Codec(reed_solomon_erasure::Error),
+}
+
+impl fmt::Display for ErasureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErasureError::InvalidParams {
+                num_nodes,
+                num_faults,
+            } => write!(
+                f,
+                "cannot code for {} nodes with {} faults",
+                num_nodes, num_faults
+            ),
+            ErasureError::WrongShardCount { expected, got } => {
+                write!(f, "expected {} shards, got {}", expected, got)
+            }
+            ErasureError::TooFewShards { needed, got } => {
+                write!(f, "needed {} shards, got {}", needed, got)
+            }
+            ErasureError::ShardSize => write!(f, "the shards have invalid sizes"),
+            ErasureError::BadHeader => write!(f, "the length header is corrupted"),
+            ErasureError::Inconsistent => write!(f, "the shards are inconsistent"),
+            ErasureError::Codec(e) => write!(f, "reed-solomon error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ErasureError {}
+
+impl From<reed_solomon_erasure::Error> for ErasureError {
+    fn from(e: reed_solomon_erasure::Error) -> Self {
+        ErasureError::Codec(e)
+    }
+}
+
+fn check_params(num_nodes: usize, num_faults: usize) -> Result<(), ErasureError> {
+    if num_faults >= num_nodes || num_nodes > GF16_MAX_SHARDS {
+        return Err(ErasureError::InvalidParams {
+            num_nodes,
+            num_faults,
+        });
+    }
+    Ok(())
+}
+
+fn is_wide(num_nodes: usize) -> bool {
+    num_nodes > GF8_MAX_SHARDS
+}
+
+fn to_symbols(shard: &[u8]) -> Vec<[u8; 2]> {
+    shard.chunks_exact(2).map(|c| [c[0], c[1]]).collect()
+}
+
+fn from_symbols(shard: Vec<[u8; 2]>) -> Vec<u8> {
+    shard.into_iter().flat_map(|s| s.to_vec()).collect()
+}
+
+/// Splits `data` into `num_nodes` shards, any `num_nodes - num_faults` of
+/// which can rebuild it
+pub fn encode(
+    data: &[u8],
+    num_nodes: usize,
+    num_faults: usize,
+) -> Result<Vec<Vec<u8>>, ErasureError> {
+    check_params(num_nodes, num_faults)?;
+    let num_data_shards = num_nodes - num_faults;
+    let total = HEADER_SIZE + data.len();
+    let mut shard_size = total.div_ceil(num_data_shards);
+    // GF(2^16) works on two bytes at a time
+    if is_wide(num_nodes) && shard_size % 2 == 1 {
+        shard_size += 1;
+    }
+    let mut padded = Vec::with_capacity(shard_size * num_nodes);
+    padded.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    padded.extend_from_slice(data);
+    padded.resize(shard_size * num_nodes, 0);
+    let mut shards: Vec<Vec<u8>> = padded.chunks(shard_size).map(|c| c.to_vec()).collect();
+    if num_faults == 0 {
+        return Ok(shards);
+    }
+    if is_wide(num_nodes) {
+        let r = galois_16::ReedSolomon::new(num_data_shards, num_faults)?;
+        let mut symbols: Vec<Vec<[u8; 2]>> = shards.iter().map(|s| to_symbols(s)).collect();
+        r.encode(&mut symbols)?;
+        shards = symbols.into_iter().map(from_symbols).collect();
+    } else {
+        let r = galois_8::ReedSolomon::new(num_data_shards, num_faults)?;
+        r.encode(&mut shards)?;
+    }
+    Ok(shards)
+}
+
+/// Rebuilds the data from the shards that arrived. `shards[i]` is the shard
+/// of node `i`.
+pub fn decode(
+    mut shards: Vec<Option<Vec<u8>>>,
+    num_nodes: usize,
+    num_faults: usize,
+) -> Result<Vec<u8>, ErasureError> {
+    check_params(num_nodes, num_faults)?;
+    if shards.len() != num_nodes {
+        return Err(ErasureError::WrongShardCount {
+            expected: num_nodes,
+            got: shards.len(),
+        });
+    }
+    let num_data_shards = num_nodes - num_faults;
+    let got = shards.iter().filter(|s| s.is_some()).count();
+    if got < num_data_shards {
+        return Err(ErasureError::TooFewShards {
+            needed: num_data_shards,
+            got,
+        });
+    }
+    let shard_size = shards.iter().flatten().next().unwrap().len();
+    if shard_size == 0
+        || (is_wide(num_nodes) && shard_size % 2 == 1)
+        || shards.iter().flatten().any(|s| s.len() != shard_size)
+    {
+        return Err(ErasureError::ShardSize);
+    }
+    if num_faults > 0 {
+        if is_wide(num_nodes) {
+            let r = galois_16::ReedSolomon::new(num_data_shards, num_faults)?;
+            let mut symbols: Vec<Option<Vec<[u8; 2]>>> = shards
+                .iter()
+                .map(|s| s.as_ref().map(|s| to_symbols(s)))
+                .collect();
+            r.reconstruct_data(&mut symbols)?;
+            shards = symbols.into_iter().map(|s| s.map(from_symbols)).collect();
+        } else {
+            let r = galois_8::ReedSolomon::new(num_data_shards, num_faults)?;
+            r.reconstruct_data(&mut shards)?;
+        }
+    }
+    let mut result = Vec::with_capacity(num_data_shards * shard_size);
+    for shard in shards.into_iter().take(num_data_shards) {
+        result.append(&mut shard.unwrap());
+    }
+    if result.len() < HEADER_SIZE {
+        return Err(ErasureError::BadHeader);
+    }
+    let mut header = [0u8; HEADER_SIZE];
+    header.copy_from_slice(&result[..HEADER_SIZE]);
+    let len = u64::from_le_bytes(header);
+    if len > (result.len() - HEADER_SIZE) as u64 {
+        return Err(ErasureError::BadHeader);
+    }
+    result.truncate(HEADER_SIZE + len as usize);
+    Ok(result.split_off(HEADER_SIZE))
+}
+
+/// Decodes the shards and checks that encoding the data again gives back
+/// every shard that arrived. Returns the data along with all the shards.
+pub fn decode_checked(
+    shards: Vec<Option<Vec<u8>>>,
+    num_nodes: usize,
+    num_faults: usize,
+) -> Result<(Vec<u8>, Vec<Vec<u8>>), ErasureError> {
+    let data = decode(shards.clone(), num_nodes, num_faults)?;
+    let encoded = encode(&data, num_nodes, num_faults)?;
+    let matches = shards
+        .iter()
+        .zip(encoded.iter())
+        .all(|(s, e)| s.iter().all(|s| s == e));
+    if !matches {
+        return Err(ErasureError::Inconsistent);
+    }
+    Ok((data, encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn erase(shards: &[Vec<u8>], erased: &[usize]) -> Vec<Option<Vec<u8>>> {
+        shards
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                if erased.contains(&i) {
+                    None
+                } else {
+                    Some(s.clone())
+                }
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(
+            data in proptest::collection::vec(any::<u8>(), 0..2048),
+            num_nodes in 1usize..40,
+            faults in any::<proptest::sample::Index>(),
+            seed in any::<u64>(),
+        ) {
+            let num_faults = faults.index(num_nodes);
+            let shards = encode(&data, num_nodes, num_faults).unwrap();
+            prop_assert_eq!(shards.len(), num_nodes);
+            // Drop `num_faults` shards, picked by the seed
+            let erased: Vec<usize> = (0..num_faults)
+                .map(|i| (seed as usize).wrapping_add(i * 7) % num_nodes)
+                .collect();
+            prop_assert_eq!(decode(erase(&shards, &erased), num_nodes, num_faults).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn large_padding() {
+        // Used to store the padding in a byte
+        let data = vec![7u8; 10];
+        let shards = encode(&data, 200, 50).unwrap();
+        let erased: Vec<usize> = (0..50).collect();
+        assert_eq!(decode(erase(&shards, &erased), 200, 50).unwrap(), data);
+    }
+
+    #[test]
+    fn wide() {
+        let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let shards = encode(&data, 301, 100).unwrap();
+        let erased: Vec<usize> = (100..200).collect();
+        assert_eq!(decode(erase(&shards, &erased), 301, 100).unwrap(), data);
+    }
+
+    #[test]
+    fn inconsistent() {
+        let data = vec![3u8; 100];
+        let mut shards = encode(&data, 7, 3).unwrap();
+        let (decoded, encoded) = decode_checked(erase(&shards, &[]), 7, 3).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(encoded, shards);
+        // A corrupted parity shard goes unnoticed by a plain decode
+        shards[6][0] ^= 1;
+        assert_eq!(decode(erase(&shards, &[]), 7, 3).unwrap(), data);
+        assert_eq!(
+            decode_checked(erase(&shards, &[]), 7, 3),
+            Err(ErasureError::Inconsistent)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(encode(&[1, 2, 3], 3, 3).is_err());
+        let shards = encode(&[1, 2, 3], 4, 1).unwrap();
+        assert_eq!(
+            decode(erase(&shards, &[0, 1]), 4, 1),
+            Err(ErasureError::TooFewShards { needed: 3, got: 2 })
+        );
+        let mut short = erase(&shards, &[0]);
+        short[1].as_mut().unwrap().pop();
+        assert_eq!(decode(short, 4, 1), Err(ErasureError::ShardSize));
+        assert!(decode(erase(&shards, &[]), 5, 1).is_err());
+    }
+}
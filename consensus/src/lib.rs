@@ -2,6 +2,7 @@ use crypto::hash::Hash;
 use std::{collections::HashMap, time::SystemTime};
 
 pub mod bft;
+pub mod erasure;
 
 pub fn statistics(
     now: SystemTime,
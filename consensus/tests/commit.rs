@@ -0,0 +1,42 @@
+extern crate consensus;
+use consensus::bft::node::accumulator::get_acc;
+use consensus::bft::node::context::Context;
+use consensus::bft::node::signer::{LocalSigner, SignKind};
+use consensus::bft::node::{check_commit, next_dealing};
+use crypto::Algorithm;
+use tokio::sync::mpsc::unbounded_channel;
+use util::peers::channel;
+
+const SEED: u64 = 42;
+
+fn context(config: &mut config::Node) -> Context {
+    let signer = LocalSigner::from_keys(&Algorithm::ED25519, &config.secret_key_bytes, &[]);
+    let (net_send, _) = channel();
+    let (cli_send, _) = unbounded_channel();
+    Context::new(config, Box::new(signer), net_send, cli_send)
+}
+
+#[test]
+fn forged_commit() {
+    let mut nodes = genconfig::generate_testnet(3, 1, SEED).nodes;
+    let cx = context(&mut nodes[0]);
+    let leader = cx.next_leader();
+    let leader_cx = context(&mut nodes[leader as usize]);
+    let other = (leader + 1) % 3;
+    let other_cx = context(&mut nodes[other as usize]);
+
+    let commits = next_dealing(&leader_cx).1;
+    let (_, z) = get_acc(&leader_cx, SignKind::Commit, &commits).unwrap();
+    assert!(check_commit(&cx, leader, &z));
+
+    // Another replica cannot pass its own commitments off as the leader's,
+    // nor relay those of the leader
+    let (_, forged) = get_acc(&other_cx, SignKind::Commit, &commits).unwrap();
+    assert!(!check_commit(&cx, other, &forged));
+    assert!(!check_commit(&cx, leader, &forged));
+    assert!(!check_commit(&cx, other, &z));
+
+    // A signature for another kind does not pass either
+    let (_, propose) = get_acc(&leader_cx, SignKind::Propose, &commits).unwrap();
+    assert!(!check_commit(&cx, leader, &propose));
+}
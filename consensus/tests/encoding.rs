@@ -0,0 +1,61 @@
+extern crate consensus;
+use consensus::bft::node::accumulator::{get_sign, to_shards, Reconstructed};
+use consensus::bft::node::check_bad_encoding;
+use consensus::bft::node::context::Context;
+use consensus::bft::node::signer::{LocalSigner, SignKind};
+use crypto::merkle::MerkleTree;
+use crypto::Algorithm;
+use tokio::sync::mpsc::unbounded_channel;
+use types::{Accumulator, BadEncoding, DataWithAcc, Replica};
+use util::peers::channel;
+
+const SEED: u64 = 42;
+
+fn context(config: &mut config::Node) -> Context {
+    let signer = LocalSigner::from_keys(&Algorithm::ED25519, &config.secret_key_bytes, &[]);
+    let (net_send, _) = channel();
+    let (cli_send, _) = unbounded_channel();
+    Context::new(config, Box::new(signer), net_send, cli_send)
+}
+
+#[test]
+fn bad_proposal_encoding() {
+    let mut nodes = genconfig::generate_testnet(4, 1, SEED).nodes;
+    let mut cx = context(&mut nodes[1]);
+    let leader = cx.last_leader;
+    let leader_cx = context(&mut nodes[leader as usize]);
+    let other = context(&mut nodes[2]);
+
+    // The leader proposes shards that are not a codeword
+    let mut shards = to_shards(&[1, 2, 3, 4, 5], 4, 1);
+    shards[3][0] ^= 1;
+    let acc = Accumulator::Merkle(MerkleTree::build(cx.hash_alg, &shards));
+    let sign = leader_cx
+        .signer
+        .sign(SignKind::Propose, cx.epoch, &acc.digest(cx.hash_alg))
+        .unwrap();
+    let acc = DataWithAcc { sign, acc };
+    for n in 1..4 {
+        cx.propose_gatherer.add_share(
+            shards[n].clone(),
+            n as Replica,
+            cx.epoch,
+            &cx.accumulator_pub_params_map[&leader],
+            cx.pub_key_map.get(&leader, cx.epoch).unwrap(),
+            get_sign(&acc, n as Replica),
+        );
+    }
+    let mut proof = match cx.propose_gatherer.reconstruct(4, 1) {
+        Reconstructed::BadEncoding(shards) => BadEncoding {
+            dealer: leader,
+            kind: cx.propose_gatherer.kind,
+            shards,
+        },
+        _ => panic!("the encoding should be found bad"),
+    };
+    assert!(check_bad_encoding(&other, &proof));
+
+    // Nor can it pass for commitments
+    proof.kind = SignKind::Commit;
+    assert!(!check_bad_encoding(&other, &proof));
+}
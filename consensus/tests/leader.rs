@@ -0,0 +1,53 @@
+extern crate consensus;
+use consensus::bft::node::context::Context;
+use consensus::bft::node::end_epoch;
+use consensus::bft::node::leader::{BeaconSchedule, Committed};
+use consensus::bft::node::signer::LocalSigner;
+use crypto::hash::{hash_in, Domain, EMPTY_HASH};
+use crypto::rand::{rngs::StdRng, SeedableRng};
+use crypto::{Algorithm, UniformRand, EVSS381, F381};
+use tokio::sync::mpsc::unbounded_channel;
+use util::peers::channel;
+
+const SEED: u64 = 42;
+
+fn context(config: &mut config::Node) -> Context {
+    let signer = LocalSigner::from_keys(&Algorithm::ED25519, &config.secret_key_bytes, &[]);
+    let (net_send, _) = channel();
+    let (cli_send, _) = unbounded_channel();
+    let mut cx = Context::new(config, Box::new(signer), net_send, cli_send);
+    cx.leader_schedule = Box::new(BeaconSchedule::new(cx.num_nodes, 10, cx.hash_alg));
+    cx
+}
+
+#[test]
+fn leader_ignores_local_beacon() {
+    let mut nodes = genconfig::generate_testnet(4, 1, SEED).nodes;
+    let mut a = context(&mut nodes[0]);
+    let mut b = context(&mut nodes[1]);
+    let committed = Committed {
+        hash: hash_in(a.hash_alg, Domain::Block, b"block"),
+        author: a.next_leader(),
+        dealt: true,
+    };
+    a.committed = Some(committed.clone());
+    b.committed = Some(committed);
+
+    // Only the first node gathers enough shares to reconstruct the beacon
+    let rng = &mut StdRng::seed_from_u64(SEED);
+    let params = &a.rand_beacon_parameter;
+    let poly = EVSS381::commit(params, F381::rand(rng), rng).unwrap();
+    let shares: Vec<_> = (0..3u16)
+        .map(|j| EVSS381::get_share(F381::from(j + 1), params, &poly, rng).unwrap())
+        .collect();
+    for share in shares {
+        a.reconstruct_queue.push_back((share, a.epoch));
+    }
+
+    assert_ne!(end_epoch(&mut a), EMPTY_HASH);
+    assert_eq!(end_epoch(&mut b), EMPTY_HASH);
+    assert_eq!(
+        a.leader_schedule.leader(a.epoch + 2),
+        b.leader_schedule.leader(b.epoch + 2)
+    );
+}
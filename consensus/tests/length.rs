@@ -8,6 +8,7 @@ use types::*;
 use util::io::to_bytes;
 
 const SEED: u64 = 42;
+const ALG: hash::HashAlgorithm = hash::HashAlgorithm::SHA256;
 static TEST_POINTS: [usize; 98] = [
     3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
     28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51,
@@ -21,27 +22,12 @@ fn tree_get_dummy_acc<T: Serialize>(
     cx_num_faults: Replica,
     data: &T,
 ) -> (Vec<Vec<u8>>, DataWithAcc) {
-    let shards = accumulator::to_shards(
-        &to_bytes(data),
-        cx_num_nodes as usize,
-        cx_num_faults as usize,
-    );
-    let size = accumulator::get_size(cx_num_nodes) as usize;
-    let mut tree = vec![Vec::new(); (1 << size) + 1];
-    for i in 0..cx_num_nodes as usize {
-        tree[1 << size - 1 | i] = hash::ser_and_hash(&shards[i]).to_vec();
-    }
-    for i in 0..(1 << size - 1) - 1 {
-        let index = (1 << size - 1) - 1 - i;
-        tree[index] =
-            hash::ser_and_hash(&(tree[index << 1].clone(), tree[index << 1 | 1].clone())).to_vec();
-    }
+    let (shards, tree) = accumulator::build_tree(ALG, data, cx_num_nodes, cx_num_faults);
     (
         shards,
         DataWithAcc {
             sign: Vec::new(),
-            tree: tree,
-            size: size as Replica,
+            acc: Accumulator::Merkle(tree),
         },
     )
 }
@@ -64,10 +50,12 @@ fn generate_propose() -> HashMap<usize, Propose> {
         let content = Content {
             acks: certificate.votes.clone(),
             commits: vec![poly.get_commit(); *test],
+            reconfig: None,
+            rotations: Vec::new(),
         };
         let mut block = Block::new();
         block.body.data = content;
-        block.update_hash();
+        block.update_hash(ALG);
         let propose = Propose {
             new_block: block,
             certificate: certificate,
@@ -95,6 +83,7 @@ mod tests {
         for n in TEST_POINTS.iter() {
             let data = Certificate {
                 votes: vec![empty_vote(); *n],
+                epoch: 0,
             };
             println!(
                 "Certificate,{},{}",
@@ -133,6 +122,7 @@ mod tests {
         for n in TEST_POINTS.iter() {
             let data = Certificate {
                 votes: vec![empty_vote(); *n],
+                epoch: 0,
             };
             let acc = tree_get_dummy_acc(*n as u16, ((n - 1) / 2) as u16, &data).1;
             println!(
@@ -161,11 +151,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn deliver_propose_bi_length() {
+        let p = generate_propose();
+        let rng = &mut StdRng::seed_from_u64(SEED);
+        for n in TEST_POINTS.iter() {
+            let params = Biaccumulator381::setup(*n, rng).unwrap();
+            let (shards, acc) = accumulator::build_bi(
+                ALG,
+                &params,
+                p.get(&n).unwrap(),
+                *n as u16,
+                ((n - 1) / 2) as u16,
+            );
+            let acc = DataWithAcc {
+                sign: Vec::new(),
+                acc,
+            };
+            println!(
+                "DeliverProposeBi,{},{}",
+                n,
+                to_bytes(&ProtocolMsg::DeliverPropose(
+                    shards[0].clone(),
+                    0,
+                    accumulator::get_sign(&acc, 0)
+                ))
+                .len()
+            );
+        }
+    }
+
     #[test]
     fn deliver_vote_cert_length() {
         for n in TEST_POINTS.iter() {
             let data = Certificate {
                 votes: vec![empty_vote(); *n],
+                epoch: 0,
             };
             let acc = tree_get_dummy_acc(*n as u16, ((n - 1) / 2) as u16, &data);
             println!(
@@ -291,4 +312,56 @@ mod tests {
             );
         }
     }
+
+    /// A proposal as a node of a generated network makes it, with signed
+    /// votes and the commitments of a dealing
+    #[test]
+    fn testnet_propose_length() {
+        use consensus::bft::node::signer::{LocalSigner, SignKind, Signer};
+        let n = 4;
+        let mut node = genconfig::generate_testnet(n, 1, SEED).nodes.remove(0);
+        let signer = LocalSigner::from_keys(&node.crypto_alg, &node.secret_key_bytes, &[]);
+        let vote = |r: Replica| Vote {
+            msg: [0; 32].to_vec(),
+            origin: r,
+            auth: signer.sign(SignKind::Vote, 0, &[0; 32]).unwrap(),
+        };
+        let mut certificate = Certificate::empty_cert();
+        certificate.votes = (0..n as Replica).map(vote).collect();
+        let mut block = Block::new();
+        block.body.data = Content {
+            acks: certificate.votes.clone(),
+            commits: vec![node.rand_beacon_shares[0].1.clone(); n],
+            reconfig: None,
+            rotations: Vec::new(),
+        };
+        block.update_hash(node.hash_alg);
+        let propose = Propose {
+            new_block: block,
+            certificate,
+            epoch: 0,
+        };
+        let params = node.bi_p.take().unwrap();
+        let (shards, acc) =
+            accumulator::build_bi(node.hash_alg, &params, &propose, n as Replica, 1);
+        let acc = DataWithAcc {
+            sign: signer.sign(SignKind::Propose, 0, &[0; 32]).unwrap(),
+            acc,
+        };
+        println!(
+            "TestnetPropose,{},{}",
+            n,
+            to_bytes(&ProtocolMsg::Propose(propose, acc.clone())).len()
+        );
+        println!(
+            "TestnetDeliverPropose,{},{}",
+            n,
+            to_bytes(&ProtocolMsg::DeliverPropose(
+                shards[0].clone(),
+                0,
+                accumulator::get_sign(&acc, 0)
+            ))
+            .len()
+        );
+    }
 }
@@ -0,0 +1,80 @@
+extern crate consensus;
+use consensus::bft::node::context::Context;
+use consensus::bft::node::next_dealing;
+use consensus::bft::node::signer::LocalSigner;
+use crypto::Algorithm;
+use tokio::sync::mpsc::unbounded_channel;
+use types::Reconfig;
+use util::peers::{channel, Outgoing};
+
+const SEED: u64 = 42;
+
+#[test]
+fn reconfigure() {
+    let mut config = genconfig::generate_testnet(3, 1, SEED).nodes.remove(0);
+    let signer = LocalSigner::from_keys(&Algorithm::ED25519, &config.secret_key_bytes, &[]);
+    let (net_send, mut net_recv) = channel();
+    let (cli_send, _cli_recv) = unbounded_channel();
+    let mut cx = Context::new(&mut config, Box::new(signer), net_send, cli_send);
+    assert_eq!(cx.broadcast, 3);
+    assert_eq!(next_dealing(&cx).1.len(), 3);
+
+    // A fourth node joins
+    let mut joined = genconfig::generate_testnet(4, 1, SEED + 1).nodes.remove(3);
+    let mut r = Reconfig {
+        epoch: 5,
+        num_nodes: 4,
+        num_faults: 1,
+        pk_map: config
+            .pk_map
+            .iter()
+            .map(|(id, pk)| (*id, pk.clone()))
+            .collect(),
+        net_map: config
+            .net_map
+            .iter()
+            .map(|(id, addr)| (*id, addr.to_string()))
+            .collect(),
+        bi_pp_map: config
+            .bi_pp_map
+            .iter()
+            .map(|(id, pp)| (*id, pp.clone()))
+            .collect(),
+        rand_beacon_parameter: joined.rand_beacon_parameter.clone().unwrap(),
+    };
+    r.pk_map.insert(3, joined.pk_map[&3].clone());
+    r.net_map.insert(3, joined.net_map[&3].to_string());
+    r.bi_pp_map.insert(3, joined.bi_pp_map[&3].clone());
+    assert!(r.is_well_formed());
+    cx.reconfigure(&r);
+
+    assert_eq!((cx.num_nodes, cx.num_faults), (4, 1));
+    assert_eq!(cx.broadcast, 4);
+    let peers = match net_recv.try_recv().unwrap() {
+        Outgoing::Peers(peers) => peers,
+        out => panic!("expected the new membership, got {:?}", out),
+    };
+    assert_eq!(peers.num_nodes, 4);
+    assert_eq!(peers.addrs[&3], joined.net_map[&3].to_string());
+    assert_eq!(peers.keys[&3], joined.pk_map[&3]);
+    assert_eq!(peers.epoch, 5);
+    // The joining node says hello with the digest of its config for the new
+    // membership
+    joined.pk_map = r.pk_map.clone().into_iter().collect();
+    joined.bi_pp_map = r.bi_pp_map.clone().into_iter().collect();
+    assert_eq!(peers.digest, joined.digest());
+    assert!(cx.pub_key_map.get(&3, 5).is_some());
+    assert!(cx.pub_key_map.get(&3, 4).is_none());
+    assert_eq!(cx.rand_beacon_queue.len(), 4);
+    assert!(cx.rand_beacon_shares.is_empty());
+    assert!((5..9).all(|e| cx.leader_schedule.leader(e) < 4));
+
+    // A fresh dealing for the new membership, with a polynomial per epoch
+    let (shares, commits) = next_dealing(&cx);
+    assert_eq!(commits.len(), 4);
+    assert!(shares.iter().all(|s| s.len() == 4));
+    assert_ne!(
+        bincode::serialize(&commits[0]).unwrap(),
+        bincode::serialize(&commits[1]).unwrap()
+    );
+}
@@ -26,15 +26,16 @@ impl FromStr for Algorithm {
 // openssl pkcs8 -in private.pem -inform PEM -topk8 -out private.pk8 -outform
 // DER -nocrypt
 // rm private.pem      # optional
+// or use scheme::RsaPssScheme.generate()
 // Codec
-// let mut bytes = std::fs::read("private.pk8").unwrap();
-// let keypair = Keypair::rsa_from_pkcs8(&mut bytes);
-// pk = keypair.public()
-// pk.verify for verification
-pub const RSA_PK_SIZE: usize = 64;
+// the public key is a DER encoded SubjectPublicKeyInfo
+// use rsa::RSAPublicKey::from_pkcs8 to deserialize
+// keys are signed and verified with RSA-PSS over SHA-256
+pub const RSA_BITS: usize = 2048;
+pub const RSA_PK_SIZE: usize = 294;
 // Codec
-// keypair.sign()
-pub const RSA_PVT_SIZE: usize = 64;
+// the secret key is the PKCS#8 DER file as is; its size varies by a few bytes
+// use rsa::RSAPrivateKey::from_pkcs8 to deserialize
 
 // Generate Keys
 // use crypto::ed25519::Keypair::generate().{public(), secret()} to
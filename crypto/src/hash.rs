@@ -1,16 +1,183 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::str::FromStr;
 
 pub const HASH_SIZE: usize = 32;
 pub type Hash = [u8; HASH_SIZE];
 
 pub const EMPTY_HASH: Hash = [0 as u8; 32];
 
+/// The hash function behind every hash in the protocol. All nodes must use
+/// the same one, so it is part of the config, and it is passed along to
+/// wherever the protocol hashes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    SHA256,
+    BLAKE3,
+}
+
+impl Default for HashAlgorithm {
+    fn default() -> Self {
+        HashAlgorithm::SHA256
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SHA256" => Ok(HashAlgorithm::SHA256),
+            "BLAKE3" => Ok(HashAlgorithm::BLAKE3),
+            _ => Err("no match"),
+        }
+    }
+}
+
+/// What a hash is for. Hashes from different domains never collide, e.g. a
+/// Merkle inner node cannot pass for a block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Domain {
+    Block,
+    Ack,
+    Commitments,
+    Reconfig,
+    Accumulator,
+    MerkleLeaf,
+    MerkleNode,
+    Beacon,
+    Leader,
+    KeyRotation,
+    Handshake,
+    Config,
+    Signature,
+}
+
+impl Domain {
+    fn label(&self) -> &'static str {
+        match self {
+            Domain::Block => "randpiper block",
+            Domain::Ack => "randpiper ack",
+            Domain::Commitments => "randpiper commitments",
+            Domain::Reconfig => "randpiper reconfig",
+            Domain::Accumulator => "randpiper accumulator",
+            Domain::MerkleLeaf => "randpiper merkle-leaf",
+            Domain::MerkleNode => "randpiper merkle-node",
+            Domain::Beacon => "randpiper beacon",
+            Domain::Leader => "randpiper leader",
+            Domain::KeyRotation => "randpiper key-rotation",
+            Domain::Handshake => "randpiper handshake",
+            Domain::Config => "randpiper config",
+            Domain::Signature => "randpiper signature",
+        }
+    }
+}
+
+enum State {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+/// Hashes data in a domain
+pub struct Hasher {
+    state: State,
+}
+
+impl Hasher {
+    pub fn new(alg: HashAlgorithm, domain: Domain) -> Self {
+        let label = domain.label();
+        let state = match alg {
+            HashAlgorithm::SHA256 => {
+                let mut h = Sha256::new();
+                h.update([label.len() as u8]);
+                h.update(label.as_bytes());
+                State::Sha256(h)
+            }
+            HashAlgorithm::BLAKE3 => State::Blake3(Box::new(blake3::Hasher::new_derive_key(label))),
+        };
+        Hasher { state }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+        match &mut self.state {
+            State::Sha256(h) => h.update(bytes),
+            State::Blake3(h) => {
+                h.update(bytes);
+            }
+        }
+        self
+    }
+
+    pub fn finalize(self) -> Hash {
+        match self.state {
+            State::Sha256(h) => h.finalize().into(),
+            State::Blake3(h) => h.finalize().into(),
+        }
+    }
+}
+
+/// Hashes `bytes` in `domain`
+pub fn hash_in(alg: HashAlgorithm, domain: Domain, bytes: &[u8]) -> Hash {
+    let mut h = Hasher::new(alg, domain);
+    h.update(bytes);
+    h.finalize()
+}
+
+/// Hashes the serialisation of `obj` in `domain`
+pub fn ser_and_hash_in(alg: HashAlgorithm, domain: Domain, obj: &impl Serialize) -> Hash {
+    hash_in(alg, domain, &bincode::serialize(&obj).unwrap())
+}
+
+/// Hashes `bytes` with SHA-256, outside of any domain. What nodes sign or
+/// compare is hashed in a domain with the algorithm of the config instead.
 pub fn do_hash(bytes: &[u8]) -> Hash {
-    let hash = Sha256::digest(bytes);
-    return hash.into();
+    Sha256::digest(bytes).into()
 }
 
 pub fn ser_and_hash(obj: &impl Serialize) -> Hash {
     return do_hash(&bincode::serialize(&obj).unwrap());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domains() {
+        let domains = [
+            Domain::Block,
+            Domain::Ack,
+            Domain::Commitments,
+            Domain::Reconfig,
+            Domain::Accumulator,
+            Domain::MerkleLeaf,
+            Domain::MerkleNode,
+            Domain::Beacon,
+            Domain::Leader,
+            Domain::KeyRotation,
+            Domain::Handshake,
+            Domain::Config,
+            Domain::Signature,
+        ];
+        for alg in [HashAlgorithm::SHA256, HashAlgorithm::BLAKE3].iter() {
+            let hashes: Vec<Hash> = domains
+                .iter()
+                .map(|d| {
+                    let mut h = Hasher::new(*alg, *d);
+                    h.update(b"data");
+                    h.finalize()
+                })
+                .collect();
+            for i in 0..hashes.len() {
+                for j in 0..i {
+                    assert_ne!(hashes[i], hashes[j]);
+                }
+            }
+        }
+        let mut sha = Hasher::new(HashAlgorithm::SHA256, Domain::Block);
+        let mut blake = Hasher::new(HashAlgorithm::BLAKE3, Domain::Block);
+        sha.update(b"data");
+        blake.update(b"data");
+        assert_ne!(sha.finalize(), blake.finalize());
+    }
+}
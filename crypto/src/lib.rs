@@ -7,6 +7,9 @@ mod tests {
 }
 
 pub mod hash;
+pub mod merkle;
+pub mod scheme;
+pub mod secret;
 
 mod crypto;
 pub use crypto::*;
@@ -0,0 +1,159 @@
+use crate::hash::{Domain, Hash, HashAlgorithm, Hasher, EMPTY_HASH};
+use serde::{Deserialize, Serialize};
+
+// Leaves and inner nodes are hashed in different domains so that an inner
+// node can never be passed off as a leaf, or the other way around.
+pub fn leaf_hash(alg: HashAlgorithm, data: &[u8]) -> Hash {
+    let mut h = Hasher::new(alg, Domain::MerkleLeaf);
+    h.update(data);
+    h.finalize()
+}
+
+pub fn node_hash(alg: HashAlgorithm, left: &Hash, right: &Hash) -> Hash {
+    let mut h = Hasher::new(alg, Domain::MerkleNode);
+    h.update(left).update(right);
+    h.finalize()
+}
+
+/// A binary Merkle tree over a fixed number of leaves. The leaves are padded
+/// to a power of two with `EMPTY_HASH`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleTree {
+    pub num_leaves: usize,
+    /// levels[0] holds the leaves and the last level holds the root
+    levels: Vec<Vec<Hash>>,
+}
+
+/// The path from a leaf to the root. Only valid for the index it was made
+/// for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InclusionProof {
+    pub index: usize,
+    pub num_leaves: usize,
+    pub siblings: Vec<Hash>,
+}
+
+/// The number of levels below the root in a tree with `num_leaves` leaves
+fn depth(num_leaves: usize) -> usize {
+    let mut d = 0;
+    while 1 << d < num_leaves {
+        d += 1;
+    }
+    d
+}
+
+impl MerkleTree {
+    pub fn build<T: AsRef<[u8]>>(alg: HashAlgorithm, leaves: &[T]) -> Self {
+        let width = 1 << depth(leaves.len());
+        let mut level: Vec<Hash> = leaves.iter().map(|l| leaf_hash(alg, l.as_ref())).collect();
+        level.resize(width, EMPTY_HASH);
+        let mut levels = vec![level];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| node_hash(alg, &pair[0], &pair[1]))
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree {
+            num_leaves: leaves.len(),
+            levels,
+        }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().unwrap()[0]
+    }
+
+    pub fn prove(&self, index: usize) -> InclusionProof {
+        assert!(index < self.num_leaves, "leaf index out of range");
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        let mut p = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level[p ^ 1]);
+            p >>= 1;
+        }
+        InclusionProof {
+            index,
+            num_leaves: self.num_leaves,
+            siblings,
+        }
+    }
+}
+
+impl InclusionProof {
+    /// Checks that `leaf` is the leaf at `index` of the tree with `root`
+    pub fn verify(&self, alg: HashAlgorithm, root: &Hash, index: usize, leaf: &[u8]) -> bool {
+        if self.index != index
+            || index >= self.num_leaves
+            || self.siblings.len() != depth(self.num_leaves)
+        {
+            return false;
+        }
+        let mut h = leaf_hash(alg, leaf);
+        let mut p = index;
+        for sibling in &self.siblings {
+            h = if p & 1 == 0 {
+                node_hash(alg, &h, sibling)
+            } else {
+                node_hash(alg, sibling, &h)
+            };
+            p >>= 1;
+        }
+        h == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALG: HashAlgorithm = HashAlgorithm::SHA256;
+
+    #[test]
+    fn prove_and_verify() {
+        for n in 1..40 {
+            let leaves: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8; i + 1]).collect();
+            let tree = MerkleTree::build(ALG, &leaves);
+            let root = tree.root();
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.prove(i);
+                assert!(proof.verify(ALG, &root, i, leaf));
+                let proof: InclusionProof =
+                    bincode::deserialize(&bincode::serialize(&proof).unwrap()).unwrap();
+                assert!(proof.verify(ALG, &root, i, leaf));
+                assert!(!proof.verify(ALG, &root, i, &[0xff; 3]));
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_index() {
+        // A shard with its proof must not pass as the shard of another node
+        let leaves = vec![vec![1u8], vec![2u8], vec![3u8]];
+        let tree = MerkleTree::build(ALG, &leaves);
+        let proof = tree.prove(0);
+        assert!(!proof.verify(ALG, &tree.root(), 1, &leaves[0]));
+        let mut forged = proof.clone();
+        forged.index = 1;
+        assert!(!forged.verify(ALG, &tree.root(), 1, &leaves[0]));
+    }
+
+    #[test]
+    fn no_second_preimage() {
+        let leaves = vec![vec![1u8], vec![2u8], vec![3u8], vec![4u8]];
+        let tree = MerkleTree::build(ALG, &leaves);
+        // An inner node is not accepted as a leaf
+        let mut inner = Vec::new();
+        inner.extend_from_slice(&leaf_hash(ALG, &leaves[0]));
+        inner.extend_from_slice(&leaf_hash(ALG, &leaves[1]));
+        let proof = InclusionProof {
+            index: 0,
+            num_leaves: 2,
+            siblings: vec![tree.prove(2).siblings[1]],
+        };
+        assert!(!proof.verify(ALG, &tree.root(), 0, &inner));
+    }
+}
@@ -0,0 +1,342 @@
+//! Signature schemes for the keys of the nodes.
+//!
+//! Each `Algorithm` has a `SignatureScheme` in `SCHEMES` that knows how its
+//! keys are encoded, generated and used. The rest of the code only deals with
+//! `SigningKey` and `VerifyingKey`, so adding a scheme means implementing the
+//! trait and registering it here.
+//!
+//! A scheme may also give the X25519 keys for the encrypted transport between
+//! the nodes, so that the links are authenticated with the same keys as the
+//! messages.
+
+use crate::secret::SecretBytes;
+use crate::Algorithm;
+use crypto_lib::{ed25519, secp256k1, Keypair, PublicKey};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rsa::{PaddingScheme, PrivateKeyEncoding, PublicKeyEncoding, RSAPrivateKey, RSAPublicKey};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use zeroize::Zeroizing;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemeError {
+    /// No scheme is registered for the algorithm
+    Unsupported(Algorithm),
+    Decode(String),
+    Sign(String),
+    Generate(String),
+}
+
+impl fmt::Display for SchemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemeError::Unsupported(alg) => write!(f, "no signature scheme for {:?}", alg),
+            SchemeError::Decode(e) => write!(f, "failed to decode the key: {}", e),
+            SchemeError::Sign(e) => write!(f, "failed to sign: {}", e),
+            SchemeError::Generate(e) => write!(f, "failed to generate a key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchemeError {}
+
+pub trait SigningKey: Send + Sync {
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SchemeError>;
+}
+
+pub trait VerifyingKey: Send + Sync {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool;
+}
+
+pub trait SignatureScheme: Send + Sync {
+    fn algorithm(&self) -> Algorithm;
+
+    /// The size of an encoded public key
+    fn pk_size(&self) -> usize;
+
+    /// The size of an encoded secret key, if the encoding has a fixed size
+    fn sk_size(&self) -> Option<usize>;
+
+    /// Generates a key pair from `rng`, encoded as (public key, secret key)
+    fn generate_from(&self, rng: &mut dyn RngCore) -> Result<(Vec<u8>, Vec<u8>), SchemeError>;
+
+    /// Generates a key pair from the randomness of the OS
+    fn generate(&self) -> Result<(Vec<u8>, Vec<u8>), SchemeError> {
+        self.generate_from(&mut OsRng)
+    }
+
+    fn decode_pk(&self, bytes: &[u8]) -> Result<Box<dyn VerifyingKey>, SchemeError>;
+
+    fn decode_sk(&self, bytes: &[u8]) -> Result<Box<dyn SigningKey>, SchemeError>;
+
+    /// The X25519 public key for the encrypted transport that goes with the
+    /// encoded public key `pk`
+    fn transport_pk(&self, _pk: &[u8]) -> Result<[u8; 32], SchemeError> {
+        Err(SchemeError::Unsupported(self.algorithm()))
+    }
+
+    /// The X25519 secret key that goes with the encoded secret key `sk`
+    fn transport_sk(&self, _sk: &[u8]) -> Result<SecretBytes, SchemeError> {
+        Err(SchemeError::Unsupported(self.algorithm()))
+    }
+}
+
+/// Every scheme a node can use
+pub static SCHEMES: [&dyn SignatureScheme; 3] = [&Ed25519Scheme, &Secp256k1Scheme, &RsaPssScheme];
+
+pub fn lookup(alg: &Algorithm) -> Result<&'static dyn SignatureScheme, SchemeError> {
+    SCHEMES
+        .iter()
+        .find(|s| s.algorithm() == *alg)
+        .copied()
+        .ok_or_else(|| SchemeError::Unsupported(alg.clone()))
+}
+
+/// The X25519 public key that goes with the transport secret key `sk`
+pub fn transport_public(sk: &[u8]) -> Result<[u8; 32], SchemeError> {
+    use curve25519_dalek::{constants::X25519_BASEPOINT, scalar::Scalar};
+    if sk.len() != 32 {
+        return Err(SchemeError::Decode(format!("key of {} bytes", sk.len())));
+    }
+    let mut clamped = Zeroizing::new([0u8; 32]);
+    clamped.copy_from_slice(sk);
+    clamped[0] &= 248;
+    clamped[31] &= 127;
+    clamped[31] |= 64;
+    Ok((X25519_BASEPOINT * Scalar::from_bits(*clamped)).to_bytes())
+}
+
+impl SigningKey for Keypair {
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SchemeError> {
+        Keypair::sign(self, msg).map_err(|e| SchemeError::Sign(format!("{:?}", e)))
+    }
+}
+
+impl VerifyingKey for PublicKey {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        PublicKey::verify(self, msg, sig)
+    }
+}
+
+pub struct Ed25519Scheme;
+
+impl SignatureScheme for Ed25519Scheme {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ED25519
+    }
+
+    fn pk_size(&self) -> usize {
+        crate::ED25519_PK_SIZE
+    }
+
+    fn sk_size(&self) -> Option<usize> {
+        Some(crate::ED25519_PVT_SIZE)
+    }
+
+    fn generate_from(&self, rng: &mut dyn RngCore) -> Result<(Vec<u8>, Vec<u8>), SchemeError> {
+        let mut seed = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(&mut seed[..]);
+        let sk = ed25519::SecretKey::from_bytes(&mut seed[..])
+            .map_err(|e| SchemeError::Generate(format!("{:?}", e)))?;
+        let kp = ed25519::Keypair::from(sk);
+        Ok((kp.public().encode().to_vec(), kp.encode().to_vec()))
+    }
+
+    fn decode_pk(&self, bytes: &[u8]) -> Result<Box<dyn VerifyingKey>, SchemeError> {
+        let pk = ed25519::PublicKey::decode(&mut bytes.to_vec())
+            .map_err(|e| SchemeError::Decode(format!("{:?}", e)))?;
+        Ok(Box::new(PublicKey::Ed25519(pk)))
+    }
+
+    fn decode_sk(&self, bytes: &[u8]) -> Result<Box<dyn SigningKey>, SchemeError> {
+        // Decoding takes the key by mutable reference, so it goes through a
+        // copy that is wiped afterwards
+        let mut bytes = Zeroizing::new(bytes.to_vec());
+        let kp = ed25519::Keypair::decode(&mut bytes[..])
+            .map_err(|e| SchemeError::Decode(format!("{:?}", e)))?;
+        Ok(Box::new(Keypair::Ed25519(kp)))
+    }
+
+    // Ed25519 keys are points and scalars of the same curve as X25519 keys,
+    // so they convert the way RFC 7748 and libsodium do
+
+    fn transport_pk(&self, pk: &[u8]) -> Result<[u8; 32], SchemeError> {
+        if pk.len() != crate::ED25519_PK_SIZE {
+            return Err(SchemeError::Decode(format!("key of {} bytes", pk.len())));
+        }
+        let point = CompressedEdwardsY::from_slice(pk)
+            .decompress()
+            .ok_or_else(|| SchemeError::Decode("not a point of the curve".to_string()))?;
+        Ok(point.to_montgomery().to_bytes())
+    }
+
+    fn transport_sk(&self, sk: &[u8]) -> Result<SecretBytes, SchemeError> {
+        // The encoded key pair starts with the seed
+        if sk.len() != crate::ED25519_PVT_SIZE {
+            return Err(SchemeError::Decode(format!("key of {} bytes", sk.len())));
+        }
+        let mut hash = Sha512::digest(&sk[..32]);
+        let key = SecretBytes::new(hash[..32].to_vec());
+        hash.iter_mut().for_each(|b| *b = 0);
+        Ok(key)
+    }
+}
+
+pub struct Secp256k1Scheme;
+
+impl SignatureScheme for Secp256k1Scheme {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::SECP256K1
+    }
+
+    fn pk_size(&self) -> usize {
+        crate::SECP256K1_PK_SIZE
+    }
+
+    fn sk_size(&self) -> Option<usize> {
+        Some(crate::SECP256K1_PVT_SIZE)
+    }
+
+    fn generate_from(&self, rng: &mut dyn RngCore) -> Result<(Vec<u8>, Vec<u8>), SchemeError> {
+        // Nearly every 32 byte string is a valid key
+        let sk = loop {
+            let mut bytes = Zeroizing::new(vec![0u8; 32]);
+            rng.fill_bytes(&mut bytes[..]);
+            if let Ok(sk) = secp256k1::SecretKey::from_bytes(&mut bytes[..]) {
+                break sk;
+            }
+        };
+        let kp = secp256k1::Keypair::from(sk);
+        Ok((
+            kp.public().encode().to_vec(),
+            kp.secret().to_bytes().to_vec(),
+        ))
+    }
+
+    fn decode_pk(&self, bytes: &[u8]) -> Result<Box<dyn VerifyingKey>, SchemeError> {
+        let pk = secp256k1::PublicKey::decode(bytes)
+            .map_err(|e| SchemeError::Decode(format!("{:?}", e)))?;
+        Ok(Box::new(PublicKey::Secp256k1(pk)))
+    }
+
+    fn decode_sk(&self, bytes: &[u8]) -> Result<Box<dyn SigningKey>, SchemeError> {
+        let mut bytes = Zeroizing::new(bytes.to_vec());
+        let sk = secp256k1::SecretKey::from_bytes(&mut bytes[..])
+            .map_err(|e| SchemeError::Decode(format!("{:?}", e)))?;
+        Ok(Box::new(Keypair::Secp256k1(secp256k1::Keypair::from(sk))))
+    }
+}
+
+/// RSA-PSS over SHA-256 with 2048 bit keys. Public keys are DER encoded
+/// SubjectPublicKeyInfo, and secret keys are DER encoded PKCS#8, e.g. from
+/// `openssl pkcs8 -topk8 -outform DER -nocrypt`.
+pub struct RsaPssScheme;
+
+impl SigningKey for RSAPrivateKey {
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SchemeError> {
+        let digest = Sha256::digest(msg);
+        let padding = PaddingScheme::new_pss::<Sha256, _>(OsRng);
+        RSAPrivateKey::sign(self, padding, &digest).map_err(|e| SchemeError::Sign(e.to_string()))
+    }
+}
+
+impl VerifyingKey for RSAPublicKey {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        use rsa::PublicKey;
+        let digest = Sha256::digest(msg);
+        let padding = PaddingScheme::new_pss::<Sha256, _>(OsRng);
+        PublicKey::verify(self, padding, &digest, sig).is_ok()
+    }
+}
+
+impl SignatureScheme for RsaPssScheme {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::RSA
+    }
+
+    fn pk_size(&self) -> usize {
+        crate::RSA_PK_SIZE
+    }
+
+    fn sk_size(&self) -> Option<usize> {
+        // PKCS#8 encodings of the same size of key differ by a few bytes
+        None
+    }
+
+    fn generate_from(&self, mut rng: &mut dyn RngCore) -> Result<(Vec<u8>, Vec<u8>), SchemeError> {
+        let sk = RSAPrivateKey::new(&mut rng, crate::RSA_BITS)
+            .map_err(|e| SchemeError::Generate(e.to_string()))?;
+        let pk = RSAPublicKey::from(&sk)
+            .to_pkcs8()
+            .map_err(|e| SchemeError::Generate(e.to_string()))?;
+        let sk = sk
+            .to_pkcs8()
+            .map_err(|e| SchemeError::Generate(e.to_string()))?;
+        Ok((pk, sk))
+    }
+
+    fn decode_pk(&self, bytes: &[u8]) -> Result<Box<dyn VerifyingKey>, SchemeError> {
+        let pk = RSAPublicKey::from_pkcs8(bytes).map_err(|e| SchemeError::Decode(e.to_string()))?;
+        Ok(Box::new(pk))
+    }
+
+    fn decode_sk(&self, bytes: &[u8]) -> Result<Box<dyn SigningKey>, SchemeError> {
+        let sk =
+            RSAPrivateKey::from_pkcs8(bytes).map_err(|e| SchemeError::Decode(e.to_string()))?;
+        sk.validate()
+            .map_err(|e| SchemeError::Decode(e.to_string()))?;
+        Ok(Box::new(sk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        for scheme in SCHEMES.iter() {
+            let (pk, sk) = scheme.generate().unwrap();
+            assert_eq!(pk.len(), scheme.pk_size());
+            if let Some(size) = scheme.sk_size() {
+                assert_eq!(sk.len(), size);
+            }
+            let sk = scheme.decode_sk(&sk).unwrap();
+            let pk = scheme.decode_pk(&pk).unwrap();
+            let sig = sk.sign(b"block").unwrap();
+            assert!(pk.verify(b"block", &sig));
+            assert!(!pk.verify(b"other", &sig));
+            assert_eq!(
+                lookup(&scheme.algorithm()).unwrap().pk_size(),
+                scheme.pk_size()
+            );
+        }
+    }
+
+    #[test]
+    fn seeded() {
+        use rand::{rngs::StdRng, SeedableRng};
+        for scheme in SCHEMES.iter() {
+            let a = scheme.generate_from(&mut StdRng::seed_from_u64(7)).unwrap();
+            let b = scheme.generate_from(&mut StdRng::seed_from_u64(7)).unwrap();
+            let c = scheme.generate_from(&mut StdRng::seed_from_u64(8)).unwrap();
+            assert_eq!(a, b);
+            assert_ne!(a.0, c.0);
+        }
+    }
+
+    #[test]
+    fn transport_keys() {
+        let (pk, sk) = Ed25519Scheme.generate().unwrap();
+        let public = transport_public(&Ed25519Scheme.transport_sk(&sk).unwrap()).unwrap();
+        assert_eq!(Ed25519Scheme.transport_pk(&pk).unwrap(), public);
+        assert!(transport_public(&sk).is_err());
+        assert!(Ed25519Scheme.transport_pk(&sk).is_err());
+
+        let (pk, sk) = Secp256k1Scheme.generate().unwrap();
+        assert!(Secp256k1Scheme.transport_pk(&pk).is_err());
+        assert!(Secp256k1Scheme.transport_sk(&sk).is_err());
+    }
+}
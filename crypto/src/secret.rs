@@ -0,0 +1,211 @@
+//! Secret key and share material.
+//!
+//! Secrets are kept in a `Secret`, which overwrites them when dropped and
+//! prints only a placeholder with `Debug`. It is not `Clone`, so every copy of
+//! a secret has to be made on purpose through the inner value.
+//!
+//! Values whose fields are not ours to wipe, such as decoded keys, go in a
+//! `SecretBox` instead, which overwrites their memory once they are dropped.
+
+use crate::{EVSSCommit381, EVSSParams381, EVSSShare381, F381};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::alloc::{dealloc, Layout};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+pub use zeroize::{Zeroize, Zeroizing};
+
+/// Overwrites the secret parts of a value
+pub trait Wipe {
+    fn wipe(&mut self);
+}
+
+impl Wipe for u8 {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Wipe for F381 {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Wipe for EVSSShare381 {
+    fn wipe(&mut self) {
+        self.point.zeroize();
+        self.value.zeroize();
+        self.challenge.zeroize();
+        self.proof.w.zeroize();
+        if let Some(v) = self.proof.random_v.as_mut() {
+            v.zeroize();
+        }
+    }
+}
+
+impl Wipe for EVSSParams381 {
+    /// The fields are private to evss, so there is nothing to overwrite in
+    /// place. A `Secret` still keeps the parameters from being cloned or
+    /// printed.
+    fn wipe(&mut self) {}
+}
+
+impl Wipe for EVSSCommit381 {
+    /// Commitments are public
+    fn wipe(&mut self) {}
+}
+
+impl<T: Wipe> Wipe for Vec<T> {
+    fn wipe(&mut self) {
+        self.iter_mut().for_each(Wipe::wipe);
+    }
+}
+
+impl<T: Wipe> Wipe for VecDeque<T> {
+    fn wipe(&mut self) {
+        self.iter_mut().for_each(Wipe::wipe);
+    }
+}
+
+impl<K, V: Wipe> Wipe for HashMap<K, V> {
+    fn wipe(&mut self) {
+        self.values_mut().for_each(Wipe::wipe);
+    }
+}
+
+impl<A: Wipe, B: Wipe> Wipe for (A, B) {
+    fn wipe(&mut self) {
+        self.0.wipe();
+        self.1.wipe();
+    }
+}
+
+/// A secret that is wiped when it is dropped. It serialises as the inner
+/// value.
+#[derive(Default)]
+pub struct Secret<T: Wipe>(T);
+
+/// A secret key in its encoded form
+pub type SecretBytes = Secret<Vec<u8>>;
+
+impl<T: Wipe> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Wipe> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Wipe> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Wipe> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Wipe> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.wipe();
+    }
+}
+
+impl<T: Wipe> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl<T: Wipe + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Wipe + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// A boxed value that is overwritten once it is dropped. Memory the value
+/// owns outside of the box is left to its own `Drop`.
+pub struct SecretBox<T: ?Sized>(ManuallyDrop<Box<T>>);
+
+impl<T: ?Sized> SecretBox<T> {
+    pub fn new(value: Box<T>) -> Self {
+        SecretBox(ManuallyDrop::new(value))
+    }
+}
+
+impl<T: ?Sized> Deref for SecretBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> Drop for SecretBox<T> {
+    fn drop(&mut self) {
+        // Safety: the box is taken once, dropped in place and then freed
+        // with the layout it was allocated with
+        unsafe {
+            let value = ManuallyDrop::take(&mut self.0);
+            let layout = Layout::for_value(&*value);
+            let raw = Box::into_raw(value);
+            ptr::drop_in_place(raw);
+            if layout.size() != 0 {
+                std::slice::from_raw_parts_mut(raw as *mut u8, layout.size()).zeroize();
+                dealloc(raw as *mut u8, layout);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for SecretBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretBox(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wipe_and_redact() {
+        let mut key = SecretBytes::new(vec![7; 32]);
+        assert_eq!(format!("{:?}", key), "Secret(..)");
+        let bytes = bincode::serialize(&key).unwrap();
+        assert_eq!(bytes, bincode::serialize(&vec![7u8; 32]).unwrap());
+        let decoded: SecretBytes = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(*decoded, vec![7; 32]);
+        key.wipe();
+        assert_eq!(*key, vec![0; 32]);
+    }
+
+    #[test]
+    fn secret_box_drops_once() {
+        use std::rc::Rc;
+        let value = Rc::new(());
+        let boxed: SecretBox<dyn fmt::Debug> = SecretBox::new(Box::new(value.clone()));
+        assert_eq!(Rc::strong_count(&value), 2);
+        assert_eq!(format!("{:?}", boxed), "SecretBox(..)");
+        drop(boxed);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
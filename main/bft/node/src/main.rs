@@ -1,51 +1,143 @@
 // use futures::prelude::*;
 use clap::{load_yaml, App};
-use config::Node;
+use config::{Keystore, Node};
+use consensus::bft::node::accumulator::AccumulatorType;
+use consensus::bft::node::leader::{BeaconSchedule, LeaderSchedule, RoundRobin};
+use consensus::bft::node::signer::{LocalSigner, RemoteSigner, SharedSigner, SignKind, Signer};
+use crypto::secret::SecretBytes;
 use std::error::Error;
-use types::{Block, ProtocolMsg, Transaction};
+use std::sync::Arc;
+use types::{Block, Hello, ProtocolMsg, Replica, Transaction};
+use util::noise::Transport;
+use util::peers::{Greeter, Peers};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let yaml = load_yaml!("cli.yml");
     let m = App::from_yaml(yaml).get_matches();
 
+    // Set up first, so that the config overrides can warn
+    simple_logger::SimpleLogger::new().init().unwrap();
+    let x = m.occurrences_of("debug");
+    match x {
+        0 => log::set_max_level(log::LevelFilter::Info),
+        1 => log::set_max_level(log::LevelFilter::Debug),
+        2 | _ => log::set_max_level(log::LevelFilter::Trace),
+    }
+
     let conf_str = m
         .value_of("config")
         .expect("unable to convert config file into a string");
-    let conf_file = std::path::Path::new(conf_str);
-    let str = String::from(conf_str);
-    let mut config = match conf_file
-        .extension()
-        .expect("Unable to get file extension")
-        .to_str()
-        .expect("Failed to convert the extension into ascii string")
-    {
-        "json" => Node::from_json(str),
-        "dat" => Node::from_bin(str),
-        "toml" => Node::from_toml(str),
-        "yaml" => Node::from_yaml(str),
-        _ => panic!("Invalid config file extension"),
-    };
-    config.validate().expect("The decoded config is not valid");
+    let sets = m.values_of("set").into_iter().flatten();
+    let mut config = Node::load_layered(conf_str, std::env::vars_os(), sets)?;
     if let Some(d) = m.value_of("delta") {
         config.delta = d.parse().unwrap();
     }
     if let Some(f) = m.value_of("ip") {
-        config.update_config(util::io::file_to_ips(f.to_string()));
+        config
+            .update_config(util::io::file_to_ips(f.to_string()))
+            .unwrap_or_else(|e| panic!("invalid ip file: {}", e));
+    }
+    // With every override applied
+    if m.is_present("print_config") {
+        print!("{}", config.public_yaml());
+        return Ok(());
+    }
+    if let Some(k) = m.value_of("keys") {
+        let passphrase = m
+            .value_of("passphrase_file")
+            .map(|f| config::read_passphrase(f).expect("failed to read the passphrase file"));
+        let mut keys = Keystore::read(k, passphrase.as_ref().map(|p| p.as_str()))
+            .expect("failed to load the keystore");
+        // The daemon holds the signing keys
+        if m.is_present("signer") {
+            keys = keys.without_signing_keys();
+        }
+        config
+            .set_keys(keys)
+            .expect("the keystore does not fit the config");
     }
+    // Taken before the signer gets the key
+    let transport_sk = if m.is_present("secure") {
+        let scheme = crypto::scheme::lookup(&config.crypto_alg)
+            .unwrap_or_else(|e| panic!("invalid config: {}", e));
+        let transport_sk = |sk: &[u8]| {
+            scheme
+                .transport_sk(sk)
+                .unwrap_or_else(|e| panic!("cannot use the key for the encrypted transport: {}", e))
+        };
+        // The links follow our key through a committed rotation
+        let rotated = config
+            .rotated_keys
+            .iter()
+            .map(|(_, sk)| transport_sk(sk))
+            .collect();
+        Some((transport_sk(&config.secret_key_bytes), rotated))
+    } else {
+        None
+    };
+    let signer: Box<dyn Signer> = match m.value_of("signer") {
+        Some(path) => {
+            // An older config may still carry the key
+            config.secret_key_bytes = SecretBytes::default();
+            config.rotated_keys.clear();
+            config.validate_without_key()?;
+            Box::new(RemoteSigner::connect(path).expect("failed to connect to the signer"))
+        }
+        None => {
+            config.validate()?;
+            let signer = LocalSigner::from_keys(
+                &config.crypto_alg,
+                &config.secret_key_bytes,
+                &config.rotated_keys,
+            );
+            // Only the signer keeps the keys
+            config.secret_key_bytes = SecretBytes::default();
+            config.rotated_keys.clear();
+            Box::new(signer)
+        }
+    };
     println!("{:?}", config.net_map);
+    // Hostnames may only resolve once the other nodes are up
+    for (r, e) in config.unresolved_peers() {
+        println!("[WARN] Cannot resolve the address of node {} yet: {}", r, e);
+    }
     let config = config;
+    // The transport signs the hello of every link it dials
+    let signer = SharedSigner::new(signer);
+    let peers = Peers {
+        num_nodes: config.num_nodes as Replica,
+        addrs: config.network_map(),
+        keys: config.pk_map.clone(),
+        digest: config.digest(),
+        epoch: 0,
+    };
+    // Every connection to another node opens with a signed hello
+    let greeter = Greeter::new(config.crypto_alg.clone(), config.id, &peers);
     let mut is_client_apollo_enabled = false;
     if let Some(_x) = m.value_of("special_client") {
         is_client_apollo_enabled = true;
     }
+    let leader_schedule: Box<dyn LeaderSchedule> = match m.value_of("leader") {
+        Some("beacon") => {
+            let penalty = m
+                .value_of("penalty")
+                .unwrap_or("10")
+                .parse()
+                .map_err(|e| format!("invalid penalty: {}", e))?;
+            Box::new(BeaconSchedule::new(
+                config.num_nodes as u16,
+                penalty,
+                config.hash_alg,
+            ))
+        }
+        _ => Box::new(RoundRobin::new(config.num_nodes as u16)),
+    };
+    let accumulator_type: AccumulatorType = m
+        .value_of("accumulator")
+        .unwrap_or("merkle")
+        .parse()
+        .unwrap();
 
-    simple_logger::SimpleLogger::new().init().unwrap();
-    let x = m.occurrences_of("debug");
-    match x {
-        0 => log::set_max_level(log::LevelFilter::Info),
-        1 => log::set_max_level(log::LevelFilter::Debug),
-        2 | _ => log::set_max_level(log::LevelFilter::Trace),
-    }
     unsafe {
         config_lc::SLEEP_TIME = 10 + 4 * config.num_nodes as u64;
     }
@@ -70,16 +162,47 @@ fn main() -> Result<(), Box<dyn Error>> {
         .build()
         .unwrap();
 
-    // Setup networking
-    let protocol_network =
-        net::Protocol::<ProtocolMsg, ProtocolMsg>::new(config.id, config.num_nodes as u16);
-
     // Setup the protocol network
-    let (net_send, net_recv) = prot_net_rt.block_on(protocol_network.server_setup(
-        config.net_map.clone(),
-        util::codec::EnCodec::new(),
-        util::codec::proto::Codec::new(),
-    ));
+    let (net_send, net_recv) = match transport_sk {
+        Some((secret, rotated)) => {
+            let transport = Transport {
+                id: config.id,
+                alg: config.crypto_alg.clone(),
+                secret,
+                rotated,
+                greeter,
+                sign: {
+                    let signer = signer.clone();
+                    Arc::new(move |hello: &Hello| {
+                        signer
+                            .sign(SignKind::Hello, hello.epoch, &hello.hash())
+                            .map_err(|e| e.to_string())
+                    })
+                },
+            };
+            prot_net_rt.block_on(transport.setup(peers))
+        }
+        None => {
+            // A plain link cannot follow a new membership, so its hello
+            // stays that of the config
+            let mut hello = greeter.hello();
+            hello.sig = signer
+                .sign(SignKind::Hello, hello.epoch, &hello.hash())
+                .unwrap_or_else(|e| panic!("failed to sign the handshake: {}", e));
+            let protocol_network =
+                net::Protocol::<ProtocolMsg, ProtocolMsg>::new(config.id, config.num_nodes as u16);
+            prot_net_rt.block_on(async {
+                let (net_send, net_recv) = protocol_network
+                    .server_setup(
+                        config.network_map(),
+                        util::codec::proto::HelloEnCodec::new(&hello),
+                        util::codec::proto::Codec::with_handshake(greeter.check()),
+                    )
+                    .await;
+                (util::peers::fixed(net_send, peers), net_recv)
+            })
+        }
+    };
 
     let core_rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -89,8 +212,11 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Start the Apollo consensus protocol
     core_rt.block_on(consensus::bft::node::reactor(
-        &config,
+        config,
         is_client_apollo_enabled,
+        leader_schedule,
+        accumulator_type,
+        Box::new(signer),
         net_send,
         net_recv,
         cli_send,
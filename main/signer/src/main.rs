@@ -0,0 +1,44 @@
+// A daemon that holds the key of a node and signs for it over a Unix socket.
+// It never signs two different messages of the same kind for one epoch.
+
+use clap::{load_yaml, App};
+use config::Keystore;
+use consensus::bft::node::signer::{serve, LocalSigner, SignGuard};
+use crypto::Algorithm;
+use std::error::Error;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let yaml = load_yaml!("cli.yml");
+    let m = App::from_yaml(yaml).get_matches();
+
+    let passphrase = m
+        .value_of("passphrase_file")
+        .map(|f| config::read_passphrase(f).expect("failed to read the passphrase file"));
+    let keys = Keystore::read(
+        m.value_of("keys").expect("no keystore specified"),
+        passphrase.as_ref().map(|p| p.as_str()),
+    )
+    .expect("failed to load the keystore");
+    let alg: Algorithm = m
+        .value_of("crypto")
+        .unwrap_or("ED25519")
+        .parse::<Algorithm>()
+        .unwrap_or(Algorithm::ED25519);
+    let key = LocalSigner::from_keys(&alg, &keys.secret_key_bytes, &keys.rotated_keys);
+
+    let state = m.value_of("state").map(Path::new);
+    let guard = match state {
+        Some(path) => SignGuard::load(path).expect("failed to load the signer state"),
+        None => SignGuard::default(),
+    };
+
+    let socket = m.value_of("socket").expect("no socket specified");
+    // A socket left behind by an earlier run
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket)?;
+    println!("Signing for node {} on {}", keys.id, socket);
+    serve(listener, key, guard, state)?;
+    Ok(())
+}
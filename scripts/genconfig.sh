@@ -1,5 +1,23 @@
 set -e
 
+# Regenerates the configs under test/ from the seeds below, so that anyone
+# gets the same files byte for byte. The keys are for tests only.
+SEED=${SEED:-42}
+
 cargo build --package=genconfig --release
-mkdir -p ./test/config
-./target/release/genconfig -o binary -n 4 -d 100 -b 1 -C 6000 -P 7000 -t ./test/config/
+gen() {
+    dir=./test/$1
+    shift
+    rm -rf $dir
+    mkdir -p $dir
+    ./target/release/genconfig -o binary --seed $SEED -t $dir/ "$@"
+}
+
+gen config -n 4 -d 100 -b 1 -C 6000 -P 7000
+gen d100-n3 -n 3 -d 100
+gen d100-n8 -n 8 -d 100
+# Only the client configs of the larger networks are kept
+for n in 16 32; do
+    gen d100-n$n -n $n -d 100
+    find ./test/d100-n$n -type f ! -name client.dat -delete
+done
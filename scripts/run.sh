@@ -3,11 +3,21 @@ trap "trap - SIGTERM && kill -- -$$" SIGINT SIGTERM EXIT
 
 TYPE=${TYPE:-"release"}
 TESTDIR=${TESTDIR:-"./test/config"}
+# The configs list the nodes on localhost; IPFILE puts them elsewhere
+IPFILE=${IPFILE:-""}
+
+# The .dat files under test/ still carry their secrets; a config written by
+# genconfig has them in a keystore next to it
+keys() {
+    if [ -f $TESTDIR/nodes-$1.keys ]; then
+        echo "-k $TESTDIR/nodes-$1.keys"
+    fi
+}
 
 cargo build --package=node-bft --release
-./target/$TYPE/node-bft -c $TESTDIR/nodes-0.dat -d 500 -i ./scripts/ip_file $1 &> 0.log&
-./target/$TYPE/node-bft -c $TESTDIR/nodes-1.dat -d 500 -i ./scripts/ip_file $1 &> 1.log&
-./target/$TYPE/node-bft -c $TESTDIR/nodes-2.dat -d 500 -i ./scripts/ip_file $1 &> 2.log&
-./target/$TYPE/node-bft -c $TESTDIR/nodes-3.dat -d 500 -i ./scripts/ip_file $1 &> 3.log&
+./target/$TYPE/node-bft -c $TESTDIR/nodes-0.dat $(keys 0) -d 500 ${IPFILE:+-i $IPFILE} $1 &> 0.log&
+./target/$TYPE/node-bft -c $TESTDIR/nodes-1.dat $(keys 1) -d 500 ${IPFILE:+-i $IPFILE} $1 &> 1.log&
+./target/$TYPE/node-bft -c $TESTDIR/nodes-2.dat $(keys 2) -d 500 ${IPFILE:+-i $IPFILE} $1 &> 2.log&
+./target/$TYPE/node-bft -c $TESTDIR/nodes-3.dat $(keys 3) -d 500 ${IPFILE:+-i $IPFILE} $1 &> 3.log&
 
 wait
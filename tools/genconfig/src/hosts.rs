@@ -0,0 +1,89 @@
+// The hosts a network is deployed on. Each line of a hosts file holds the
+// address of a node, in the order of the replicas, and optionally the port
+// it listens to clients on:
+//
+//     10.0.0.1:7000 6000
+//     node-1.randpiper        # at the base ports + 1
+//     [fd00::2]:7000
+//
+// Blank lines, and everything after a `#`, are skipped.
+
+use config::PeerAddr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    /// Where the other nodes reach this one
+    pub addr: PeerAddr,
+    /// The port clients connect to, on the same host
+    pub client_port: u16,
+}
+
+impl Host {
+    /// The client address of the node
+    pub fn client_addr(&self) -> PeerAddr {
+        self.addr.with_port(self.client_port)
+    }
+}
+
+/// Parses a hosts file. Nodes without ports get `base_port + i` and
+/// `client_base_port + i`, as on localhost.
+pub fn parse_hosts(text: &str, base_port: u16, client_base_port: u16) -> Result<Vec<Host>, String> {
+    let mut hosts = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let i = hosts.len() as u16;
+        let err = |e: &dyn std::fmt::Display| format!("line {}: {}", n + 1, e);
+        let mut words = line.split_whitespace();
+        let word = words.next().unwrap();
+        let addr = match word.parse::<PeerAddr>() {
+            Ok(addr) => addr,
+            // A host without a port; an IPv6 address needs brackets then
+            Err(_) if word.contains(':') && !word.starts_with('[') => {
+                format!("[{}]:{}", word, base_port + i)
+                    .parse()
+                    .map_err(|e| err(&e))?
+            }
+            Err(_) => format!("{}:{}", word, base_port + i)
+                .parse()
+                .map_err(|e| err(&e))?,
+        };
+        let client_port = match words.next() {
+            Some(port) => port.parse().map_err(|e| err(&e))?,
+            None => client_base_port + i,
+        };
+        if let Some(extra) = words.next() {
+            return Err(err(&format!("unexpected {:?}", extra)));
+        }
+        hosts.push(Host { addr, client_port });
+    }
+    Ok(hosts)
+}
+
+pub fn read_hosts(path: &str, base_port: u16, client_base_port: u16) -> Result<Vec<Host>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_hosts(&text, base_port, client_base_port).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let text = "# the testnet\n10.0.0.1:7000 6000\n\nnode-1.randpiper  # defaults\nfd00::2\n";
+        let hosts = parse_hosts(text, 4000, 10000).unwrap();
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts[0].addr.to_string(), "10.0.0.1:7000");
+        assert_eq!(hosts[0].client_addr().to_string(), "10.0.0.1:6000");
+        assert_eq!(hosts[1].addr.to_string(), "node-1.randpiper:4001");
+        assert_eq!(hosts[1].client_port, 10001);
+        assert_eq!(hosts[2].addr.to_string(), "[fd00::2]:4002");
+
+        assert!(parse_hosts("10.0.0.1:7000 client", 4000, 10000).is_err());
+        let e = parse_hosts("a:1\nb:1 2 3", 4000, 10000).unwrap_err();
+        assert!(e.starts_with("line 2"), "{}", e);
+    }
+}
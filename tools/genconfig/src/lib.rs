@@ -0,0 +1,340 @@
+// Generating the configs of a whole network. Every random value comes from
+// the one rng passed in: work spread over threads gets an rng of its own for
+// each node, seeded from it in order, so a seeded rng always yields the same
+// keys, beacon queues and shares however the work is scheduled.
+
+use config::{Client, Node};
+use crypto::hash::HashAlgorithm;
+use crypto::rand::{rngs::StdRng, Rng, SeedableRng};
+use crypto::secret::Secret;
+use crypto::Algorithm;
+use crypto::UniformRand;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use types::Replica;
+
+mod hosts;
+pub use hosts::*;
+
+/// What to generate
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub num_nodes: usize,
+    pub num_faults: usize,
+    pub delta: u64,
+    pub block_size: usize,
+    pub payload: usize,
+    pub base_port: u16,
+    pub client_base_port: u16,
+    pub crypto_alg: Algorithm,
+    pub hash_alg: HashAlgorithm,
+    /// The number of beacon shares each node deals ahead of time
+    pub num_shares: usize,
+    /// Print how far along the generation is
+    pub progress: bool,
+    /// Where the nodes run, in order; on localhost if empty
+    pub hosts: Vec<Host>,
+}
+
+impl Options {
+    pub fn new(num_nodes: usize, num_faults: usize) -> Self {
+        Options {
+            num_nodes,
+            num_faults,
+            delta: 50,
+            block_size: 400,
+            payload: 0,
+            base_port: 4000,
+            client_base_port: 10000,
+            crypto_alg: Algorithm::ED25519,
+            hash_alg: HashAlgorithm::SHA256,
+            num_shares: 100,
+            progress: false,
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Where node `i` runs
+    pub fn host(&self, i: usize) -> Host {
+        self.hosts.get(i).cloned().unwrap_or_else(|| Host {
+            addr: format!("127.0.0.1:{}", self.base_port + (i as u16))
+                .parse()
+                .unwrap(),
+            client_port: self.client_base_port + (i as u16),
+        })
+    }
+}
+
+/// The configs of every node, with their secrets, and of the client
+pub struct Testnet {
+    pub nodes: Vec<Node>,
+    pub client: Client,
+}
+
+/// Generates a network of `n` nodes tolerating `f` faults on localhost, the
+/// same one for the same seed. The keys are for tests only.
+pub fn generate_testnet(n: usize, f: usize, seed: u64) -> Testnet {
+    generate(&Options::new(n, f), &mut StdRng::seed_from_u64(seed))
+}
+
+/// Generates the whole network in memory
+pub fn generate(opts: &Options, rng: &mut StdRng) -> Testnet {
+    let Testnet { nodes, client } = generate_public(opts, rng);
+    let nodes = deal_shares(opts, nodes, rng, |node| node);
+    Testnet { nodes, client }
+}
+
+/// Runs `f` over `items` on every core, with an rng for each item drawn
+/// from `rng`, and returns the results in order
+pub fn par_map<I, T, F>(
+    opts: &Options,
+    stage: &str,
+    items: Vec<I>,
+    rng: &mut StdRng,
+    f: F,
+) -> Vec<T>
+where
+    I: Send,
+    T: Send,
+    F: Fn(I, &mut StdRng) -> T + Sync,
+{
+    let total = items.len();
+    let work: Vec<_> = items
+        .into_iter()
+        .map(|item| (item, rng.gen::<[u8; 32]>()))
+        .collect();
+    let work = Mutex::new(work.into_iter().enumerate());
+    let done = AtomicUsize::new(0);
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(total);
+    let mut results: Vec<(usize, T)> = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let next = work.lock().unwrap().next();
+                        let (idx, (item, seed)) = match next {
+                            Some(next) => next,
+                            None => return results,
+                        };
+                        results.push((idx, f(item, &mut StdRng::from_seed(seed))));
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if opts.progress {
+                            println!("{}: {}/{}", stage, done, total);
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("a generating thread panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, t)| t).collect()
+}
+
+/// Generates everything but the shares the nodes deal ahead of time, which
+/// make up most of the configs
+pub fn generate_public(opts: &Options, rng: &mut StdRng) -> Testnet {
+    let num_nodes = opts.num_nodes;
+    let num_faults = opts.num_faults;
+    let scheme =
+        crypto::scheme::lookup(&opts.crypto_alg).expect("no signature scheme for the algorithm");
+
+    let mut client = Client::new();
+    client.block_size = opts.block_size;
+    client.crypto_alg = opts.crypto_alg.clone();
+    client.num_nodes = num_nodes;
+    client.num_faults = num_faults;
+
+    let mut node: Vec<Node> = Vec::with_capacity(num_nodes);
+
+    let mut pk = HashMap::new();
+    let mut ip = HashMap::new();
+    let mut bi_pp = HashMap::new();
+
+    for i in 0..num_nodes {
+        node.push(Node::new());
+
+        node[i].delta = opts.delta;
+        node[i].id = i as Replica;
+        node[i].num_nodes = num_nodes;
+        node[i].num_faults = num_faults;
+        node[i].block_size = opts.block_size;
+        node[i].payload = opts.payload;
+        let host = opts.host(i);
+        node[i].client_port = host.client_port;
+
+        node[i].crypto_alg = opts.crypto_alg.clone();
+        node[i].hash_alg = opts.hash_alg;
+        let (public, secret) = scheme.generate_from(rng).expect("failed to generate a key");
+        pk.insert(i as Replica, public);
+        node[i].secret_key_bytes = Secret::new(secret);
+        client.net_map.insert(i as Replica, host.client_addr());
+        ip.insert(i as Replica, host.addr);
+    }
+
+    let bi_p = par_map(
+        opts,
+        "Biaccumulator setup",
+        vec![(); num_nodes],
+        rng,
+        |_, rng| crypto::Biaccumulator381::setup(num_nodes, rng).unwrap(),
+    );
+    for (i, bi_p) in bi_p.into_iter().enumerate() {
+        bi_pp.insert(i as Replica, bi_p.get_public_params());
+        node[i].bi_p = Some(Secret::new(bi_p));
+    }
+
+    let rand_beacon_parameter = crypto::EVSS381::setup(num_faults, rng).unwrap();
+
+    for i in 0..num_nodes {
+        node[i].pk_map = pk.clone();
+        node[i].net_map = ip.clone();
+        node[i].bi_pp_map = bi_pp.clone();
+        node[i].rand_beacon_parameter = Some(rand_beacon_parameter.clone());
+    }
+
+    let queue = par_map(opts, "Beacon queue", vec![(); num_nodes], rng, |_, rng| {
+        deal(&rand_beacon_parameter, num_nodes, rng).0
+    });
+    for (i, pre_shares) in queue.into_iter().enumerate() {
+        for k in 0..num_nodes {
+            let q = vec![pre_shares[k].clone(); num_nodes + num_faults];
+            node[k].rand_beacon_queue.insert(i as Replica, q.into());
+        }
+    }
+
+    client.server_pk = pk;
+
+    Testnet {
+        nodes: node,
+        client,
+    }
+}
+
+/// Deals the shares of every node in `nodes` ahead of time, and hands each
+/// node to `then` as soon as it is done. Once a node is written out, it need
+/// not be kept in memory.
+pub fn deal_shares<T, F>(opts: &Options, nodes: Vec<Node>, rng: &mut StdRng, then: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(Node) -> T + Sync,
+{
+    par_map(opts, "Beacon shares", nodes, rng, |mut node, rng| {
+        let params = node
+            .rand_beacon_parameter
+            .clone()
+            .expect("no beacon parameters in the config");
+        let table = (0..opts.num_shares)
+            .map(|_| {
+                let (mut shares, commit) = deal(&params, opts.num_nodes, rng);
+                (std::mem::take(&mut *shares), commit)
+            })
+            .collect();
+        node.rand_beacon_shares = Secret::new(table);
+        then(node)
+    })
+}
+
+/// Deals a random secret to `n` nodes: a share for each, and the commitment
+fn deal(
+    params: &crypto::EVSSParams381,
+    n: usize,
+    rng: &mut StdRng,
+) -> (Secret<Vec<crypto::EVSSShare381>>, crypto::EVSSCommit381) {
+    let poly = crypto::EVSS381::commit(params, crypto::F381::rand(rng), rng).unwrap();
+    let shares = (0..n)
+        .map(|j| {
+            crypto::EVSS381::get_share(crypto::F381::from((j + 1) as u16), params, &poly, rng)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    (Secret::new(shares), poly.get_commit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded() {
+        let mut opts = Options::new(4, 1);
+        opts.num_shares = 2;
+        let a = generate(&opts, &mut StdRng::seed_from_u64(7));
+        let b = generate(&opts, &mut StdRng::seed_from_u64(7));
+        let c = generate(&opts, &mut StdRng::seed_from_u64(8));
+        for node in &a.nodes {
+            node.validate().unwrap();
+        }
+        assert_eq!(a.nodes[0].pk_map, b.nodes[0].pk_map);
+        assert_ne!(a.nodes[0].pk_map, c.nodes[0].pk_map);
+        // The files come out the same too
+        assert_eq!(
+            bincode::serialize(&a.nodes[2]).unwrap(),
+            bincode::serialize(&b.nodes[2]).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_string(&a.client).unwrap(),
+            serde_json::to_string(&b.client).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_from_files() {
+        use config::{Binary, ConfigError};
+        let dir = std::env::temp_dir().join(format!("randpiper-genconfig-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut opts = Options::new(3, 1);
+        opts.num_shares = 2;
+        let mut net = generate(&opts, &mut StdRng::seed_from_u64(7));
+
+        let json = dir.join("nodes-0.json");
+        std::fs::write(&json, serde_json::to_vec(&net.nodes[0]).unwrap()).unwrap();
+        let bin = dir.join("nodes-1.dat");
+        std::fs::write(&bin, net.nodes[1].to_bin().unwrap()).unwrap();
+        let client = dir.join("client.json");
+        std::fs::write(&client, serde_json::to_vec(&net.client).unwrap()).unwrap();
+        for (path, id) in [(&json, 0), (&bin, 1)].iter() {
+            let node = Node::load(path).unwrap();
+            assert_eq!(node.id, *id);
+            assert_eq!(node.pk_map, net.nodes[0].pk_map);
+            node.validate().unwrap();
+        }
+        assert_eq!(Client::load(&client).unwrap(), net.client);
+
+        // A config that does not hold together is refused with its file
+        net.nodes[2].num_nodes = 5;
+        let bad = dir.join("nodes-2.json");
+        std::fs::write(&bad, serde_json::to_vec(&net.nodes[2]).unwrap()).unwrap();
+        assert!(matches!(Node::load(&bad), Err(ConfigError::Invalid(path, _)) if path == bad));
+        assert!(matches!(
+            Client::load(dir.join("missing.json")),
+            Err(ConfigError::Io(_, _))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn par_map_keeps_order() {
+        let opts = Options::new(4, 1);
+        let items: Vec<usize> = (0..50).collect();
+        let run = |seed| {
+            par_map(
+                &opts,
+                "test",
+                items.clone(),
+                &mut StdRng::seed_from_u64(seed),
+                |i, rng| (i, rng.gen::<u64>()),
+            )
+        };
+        let a = run(7);
+        assert!(a.iter().enumerate().all(|(i, (j, _))| i == *j));
+        assert_eq!(a, run(7));
+    }
+}
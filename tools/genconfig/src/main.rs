@@ -2,29 +2,31 @@
 // protocol.
 
 use clap::{load_yaml, App};
-use config::{Client, Node};
+use config::{Binary, Format};
+use crypto::hash::HashAlgorithm;
 use crypto::rand::{rngs::StdRng, SeedableRng};
 use crypto::Algorithm;
-use crypto::UniformRand;
-use crypto_lib::{ed25519, secp256k1};
-use std::collections::HashMap;
+use serde::Serialize;
 use types::Replica;
 use util::io::*;
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let m = App::from_yaml(yaml).get_matches();
-    let num_nodes: usize = m
-        .value_of("num_nodes")
-        .expect("number of nodes not specified")
-        .parse::<usize>()
-        .expect("unable to convert number of nodes into a number");
-    let num_faults: usize = match m.value_of("num_faults") {
-        Some(x) => x
-            .parse::<usize>()
-            .expect("unable to convert number of faults into a number"),
-        None => (num_nodes - 1) / 2,
-    };
+    if let Some(files) = m.values_of("migrate") {
+        for f in files {
+            match config::container::migrate(std::path::Path::new(f)) {
+                Ok(Some(kind)) => println!("{}: migrated the {:?} config", f, kind),
+                Ok(None) => println!("{}: already in the current format", f),
+                // The cause alone, as the error also names the file
+                Err(e) => match std::error::Error::source(&e) {
+                    Some(cause) => panic!("failed to migrate {}: {}", f, cause),
+                    None => panic!("failed to migrate {}: {}", f, e),
+                },
+            }
+        }
+        return;
+    }
     let delay: u64 = m
         .value_of("delay")
         .expect("delay value not specified")
@@ -45,190 +47,184 @@ fn main() {
         .expect("no client_base_port specified")
         .parse::<u16>()
         .expect("unable to parse client_base_port into an integer");
+    let hosts = match m.value_of("hosts") {
+        Some(f) => genconfig::read_hosts(f, base_port, client_base_port)
+            .unwrap_or_else(|e| panic!("invalid hosts file: {}", e)),
+        None => Vec::new(),
+    };
+    let num_nodes: usize = match m.value_of("num_nodes") {
+        Some(x) => x
+            .parse::<usize>()
+            .expect("unable to convert number of nodes into a number"),
+        None => hosts.len(),
+    };
+    if num_nodes == 0 {
+        panic!("no nodes to generate; give -n or a hosts file with at least one host");
+    }
+    if !hosts.is_empty() && hosts.len() != num_nodes {
+        panic!("{} hosts for {} nodes", hosts.len(), num_nodes);
+    }
+    let num_faults: usize = match m.value_of("num_faults") {
+        Some(x) => x
+            .parse::<usize>()
+            .expect("unable to convert number of faults into a number"),
+        None => (num_nodes - 1) / 2,
+    };
     let t: Algorithm = m
-        .value_of("algorithm")
+        .value_of("crypto")
         .unwrap_or("ED25519")
         .parse::<Algorithm>()
         .unwrap_or(Algorithm::ED25519);
-    let out = m.value_of("out_type").unwrap_or("json");
+    let hash_alg: HashAlgorithm = m.value_of("hash").unwrap_or("SHA256").parse().unwrap();
+    let out = match m.value_of("out_type").unwrap_or("json") {
+        "json" => Format::Json,
+        "binary" => Format::Bin,
+        "toml" => Format::Toml,
+        "yaml" => Format::Yaml,
+        other => panic!("unknown output type {}", other),
+    };
     let target = m
         .value_of("target")
         .expect("target directory for the config not specified");
     let payload: usize = m.value_of("payload").unwrap_or("0").parse().unwrap();
-    let mut client = Client::new();
-    client.block_size = blocksize;
-    client.crypto_alg = t.clone();
-    client.num_nodes = num_nodes;
-    client.num_faults = num_faults;
-
-    let mut node: Vec<Node> = Vec::with_capacity(num_nodes);
-
-    let mut pk = HashMap::new();
-    let mut ip = HashMap::new();
-    let mut bi_pp = HashMap::new();
-
-    for i in 0..num_nodes {
-        node.push(Node::new());
-
-        node[i].delta = delay;
-        node[i].id = i as Replica;
-        node[i].num_nodes = num_nodes;
-        node[i].num_faults = num_faults;
-        node[i].block_size = blocksize;
-        node[i].payload = payload;
-        node[i].client_port = client_base_port + (i as u16);
-
-        node[i].crypto_alg = t.clone();
-        match t {
-            Algorithm::ED25519 => {
-                let kp = ed25519::Keypair::generate();
-                pk.insert(i as Replica, kp.public().encode().to_vec());
-                node[i].secret_key_bytes = kp.encode().to_vec();
-            }
-            Algorithm::SECP256K1 => {
-                let kp = secp256k1::Keypair::generate();
-                pk.insert(i as Replica, kp.public().encode().to_vec());
-                node[i].secret_key_bytes = kp.secret().to_bytes().to_vec();
-            }
-            _ => (),
-        };
-        ip.insert(
-            i as Replica,
-            format!("{}:{}", "127.0.0.1", base_port + (i as u16)),
-        );
-        client.net_map.insert(
-            i as Replica,
-            format!("127.0.0.1:{}", client_base_port + (i as u16)),
-        );
-
-        node[i].bi_p =
-            Some(crypto::Biaccumulator381::setup(num_nodes, &mut StdRng::from_entropy()).unwrap());
-        bi_pp.insert(
-            i as Replica,
-            node[i].bi_p.as_ref().unwrap().get_public_params(),
-        );
-    }
-
-    let rng = &mut StdRng::from_entropy();
-    let rand_beacon_parameter = crypto::EVSS381::setup(num_faults, rng).unwrap();
-
-    for i in 0..num_nodes {
-        node[i].pk_map = pk.clone();
-        node[i].net_map = ip.clone();
-        node[i].bi_pp_map = bi_pp.clone();
-        node[i].rand_beacon_parameter = Some(rand_beacon_parameter.clone());
-    }
-
-    println!("Begin generating queue...");
-
-    for i in 0..num_nodes {
-        for j in 0..num_nodes {
-            node[j].rand_beacon_queue.insert(
-                i as Replica,
-                std::collections::VecDeque::with_capacity(num_nodes + num_faults),
+    let passphrase = m
+        .value_of("passphrase_file")
+        .map(|f| config::read_passphrase(f).expect("failed to read the passphrase file"));
+    let seed: Option<u64> = m
+        .value_of("seed")
+        .map(|s| s.parse().expect("unable to parse the seed into a number"));
+    let mut opts = genconfig::Options::new(num_nodes, num_faults);
+    opts.delta = delay;
+    opts.block_size = blocksize;
+    opts.payload = payload;
+    opts.base_port = base_port;
+    opts.client_base_port = client_base_port;
+    opts.crypto_alg = t;
+    opts.hash_alg = hash_alg;
+    opts.hosts = hosts;
+    let bundle = m.is_present("bundle");
+    let mut rng = match seed {
+        Some(seed) => {
+            println!(
+                "[WARN] The keys come from the seed {}; use them for tests only.",
+                seed
             );
+            StdRng::seed_from_u64(seed)
         }
-        let poly =
-            crypto::EVSS381::commit(&rand_beacon_parameter, crypto::F381::rand(rng), rng).unwrap();
-        let pre_shares: Vec<_> = (0..num_nodes)
-            .map(|j| {
-                crypto::EVSS381::get_share(
-                    crypto::F381::from((j + 1) as u16),
-                    &rand_beacon_parameter,
-                    &poly,
-                    rng,
-                )
-                .unwrap()
-            })
-            .collect();
-        for _ in 0..num_nodes + num_faults {
-            for k in 0..num_nodes {
-                node[k]
-                    .rand_beacon_queue
-                    .get_mut(&(i as Replica))
-                    .unwrap()
-                    .push_back(pre_shares[k].clone());
-            }
+        None => StdRng::from_entropy(),
+    };
+    opts.progress = true;
+    let genconfig::Testnet { nodes, client } = genconfig::generate_public(&opts, &mut rng);
+
+    // The public keys and beacon parameters are the same for every node, so
+    // they are written once and every node config refers to them
+    let ext = extension(out);
+    let public = format!("public.{}", ext);
+    write_config(
+        out,
+        format!("{}/{}", target, public),
+        &config::Public::from_node(&nodes[0]),
+    );
+    let ips: String = (0..num_nodes)
+        .map(|i| format!("{}\n", nodes[0].net_map[&(i as Replica)]))
+        .collect();
+
+    // Write the files of each node as soon as its shares are dealt. The
+    // secrets go into a keystore next to the config, so that the config
+    // itself can be shared.
+    genconfig::deal_shares(&opts, nodes, &mut rng, |mut node| {
+        let i = node.id;
+        node.validate()
+            .unwrap_or_else(|e| panic!("invalid config for node {}: {}", i, e));
+        // A bundle holds everything a node needs, to be copied to its host
+        let dir = if bundle {
+            format!("{}/node-{}", target, i)
+        } else {
+            target.to_string()
+        };
+        if bundle {
+            std::fs::create_dir_all(&dir).expect("failed to create the bundle directory");
+            std::fs::copy(
+                format!("{}/{}", target, public),
+                format!("{}/{}", dir, public),
+            )
+            .expect("failed to copy the public material into the bundle");
         }
-    }
+        let keys = node.take_keys();
+        keys.write(
+            &format!("{}/nodes-{}.keys", dir, i),
+            passphrase.as_ref().map(|p| p.as_str()),
+        )
+        .expect("failed to write the keystore");
+        node.refer_to_public(&public);
+        write_config(out, format!("{}/nodes-{}.{}", dir, i, ext), &node);
+        if bundle {
+            std::fs::write(format!("{}/ips_file", dir), &ips).expect("failed to write the ip file");
+            let script = launch_script(i, num_nodes, ext, passphrase.is_some());
+            write_script(&format!("{}/run.sh", dir), &script);
+        }
+    });
 
-    println!("Begin generating...");
+    // Write the client file
+    write_config(out, format!("{}/client.{}", target, ext), &client);
+    client
+        .validate()
+        .unwrap_or_else(|e| panic!("invalid client config: {}", e));
+}
 
-    for i in 0..num_nodes {
-        let mut vec = Vec::with_capacity(100);
-        for time in 0..100 {
-            println!("{}:{}", i, time);
-            let poly =
-                crypto::EVSS381::commit(&rand_beacon_parameter, crypto::F381::rand(rng), rng)
-                    .unwrap();
-            let poly_commit = poly.get_commit();
-            let pre_shares: Vec<_> = (0..num_nodes)
-                .map(|j| {
-                    crypto::EVSS381::get_share(
-                        crypto::F381::from((j + 1) as u16),
-                        &rand_beacon_parameter,
-                        &poly,
-                        rng,
-                    )
-                    .unwrap()
-                })
-                .collect();
-            let shares: Vec<_> = (0..num_nodes)
-                .map(|i| std::collections::VecDeque::from(vec![pre_shares[i].clone(); num_nodes]))
-                .collect();
-            let commits = vec![poly_commit; num_nodes];
-            vec.push((shares, commits));
-        }
-        node[i].rand_beacon_shares = vec;
-    }
+/// Starts the node from its bundle, wherever the bundle is copied to
+fn launch_script(i: Replica, n: usize, ext: &str, encrypted: bool) -> String {
+    let passphrase = if encrypted {
+        " --passphrase-file \"${PASSPHRASE_FILE:?set PASSPHRASE_FILE}\""
+    } else {
+        ""
+    };
+    format!(
+        "#!/bin/sh\n\
+         # Starts node {i} of {n}. Set NODE_BFT to the node-bft binary if it is not\n\
+         # on the PATH; the arguments are passed on, e.g. -d 280.\n\
+         NODE_BFT=${{NODE_BFT:-node-bft}}\n\
+         # A relative path is taken from where the script is started\n\
+         case \"$NODE_BFT\" in\n\
+         /*) ;;\n\
+         */*) NODE_BFT=\"$PWD/$NODE_BFT\" ;;\n\
+         esac\n\
+         cd \"$(dirname \"$0\")\" || exit 1\n\
+         exec \"$NODE_BFT\" -c nodes-{i}.{ext} -k nodes-{i}.keys{passphrase} -i ips_file \"$@\"\n",
+        i = i,
+        n = n,
+        ext = ext,
+        passphrase = passphrase,
+    )
+}
 
-    client.server_pk = pk;
+fn write_script(path: &str, script: &str) {
+    std::fs::write(path, script).expect("failed to write the launch script");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .expect("failed to make the launch script executable");
+    }
+}
 
-    // Write all the files
-    for i in 0..num_nodes {
-        match out {
-            "json" => {
-                let filename = format!("{}/nodes-{}.json", target, i);
-                write_json(filename, &node[i]);
-            }
-            "binary" => {
-                let filename = format!("{}/nodes-{}.dat", target, i);
-                write_bin(filename, &node[i]);
-            }
-            "toml" => {
-                let filename = format!("{}/nodes-{}.toml", target, i);
-                write_toml(filename, &node[i]);
-            }
-            "yaml" => {
-                let filename = format!("{}/nodes-{}.yml", target, i);
-                write_yaml(filename, &node[i]);
-            }
-            _ => (),
-        }
-        node[i].validate().expect("failed to validate node config");
+fn extension(out: Format) -> &'static str {
+    match out {
+        Format::Bin => "dat",
+        Format::Toml => "toml",
+        Format::Yaml => "yml",
+        Format::Json => "json",
     }
+}
 
-    // Write the client file
+fn write_config<T: Serialize + Binary>(out: Format, filename: String, obj: &T) {
     match out {
-        "json" => {
-            let filename = format!("{}/client.json", target);
-            write_json(filename, &client);
-        }
-        "binary" => {
-            let filename = format!("{}/client.dat", target);
-            write_bin(filename, &client);
+        Format::Bin => {
+            let bytes = obj.to_bin().expect("failed to encode the config");
+            std::fs::write(filename, bytes).expect("failed to write the config");
         }
-        "toml" => {
-            let filename = format!("{}/client.toml", target);
-            write_toml(filename, &client);
-        }
-        "yaml" => {
-            let filename = format!("{}/client.yml", target);
-            write_yaml(filename, &client);
-        }
-        _ => (),
+        Format::Toml => write_toml(filename, obj),
+        Format::Yaml => write_yaml(filename, obj),
+        Format::Json => write_json(filename, obj),
     }
-    client
-        .validate()
-        .expect("failed to validate the client config");
 }
@@ -0,0 +1,69 @@
+// A tool that reads the logs of all the nodes of a run and checks that they
+// agree on the beacon, and that no node fell out of sync.
+
+use clap::{load_yaml, App};
+
+mod parser;
+use parser::NodeLog;
+
+mod report;
+
+fn main() {
+    let yaml = load_yaml!("cli.yml");
+    let m = App::from_yaml(yaml).get_matches();
+    let files: Vec<&str> = m.values_of("logs").expect("no log files given").collect();
+    let verbose = m.is_present("verbose");
+
+    let mut logs = Vec::with_capacity(files.len());
+    for f in files {
+        let log = NodeLog::from_file(f.to_string())
+            .unwrap_or_else(|e| panic!("failed to read the log file {}: {}", f, e));
+        println!(
+            "{}: node {:?}, {} epochs, last epoch {}",
+            log.name,
+            log.id,
+            log.epochs.len(),
+            log.last_epoch
+        );
+        logs.push(log);
+    }
+
+    let beacons = report::agreed_beacons(&logs);
+    if verbose {
+        for (e, b) in &beacons {
+            match b {
+                Some(b) => println!("Epoch {}: {}", e, b),
+                None => println!("Epoch {}: <diverged>", e),
+            }
+        }
+    }
+
+    let issues = report::check(&logs);
+    for issue in &issues {
+        println!("[WARN] {}", issue);
+    }
+
+    let stats = report::latencies(&logs);
+    for ((node, kind, name), s) in &stats {
+        if node == "all" {
+            println!(
+                "{} {}: count {}, mean {:.2} ms, median {} ms, p99 {} ms, max {} ms",
+                kind, name, s.count, s.mean, s.median, s.p99, s.max
+            );
+        }
+    }
+    if let Some(csv) = m.value_of("csv") {
+        report::write_csv(csv.to_string(), &stats)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", csv, e));
+    }
+
+    println!(
+        "Checked {} epochs across {} logs: {} issues found.",
+        beacons.len(),
+        logs.len(),
+        issues.len()
+    );
+    if !issues.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,180 @@
+// Parses the output of a single node-bft process (the `0.log`, `1.log`, ...
+// files collected by `scripts/aws/do_test.sh`) into per epoch records.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, prelude::*};
+
+pub type Epoch = u64;
+
+#[derive(Debug, Clone, Default)]
+pub struct EpochRecord {
+    /// The beacon output printed at the end of the epoch, as a hex string
+    pub beacon: Option<String>,
+    /// The leader announced when the node entered the epoch
+    pub leader: Option<u16>,
+    /// (phase, ms) for every phase handled in the epoch
+    pub phases: Vec<(String, u64)>,
+    /// (message, ms) for every protocol message handled in the epoch
+    pub messages: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeLog {
+    pub name: String,
+    pub id: Option<u16>,
+    pub epochs: BTreeMap<Epoch, EpochRecord>,
+    /// The epoch the node was in when the log ends
+    pub last_epoch: Epoch,
+}
+
+impl NodeLog {
+    pub fn from_file(filename: String) -> io::Result<NodeLog> {
+        let f = File::open(&filename)?;
+        Ok(parse(filename, io::BufReader::new(f)))
+    }
+
+    pub fn beacon(&self, e: Epoch) -> Option<&String> {
+        self.epochs.get(&e).and_then(|r| r.beacon.as_ref())
+    }
+
+    pub fn leader(&self, e: Epoch) -> Option<u16> {
+        self.epochs.get(&e).and_then(|r| r.leader)
+    }
+}
+
+pub fn parse<R: BufRead>(name: String, reader: R) -> NodeLog {
+    let mut log = NodeLog {
+        name,
+        id: None,
+        epochs: BTreeMap::new(),
+        last_epoch: 0,
+    };
+    let mut epoch: Epoch = 0;
+    // The reactor moves to the next epoch in the middle of the End phase, so
+    // a phase duration belongs to the epoch the phase started in.
+    let mut phase_epoch: Epoch = 0;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => continue,
+        };
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Rand Beacon: ") {
+            if let Some(beacon) = parse_beacon(rest) {
+                log.epochs.entry(epoch).or_default().beacon = Some(beacon);
+            }
+            continue;
+        }
+        let (id, rest) = match split_id(line) {
+            Some(x) => x,
+            None => continue,
+        };
+        if log.id.is_none() {
+            log.id = Some(id);
+        }
+        if let Some(rest) = rest.strip_prefix("cx.epoch ") {
+            // cx.epoch {e}. Leader is {l}.
+            let mut parts = rest.trim_end_matches('.').split(". Leader is ");
+            let e = parts.next().and_then(|e| e.parse().ok());
+            let l = parts.next().and_then(|l| l.parse().ok());
+            if let Some(e) = e {
+                epoch = e;
+                log.epochs.entry(epoch).or_default().leader = l;
+            }
+        } else if let Some(rest) = rest.strip_prefix("Phase ") {
+            match split_took(rest) {
+                Some((phase, ms)) => log
+                    .epochs
+                    .entry(phase_epoch)
+                    .or_default()
+                    .phases
+                    .push((phase, ms)),
+                None => phase_epoch = epoch,
+            }
+        } else if let Some(rest) = rest.strip_prefix("Message ") {
+            if let Some((msg, ms)) = split_took(rest) {
+                log.epochs
+                    .entry(epoch)
+                    .or_default()
+                    .messages
+                    .push((msg, ms));
+            }
+        }
+    }
+    log.last_epoch = epoch;
+    log
+}
+
+/// Splits `{id}: {rest}`
+fn split_id(line: &str) -> Option<(u16, &str)> {
+    let idx = line.find(": ")?;
+    let id = line[..idx].parse().ok()?;
+    Some((id, &line[idx + 2..]))
+}
+
+/// Splits `"{name}" took {ms} ms.`
+fn split_took(rest: &str) -> Option<(String, u64)> {
+    let idx = rest.find(" took ")?;
+    let name = rest[..idx].trim_matches('"').to_string();
+    let ms = rest[idx + 6..].trim_end_matches(" ms.").parse().ok()?;
+    Some((name, ms))
+}
+
+/// The beacon is printed with `{:x?}`, i.e. `[45, 5e, 9, ...]`
+fn parse_beacon(s: &str) -> Option<String> {
+    let s = s.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut hex = String::with_capacity(64);
+    for byte in s.split(',') {
+        let b = u8::from_str_radix(byte.trim(), 16).ok()?;
+        hex.push_str(&format!("{:02x}", b));
+    }
+    Some(hex)
+}
+
+pub fn is_zero_beacon(beacon: &str) -> bool {
+    beacon.chars().all(|c| c == '0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"{2: "127.0.0.1:7002", 1: "127.0.0.1:7001", 0: "0.0.0.0:7000"}
+1: Phase "End"
+Rand Beacon: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+1: cx.epoch 1. Leader is 1.
+1: Phase "End" took 2 ms.
+1: Received "Reconstruct".
+1: Message "Reconstruct" took 0 ms.
+1: Phase "Propose"
+1: Phase "Propose" took 12 ms.
+1: Phase "End"
+Rand Beacon: [45, 5e, a9, 62, db, 2e, 5e, 1b, 77, 9, 5e, 57, cb, 5c, 29, bf, d1, 43, 10, 7f, 3, c2, d8, 23, d9, f5, e0, d1, 14, 24, f, 5b]
+1: cx.epoch 2. Leader is 2.
+1: Phase "End" took 3 ms.
+"#;
+
+    #[test]
+    fn parse_log() {
+        let log = parse("1.log".to_string(), LOG.as_bytes());
+        assert_eq!(log.id, Some(1));
+        assert_eq!(log.last_epoch, 2);
+        assert!(is_zero_beacon(log.beacon(0).unwrap()));
+        assert_eq!(
+            log.beacon(1).unwrap(),
+            "455ea962db2e5e1b77095e57cb5c29bfd143107f03c2d823d9f5e0d114240f5b"
+        );
+        assert_eq!(log.leader(1), Some(1));
+        assert_eq!(log.leader(2), Some(2));
+        assert_eq!(log.epochs[&0].phases, vec![("End".to_string(), 2)]);
+        assert_eq!(
+            log.epochs[&1].phases,
+            vec![("Propose".to_string(), 12), ("End".to_string(), 3)]
+        );
+        assert_eq!(
+            log.epochs[&1].messages,
+            vec![("Reconstruct".to_string(), 0)]
+        );
+    }
+}
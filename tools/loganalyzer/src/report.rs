@@ -0,0 +1,260 @@
+// Cross checks the parsed node logs and computes latency statistics.
+
+use super::parser::{is_zero_beacon, Epoch, NodeLog};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, prelude::*};
+
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    // epoch, (node, beacon) for every node that printed one
+    BeaconDivergence(Epoch, Vec<(String, String)>),
+    // epoch, (node, leader) for every node that announced one
+    LeaderDivergence(Epoch, Vec<(String, u16)>),
+    // node, epoch
+    MissingBeacon(String, Epoch),
+    // node, epoch
+    ZeroBeacon(String, Epoch),
+    // node, last epoch of the node, last epoch among all nodes
+    Stalled(String, Epoch, Epoch),
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Issue::BeaconDivergence(e, beacons) => {
+                write!(f, "epoch {}: beacon diverges:", e)?;
+                for (node, beacon) in beacons {
+                    write!(f, " {}={}", node, beacon)?;
+                }
+                Ok(())
+            }
+            Issue::LeaderDivergence(e, leaders) => {
+                write!(f, "epoch {}: leader diverges:", e)?;
+                for (node, leader) in leaders {
+                    write!(f, " {}={}", node, leader)?;
+                }
+                Ok(())
+            }
+            Issue::MissingBeacon(node, e) => write!(f, "epoch {}: no beacon from {}", e, node),
+            Issue::ZeroBeacon(node, e) => write!(f, "epoch {}: all-zero beacon from {}", e, node),
+            Issue::Stalled(node, last, max) => write!(
+                f,
+                "{} stopped at epoch {} while other nodes reached epoch {}",
+                node, last, max
+            ),
+        }
+    }
+}
+
+/// Returns every inconsistency found across the logs.
+///
+/// The beacon of epoch 0 is always zero since nothing has been dealt yet, and
+/// the last epoch of each log is usually cut short by the timeout, so neither
+/// is reported.
+pub fn check(logs: &[NodeLog]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if logs.is_empty() {
+        return issues;
+    }
+    let max_last = logs.iter().map(|l| l.last_epoch).max().unwrap();
+    let min_last = logs.iter().map(|l| l.last_epoch).min().unwrap();
+    // Every epoch all the nodes went through, even one no log mentions
+    let mut epochs: BTreeSet<Epoch> = (1..min_last).collect();
+    for log in logs {
+        epochs.extend(log.epochs.keys().cloned());
+        // A node one epoch behind is only the timeout hitting it first
+        if log.last_epoch + 1 < max_last {
+            issues.push(Issue::Stalled(log.name.clone(), log.last_epoch, max_last));
+        }
+    }
+    for e in epochs {
+        let mut beacons = Vec::new();
+        let mut leaders = Vec::new();
+        for log in logs {
+            match log.beacon(e) {
+                Some(b) => {
+                    if e != 0 && is_zero_beacon(b) {
+                        issues.push(Issue::ZeroBeacon(log.name.clone(), e));
+                    }
+                    beacons.push((log.name.clone(), b.clone()));
+                }
+                None => {
+                    if e < min_last {
+                        issues.push(Issue::MissingBeacon(log.name.clone(), e));
+                    }
+                }
+            }
+            if let Some(l) = log.leader(e) {
+                leaders.push((log.name.clone(), l));
+            }
+        }
+        if beacons.iter().any(|b| b.1 != beacons[0].1) {
+            issues.push(Issue::BeaconDivergence(e, beacons));
+        }
+        if leaders.iter().any(|l| l.1 != leaders[0].1) {
+            issues.push(Issue::LeaderDivergence(e, leaders));
+        }
+    }
+    issues
+}
+
+/// The beacon agreed on by all the nodes for every epoch, or None if they
+/// disagree
+pub fn agreed_beacons(logs: &[NodeLog]) -> BTreeMap<Epoch, Option<String>> {
+    let mut map: BTreeMap<Epoch, Option<String>> = BTreeMap::new();
+    for log in logs {
+        for (e, record) in &log.epochs {
+            if let Some(b) = &record.beacon {
+                let entry = map.entry(*e).or_insert_with(|| Some(b.clone()));
+                if entry.as_ref() != Some(b) {
+                    *entry = None;
+                }
+            }
+        }
+    }
+    map
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub count: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub p99: u64,
+}
+
+impl Stats {
+    pub fn from_samples(mut samples: Vec<u64>) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let count = samples.len();
+        let sum: u64 = samples.iter().sum();
+        Some(Stats {
+            count,
+            min: samples[0],
+            max: samples[count - 1],
+            mean: sum as f64 / count as f64,
+            median: samples[count / 2],
+            p99: samples[(count * 99).div_ceil(100) - 1],
+        })
+    }
+}
+
+/// Latency statistics keyed by (node, kind, name) where kind is either
+/// `phase` or `message`, and node is `all` for the numbers over every log
+pub fn latencies(logs: &[NodeLog]) -> BTreeMap<(String, &'static str, String), Stats> {
+    let mut samples: BTreeMap<(String, &'static str, String), Vec<u64>> = BTreeMap::new();
+    for log in logs {
+        for record in log.epochs.values() {
+            for (kind, list) in &[("phase", &record.phases), ("message", &record.messages)] {
+                for (name, ms) in list.iter() {
+                    for node in &[log.name.clone(), "all".to_string()] {
+                        samples
+                            .entry((node.clone(), kind, name.clone()))
+                            .or_default()
+                            .push(*ms);
+                    }
+                }
+            }
+        }
+    }
+    samples
+        .into_iter()
+        .filter_map(|(k, v)| Stats::from_samples(v).map(|s| (k, s)))
+        .collect()
+}
+
+pub fn write_csv(
+    filename: String,
+    stats: &BTreeMap<(String, &'static str, String), Stats>,
+) -> io::Result<()> {
+    let mut f = File::create(filename)?;
+    writeln!(f, "node,kind,name,count,min,mean,median,p99,max")?;
+    for ((node, kind, name), s) in stats {
+        writeln!(
+            f,
+            "{},{},{},{},{},{:.2},{},{},{}",
+            node, kind, name, s.count, s.min, s.mean, s.median, s.p99, s.max
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn node(id: u16, beacons: &[&str]) -> NodeLog {
+        let mut log = String::new();
+        for (e, b) in beacons.iter().enumerate() {
+            log.push_str(&format!("{}: Phase \"End\"\n", id));
+            log.push_str(&format!("Rand Beacon: [{}]\n", b));
+            log.push_str(&format!(
+                "{}: cx.epoch {}. Leader is {}.\n",
+                id,
+                e + 1,
+                (e + 1) % 3
+            ));
+            log.push_str(&format!("{}: Phase \"End\" took {} ms.\n", id, e));
+        }
+        parse(format!("{}.log", id), log.as_bytes())
+    }
+
+    #[test]
+    fn consistent_logs() {
+        let logs = vec![node(0, &["0, 0", "1, 2"]), node(1, &["0, 0", "1, 2"])];
+        assert!(check(&logs).is_empty());
+        assert_eq!(agreed_beacons(&logs)[&1], Some("0102".to_string()));
+        let stats = latencies(&logs);
+        let all = &stats[&("all".to_string(), "phase", "End".to_string())];
+        assert_eq!((all.count, all.min, all.max), (4, 0, 1));
+    }
+
+    #[test]
+    fn divergent_logs() {
+        let logs = vec![
+            node(0, &["0, 0", "1, 2", "0, 0", "3, 4"]),
+            node(1, &["0, 0", "1, 3", "0, 0", "3, 4"]),
+            node(2, &["0, 0"]),
+        ];
+        let issues = check(&logs);
+        assert!(issues.contains(&Issue::BeaconDivergence(
+            1,
+            vec![
+                ("0.log".to_string(), "0102".to_string()),
+                ("1.log".to_string(), "0103".to_string())
+            ]
+        )));
+        assert!(issues.contains(&Issue::ZeroBeacon("0.log".to_string(), 2)));
+        assert!(issues.contains(&Issue::Stalled("2.log".to_string(), 1, 4)));
+    }
+
+    #[test]
+    fn epoch_missing_from_every_log() {
+        let log = |id: u16| {
+            let text = format!(
+                "{id}: cx.epoch 1. Leader is 1.\n\
+                 Rand Beacon: [1, 2]\n\
+                 {id}: cx.epoch 3. Leader is 0.\n\
+                 Rand Beacon: [3, 4]\n\
+                 {id}: cx.epoch 4. Leader is 1.\n",
+                id = id
+            );
+            parse(format!("{}.log", id), text.as_bytes())
+        };
+        let issues = check(&[log(0), log(1)]);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::MissingBeacon("0.log".to_string(), 2),
+                Issue::MissingBeacon("1.log".to_string(), 2)
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use super::Certificate;
+use super::{Certificate, SignedKeyRotation, SignedReconfig};
 use crate::protocol::{Height, Replica};
 use crate::Vote;
 use crypto::hash::{Hash, EMPTY_HASH};
@@ -9,6 +9,8 @@ use types_upstream::WireReady;
 pub struct Content {
     pub commits: Vec<crypto::EVSSCommit381>,
     pub acks: Vec<Vote>,
+    pub reconfig: Option<SignedReconfig>,
+    pub rotations: Vec<SignedKeyRotation>,
 }
 
 impl Content {
@@ -16,6 +18,8 @@ impl Content {
         Content {
             commits: Vec::new(),
             acks: Vec::new(),
+            reconfig: None,
+            rotations: Vec::new(),
         }
 
     }
@@ -108,12 +112,12 @@ impl Block {
         }
     }
 
-    pub fn update_hash(&mut self) {
+    pub fn update_hash(&mut self, alg: crypto::hash::HashAlgorithm) {
         let empty_vec = vec![0; 0];
         let old_vec = std::mem::replace(&mut self.payload, empty_vec);
         let empty_cert = Certificate::empty_cert();
         let old_cert = std::mem::replace(&mut self.certificate, empty_cert);
-        self.hash = crypto::hash::ser_and_hash(&self);
+        self.hash = crypto::hash::ser_and_hash_in(alg, crypto::hash::Domain::Block, &self);
         let _ = std::mem::replace(&mut self.payload, old_vec);
         let _ = std::mem::replace(&mut self.certificate, old_cert);
     }
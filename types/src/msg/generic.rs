@@ -1,22 +1,86 @@
 use serde::{Deserialize, Serialize};
 
 use crate::protocol::*;
+use crate::SignKind;
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use crypto::merkle::{InclusionProof, MerkleTree};
 use crypto::*;
 use types_upstream::WireReady;
 
+/// Commits to the shards of some data, one leaf per node
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Accumulator {
+    Merkle(MerkleTree),
+    /// A biaccumulator commitment with the membership witness of every shard.
+    /// `shards` hashes the accumulated element of every shard, so that data
+    /// decoded from some of them can be checked against all of them.
+    Bi {
+        commit: EVSSCommit381,
+        shards: Hash,
+        witnesses: Vec<EVSSShare381>,
+    },
+}
+
+impl Accumulator {
+    /// The value the sender signs
+    pub fn digest(&self, alg: HashAlgorithm) -> Hash {
+        match self {
+            Accumulator::Merkle(tree) => tree.root(),
+            Accumulator::Bi { commit, shards, .. } => {
+                ser_and_hash_in(alg, Domain::Accumulator, &(commit, shards))
+            }
+        }
+    }
+}
+
+/// Proves that a shard was committed to by an `Accumulator`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Witness {
+    Merkle {
+        root: Hash,
+        proof: InclusionProof,
+    },
+    Bi {
+        commit: EVSSCommit381,
+        shards: Hash,
+        witness: EVSSShare381,
+    },
+}
+
+impl Witness {
+    /// The value the sender signs, the same as `Accumulator::digest`
+    pub fn digest(&self, alg: HashAlgorithm) -> Hash {
+        match self {
+            Witness::Merkle { root, .. } => *root,
+            Witness::Bi { commit, shards, .. } => {
+                ser_and_hash_in(alg, Domain::Accumulator, &(commit, shards))
+            }
+        }
+    }
+}
+
+/// The accumulator over the shards of some data, with the sender's signature
+/// on its digest
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataWithAcc {
     pub sign: Vec<u8>,
-    pub tree: Vec<Vec<u8>>,
-    pub size: Replica,
+    pub acc: Accumulator,
 }
 
+/// Proves that a shard is part of the data whose accumulator was signed
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SignedData {
     pub sign: Vec<u8>,
-    pub start: Vec<u8>,
-    pub index: Replica,
-    pub chain: Vec<(Vec<u8>, Vec<u8>)>,
+    pub witness: Witness,
+}
+
+/// Shards signed by `dealer` that do not decode to the data it accumulated
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BadEncoding {
+    pub dealer: Replica,
+    /// What the shards are signed as: a proposal or commitments
+    pub kind: SignKind,
+    pub shards: Vec<(Replica, Vec<u8>, SignedData)>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -36,11 +100,17 @@ pub struct Vote {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Certificate {
     pub votes: Vec<Vote>,
+    /// The epoch the votes were cast in, which picks the keys they verify
+    /// against
+    pub epoch: Height,
 }
 
 impl Certificate {
     pub const fn empty_cert() -> Self {
-        Certificate { votes: Vec::new() }
+        Certificate {
+            votes: Vec::new(),
+            epoch: 0,
+        }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Self {
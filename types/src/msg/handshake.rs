@@ -0,0 +1,183 @@
+use super::{sign_digest, SignKind};
+use crate::{Height, Replica};
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use crypto::scheme::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The oldest version of the wire format this build still speaks
+pub const MIN_WIRE_VERSION: u16 = 1;
+/// The newest version of the wire format this build speaks
+pub const WIRE_VERSION: u16 = 1;
+/// Starts every hello, so that a peer sending anything else stands out
+pub const HELLO_MAGIC: [u8; 4] = *b"RPHS";
+
+/// The first frame a node sends on every connection to another node: which
+/// versions of the wire format it speaks, which network it belongs to, and
+/// who it is, signed with its key.
+///
+/// On a plain link the hello is the same on every connection, so anyone who
+/// saw it can send it again: it keeps out nodes with another config, but it
+/// does not authenticate the peer. The encrypted transport ties the hello to
+/// the link it is sent on with `session`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub magic: [u8; 4],
+    pub min_version: u16,
+    pub max_version: u16,
+    /// The digest of the config every node of the network shares
+    pub config: Hash,
+    pub origin: Replica,
+    /// The hash of the Noise handshake of the link the hello is sent on, if
+    /// any
+    pub session: Option<Hash>,
+    /// The epoch whose key of `origin` signs the hello: the last one the
+    /// keys of the membership changed in
+    pub epoch: Height,
+    /// Tagged data for later versions. A peer skips the tags it does not
+    /// know, so a hello can grow without breaking older nodes.
+    pub extensions: Vec<(u16, Vec<u8>)>,
+    /// The signature of `origin` on the `sign_digest` of `hash()`, as a
+    /// `SignKind::Hello` for `epoch`
+    pub sig: Vec<u8>,
+}
+
+impl Hello {
+    /// An unsigned hello of `origin` for the network with config `config`
+    pub fn new(config: Hash, origin: Replica) -> Self {
+        Hello {
+            magic: HELLO_MAGIC,
+            min_version: MIN_WIRE_VERSION,
+            max_version: WIRE_VERSION,
+            config,
+            origin,
+            session: None,
+            epoch: 0,
+            extensions: Vec::new(),
+            sig: Vec::new(),
+        }
+    }
+
+    /// What `origin` signs: everything but the signature. It is hashed with
+    /// SHA-256, since the hash function of the peer is not known yet.
+    pub fn hash(&self) -> Hash {
+        ser_and_hash_in(
+            HashAlgorithm::SHA256,
+            Domain::Handshake,
+            &(
+                &self.magic,
+                self.min_version,
+                self.max_version,
+                &self.config,
+                self.origin,
+                &self.session,
+                self.epoch,
+                &self.extensions,
+            ),
+        )
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HandshakeError> {
+        match bincode::deserialize::<Hello>(bytes) {
+            Ok(hello) if hello.magic == HELLO_MAGIC => Ok(hello),
+            _ => Err(HandshakeError::NoHello),
+        }
+    }
+
+    /// The newest version both sides speak
+    pub fn negotiate(&self, theirs: &Hello) -> Result<u16, HandshakeError> {
+        let version = self.max_version.min(theirs.max_version);
+        if version < self.min_version.max(theirs.min_version) {
+            return Err(HandshakeError::Version {
+                origin: theirs.origin,
+                ours: (self.min_version, self.max_version),
+                theirs: (theirs.min_version, theirs.max_version),
+            });
+        }
+        Ok(version)
+    }
+
+    /// Checks the hello of a peer against ours and the key the peer is known
+    /// by, and returns the version to speak with it
+    pub fn accept(
+        &self,
+        theirs: &Hello,
+        pk: Option<&dyn VerifyingKey>,
+    ) -> Result<u16, HandshakeError> {
+        // A peer on another version may well compute the digest differently,
+        // so the version is the better thing to report
+        let version = self.negotiate(theirs)?;
+        if theirs.config != self.config {
+            return Err(HandshakeError::Config(theirs.origin));
+        }
+        let pk = pk.ok_or(HandshakeError::UnknownNode(theirs.origin))?;
+        let digest = sign_digest(SignKind::Hello, theirs.epoch, &theirs.hash());
+        if !pk.verify(&digest, &theirs.sig) {
+            return Err(HandshakeError::BadSignature(theirs.origin));
+        }
+        Ok(version)
+    }
+}
+
+/// Why a peer was turned away
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    /// The first frame was not a hello
+    NoHello,
+    Version {
+        origin: Replica,
+        ours: (u16, u16),
+        theirs: (u16, u16),
+    },
+    Config(Replica),
+    UnknownNode(Replica),
+    BadSignature(Replica),
+    /// The hello was made for another link, so it was sent again
+    Session(Replica),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::NoHello => write!(
+                f,
+                "the peer did not open with a handshake; it may run an older build"
+            ),
+            HandshakeError::Version {
+                origin,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "node {} speaks wire versions {}-{}, but this node speaks {}-{}",
+                origin, theirs.0, theirs.1, ours.0, ours.1
+            ),
+            HandshakeError::Config(origin) => write!(
+                f,
+                "node {} runs with a different config (n, f, algorithms or public keys)",
+                origin
+            ),
+            HandshakeError::UnknownNode(origin) => {
+                write!(f, "node {} is not in the config", origin)
+            }
+            HandshakeError::BadSignature(origin) => {
+                write!(
+                    f,
+                    "the handshake of node {} is not signed with its key",
+                    origin
+                )
+            }
+            HandshakeError::Session(origin) => write!(
+                f,
+                "the handshake of node {} was made for another link",
+                origin
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
@@ -13,4 +13,16 @@ pub use storage::*;
 mod propose;
 pub use propose::*;
 
+mod reconfig;
+pub use reconfig::*;
+
+mod rotation;
+pub use rotation::*;
+
+mod handshake;
+pub use handshake::*;
+
+mod sign;
+pub use sign::*;
+
 pub mod synchs;
@@ -14,4 +14,8 @@ impl Propose {
         let c: Propose = bincode::deserialize(&bytes).expect("failed to decode the propose");
         c
     }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(&bytes).ok()
+    }
 }
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use super::Certificate;
-use crate::{Propose, Height, Replica, DataWithAcc, SignedData, Vote};
+use crypto::hash::Hash;
+use crate::{BadEncoding, Propose, Height, Replica, DataWithAcc, SignedData, Vote};
 use types_upstream::WireReady;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,7 +16,8 @@ pub enum ProtocolMsg {
     Reconstruct(crypto::EVSSShare381, Height),
     Commit(std::collections::VecDeque<crypto::EVSSShare381>, Vec<crypto::EVSSCommit381>, DataWithAcc),
     DeliverCommit(Vec<u8>, Replica, SignedData),
-    // Ack(Vote),
+    Ack(Vote),
+    BadEncoding(BadEncoding),
 }
 
 pub fn commit_from_bytes(bytes: &[u8]) -> Vec<crypto::EVSSCommit381> {
@@ -23,6 +25,32 @@ pub fn commit_from_bytes(bytes: &[u8]) -> Vec<crypto::EVSSCommit381> {
     c
 }
 
+pub fn try_commit_from_bytes(bytes: &[u8]) -> Option<Vec<crypto::EVSSCommit381>> {
+    bincode::deserialize(&bytes).ok()
+}
+
+/// The hash of the commitments a leader deals
+pub fn commits_hash(
+    alg: crypto::hash::HashAlgorithm,
+    commits: &Vec<crypto::EVSSCommit381>,
+) -> Hash {
+    crypto::hash::ser_and_hash_in(alg, crypto::hash::Domain::Commitments, commits)
+}
+
+/// The message signed in an ack: the commitments that the leader of `epoch`
+/// will propose in that epoch
+pub fn ack_hash(
+    alg: crypto::hash::HashAlgorithm,
+    epoch: Height,
+    commits: &Vec<crypto::EVSSCommit381>,
+) -> Hash {
+    crypto::hash::ser_and_hash_in(
+        alg,
+        crypto::hash::Domain::Ack,
+        &(epoch, commits_hash(alg, commits)),
+    )
+}
+
 impl ProtocolMsg {
     pub fn from_bytes(bytes: &[u8]) -> Self {
         let c: ProtocolMsg =
@@ -41,7 +69,8 @@ impl ProtocolMsg {
             ProtocolMsg::Reconstruct(_, _) => "Reconstruct",
             ProtocolMsg::Commit(_, _, _) => "Commit",
             ProtocolMsg::DeliverCommit(_, _, _) => "DeliverCommit",
-            // ProtocolMsg::Ack(_) => "Ack",
+            ProtocolMsg::Ack(_) => "Ack",
+            ProtocolMsg::BadEncoding(_) => "BadEncoding",
         }
     }
 }
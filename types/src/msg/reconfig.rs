@@ -0,0 +1,71 @@
+use super::{Transaction, Vote};
+use crate::{Height, Replica};
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Marks a client transaction that carries a `SignedReconfig`
+pub const RECONFIG_TX_TAG: &[u8] = b"reconfig";
+
+/// A new membership for the protocol, effective from `epoch` onwards.
+///
+/// Replicas are numbered `0..num_nodes` as in the config files. The maps are
+/// ordered so that every node hashes a reconfiguration the same way.
+///
+/// The encrypted transport connects to the new `net_map` from `epoch` on.
+/// The plain one keeps the nodes it started with, so new nodes and addresses
+/// only reach the network of nodes restarted with the new config.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reconfig {
+    pub epoch: Height,
+    pub num_nodes: usize,
+    pub num_faults: usize,
+    pub pk_map: BTreeMap<Replica, Vec<u8>>,
+    pub net_map: BTreeMap<Replica, String>,
+    pub bi_pp_map: BTreeMap<Replica, crypto::EVSSPublicParams381>,
+    pub rand_beacon_parameter: crypto::EVSSParams381,
+}
+
+impl Reconfig {
+    pub fn hash(&self, alg: HashAlgorithm) -> Hash {
+        ser_and_hash_in(alg, Domain::Reconfig, self)
+    }
+
+    /// Checks that the new membership is well formed
+    pub fn is_well_formed(&self) -> bool {
+        let n = self.num_nodes;
+        n > 2 * self.num_faults
+            && self.pk_map.len() == n
+            && self.net_map.len() == n
+            && self.bi_pp_map.len() == n
+            && (0..n as Replica).all(|r| {
+                self.pk_map.contains_key(&r)
+                    && self.net_map.contains_key(&r)
+                    && self.bi_pp_map.contains_key(&r)
+            })
+    }
+}
+
+/// A reconfiguration along with the approvals of the current replicas.
+/// `votes` sign `reconfig.hash(alg)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedReconfig {
+    pub reconfig: Reconfig,
+    pub votes: Vec<Vote>,
+}
+
+impl SignedReconfig {
+    pub fn from_tx(tx: &Transaction) -> Option<Self> {
+        if tx.data != RECONFIG_TX_TAG {
+            return None;
+        }
+        bincode::deserialize(&tx.request).ok()
+    }
+
+    pub fn to_tx(&self) -> Transaction {
+        Transaction {
+            data: RECONFIG_TX_TAG.to_vec(),
+            request: bincode::serialize(self).unwrap(),
+        }
+    }
+}
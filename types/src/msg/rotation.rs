@@ -0,0 +1,63 @@
+use super::Transaction;
+use crate::{Height, Replica};
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use crypto::scheme::{SchemeError, SigningKey};
+use serde::{Deserialize, Serialize};
+
+/// Marks a client transaction that carries a `SignedKeyRotation`
+pub const KEY_ROTATION_TX_TAG: &[u8] = b"keyrotation";
+
+/// Replaces the public key of `replica` with `new_pk` from `epoch` onwards.
+/// Messages signed in earlier epochs keep verifying against the old key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyRotation {
+    pub replica: Replica,
+    pub epoch: Height,
+    pub new_pk: Vec<u8>,
+}
+
+impl KeyRotation {
+    pub fn hash(&self, alg: HashAlgorithm) -> Hash {
+        ser_and_hash_in(alg, Domain::KeyRotation, self)
+    }
+}
+
+/// A key rotation signed with both keys of the replica: the old one shows
+/// that the replica asks for it, and the new one that it holds the new
+/// secret. Both sign `rotation.hash(alg)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedKeyRotation {
+    pub rotation: KeyRotation,
+    pub old_sig: Vec<u8>,
+    pub new_sig: Vec<u8>,
+}
+
+impl SignedKeyRotation {
+    pub fn new(
+        rotation: KeyRotation,
+        alg: HashAlgorithm,
+        old_key: &dyn SigningKey,
+        new_key: &dyn SigningKey,
+    ) -> Result<Self, SchemeError> {
+        let hash = rotation.hash(alg);
+        Ok(SignedKeyRotation {
+            old_sig: old_key.sign(&hash)?,
+            new_sig: new_key.sign(&hash)?,
+            rotation,
+        })
+    }
+
+    pub fn from_tx(tx: &Transaction) -> Option<Self> {
+        if tx.data != KEY_ROTATION_TX_TAG {
+            return None;
+        }
+        bincode::deserialize(&tx.request).ok()
+    }
+
+    pub fn to_tx(&self) -> Transaction {
+        Transaction {
+            data: KEY_ROTATION_TX_TAG.to_vec(),
+            request: bincode::serialize(self).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::Height;
+use crypto::hash::{ser_and_hash_in, Domain, Hash, HashAlgorithm};
+use serde::{Deserialize, Serialize};
+
+/// What is being signed. A signing daemon signs at most one message of every
+/// kind per epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignKind {
+    /// The accumulator of a proposal
+    Propose,
+    /// The accumulator of a certificate
+    Certificate,
+    /// The accumulator of the commitments dealt for the next epoch
+    Commit,
+    Vote,
+    /// An ack of the commitments of the next leader
+    Ack,
+    /// The hello the node opens its connections with, for the last epoch the
+    /// keys of the membership changed in
+    Hello,
+    /// Not a signature: the switch to the key of a committed rotation, from
+    /// the epoch of the rotation on
+    Rotate,
+}
+
+/// What the key of a node actually signs for `msg`: the kind and epoch are
+/// part of it, so that a signature only verifies for the kind and epoch it
+/// was made for. It is hashed with SHA-256, since a signing daemon has no
+/// config to take the hash function from.
+pub fn sign_digest(kind: SignKind, epoch: Height, msg: &[u8]) -> Hash {
+    ser_and_hash_in(
+        HashAlgorithm::SHA256,
+        Domain::Signature,
+        &(kind, epoch, msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bound_to_kind_and_epoch() {
+        let d = sign_digest(SignKind::Vote, 3, b"block");
+        assert_eq!(d, sign_digest(SignKind::Vote, 3, b"block"));
+        assert_ne!(d, sign_digest(SignKind::Ack, 3, b"block"));
+        assert_ne!(d, sign_digest(SignKind::Vote, 4, b"block"));
+        assert_ne!(d, sign_digest(SignKind::Vote, 3, b"other"));
+    }
+}
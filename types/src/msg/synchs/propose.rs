@@ -27,6 +27,8 @@ impl Propose {
     }
 
     pub fn init(&mut self) {
-        self.new_block.update_hash();
+        // Synchs has no config to take the hash function from
+        self.new_block
+            .update_hash(crypto::hash::HashAlgorithm::SHA256);
     }
 }
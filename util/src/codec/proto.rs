@@ -1,17 +1,70 @@
 use bytes::{Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
-use types::ProtocolMsg;
+use types::{HandshakeError, Hello, ProtocolMsg};
 
-use std::{borrow::Borrow, io, sync::Arc};
+use std::{borrow::Borrow, fmt, io, sync::Arc};
 
 use crate::io::to_bytes;
 
-#[derive(Debug)]
-pub struct Codec(pub LengthDelimitedCodec);
+/// Checks the hello a peer opens a connection with, and returns the version
+/// of the wire format to speak with it
+pub type CheckHello = Arc<dyn Fn(&Hello) -> Result<u16, HandshakeError> + Send + Sync>;
+
+pub struct Codec {
+    inner: LengthDelimitedCodec,
+    check: Option<CheckHello>,
+    /// The version agreed on, once the peer said hello
+    version: Option<u16>,
+}
 
 impl Codec {
     pub fn new() -> Self {
-        Codec(LengthDelimitedCodec::new())
+        Codec {
+            inner: LengthDelimitedCodec::new(),
+            check: None,
+            version: None,
+        }
+    }
+
+    /// Expects every connection to open with a hello that passes `check`,
+    /// and drops the connection otherwise
+    pub fn with_handshake(check: CheckHello) -> Self {
+        Codec {
+            check: Some(check),
+            ..Codec::new()
+        }
+    }
+
+    /// The version of the wire format the peer speaks, once it is known
+    pub fn version(&self) -> Option<u16> {
+        self.version
+    }
+
+    fn greet(&mut self, frame: &[u8]) -> Result<(), io::Error> {
+        let check = match &self.check {
+            Some(check) => check,
+            None => return Ok(()),
+        };
+        match Hello::from_bytes(frame).and_then(|hello| check(&hello)) {
+            Ok(version) => {
+                self.version = Some(version);
+                Ok(())
+            }
+            Err(e) => {
+                println!("[WARN] Rejected a peer: {}", e);
+                Err(io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Codec")
+            .field("inner", &self.inner)
+            .field("handshake", &self.check.is_some())
+            .field("version", &self.version)
+            .finish()
     }
 }
 
@@ -20,13 +73,81 @@ impl Decoder for Codec {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        match self.0.decode(src)? {
+        if self.check.is_some() && self.version.is_none() {
+            match self.inner.decode(src)? {
+                Some(hello) => self.greet(&hello)?,
+                None => return Ok(None),
+            }
+        }
+        match self.inner.decode(src)? {
             Some(in_data) => Ok(Some(ProtocolMsg::from_bytes(&in_data))),
             None => Ok(None),
         }
     }
 }
 
+impl std::clone::Clone for Codec {
+    fn clone(&self) -> Self {
+        Codec {
+            check: self.check.clone(),
+            ..Codec::new()
+        }
+    }
+}
+
+/// Encodes protocol messages after opening every connection with a hello
+#[derive(Debug)]
+pub struct HelloEnCodec {
+    inner: LengthDelimitedCodec,
+    hello: Bytes,
+    sent: bool,
+}
+
+impl HelloEnCodec {
+    pub fn new(hello: &Hello) -> Self {
+        HelloEnCodec {
+            inner: LengthDelimitedCodec::new(),
+            hello: Bytes::from(hello.to_bytes()),
+            sent: false,
+        }
+    }
+
+    fn encode_bytes(&mut self, data: Vec<u8>, dst: &mut BytesMut) -> Result<(), io::Error> {
+        if !self.sent {
+            self.inner.encode(self.hello.clone(), dst)?;
+            self.sent = true;
+        }
+        self.inner.encode(Bytes::from(data), dst)
+    }
+}
+
+impl Encoder<ProtocolMsg> for HelloEnCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: ProtocolMsg, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_bytes(to_bytes(&item), dst)
+    }
+}
+
+impl Encoder<Arc<ProtocolMsg>> for HelloEnCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Arc<ProtocolMsg>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bor: &ProtocolMsg = item.borrow();
+        self.encode_bytes(to_bytes(bor), dst)
+    }
+}
+
+impl std::clone::Clone for HelloEnCodec {
+    fn clone(&self) -> Self {
+        HelloEnCodec {
+            inner: LengthDelimitedCodec::new(),
+            hello: self.hello.clone(),
+            sent: false,
+        }
+    }
+}
+
 impl Encoder<ProtocolMsg> for super::EnCodec {
     type Error = io::Error;
 
@@ -48,8 +169,86 @@ impl Encoder<Arc<ProtocolMsg>> for super::EnCodec {
     }
 }
 
-impl std::clone::Clone for Codec {
-    fn clone(&self) -> Self {
-        Codec::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::Vote;
+
+    fn vote() -> ProtocolMsg {
+        ProtocolMsg::Vote(Vote {
+            msg: vec![1, 2],
+            origin: 1,
+            auth: vec![3],
+        })
+    }
+
+    fn accept(config: u8) -> CheckHello {
+        Arc::new(move |hello: &Hello| {
+            if hello.config == [config; 32] {
+                Ok(hello.max_version)
+            } else {
+                Err(HandshakeError::Config(hello.origin))
+            }
+        })
+    }
+
+    #[test]
+    fn handshake() {
+        let mut enc = HelloEnCodec::new(&Hello::new([7; 32], 1));
+        let mut buf = BytesMut::new();
+        enc.encode(vote(), &mut buf).unwrap();
+        enc.encode(Arc::new(vote()), &mut buf).unwrap();
+
+        let mut dec = Codec::with_handshake(accept(7));
+        let mut wire = buf.clone();
+        assert!(matches!(
+            dec.decode(&mut wire).unwrap(),
+            Some(ProtocolMsg::Vote(_))
+        ));
+        assert_eq!(dec.version(), Some(types::WIRE_VERSION));
+        assert!(matches!(
+            dec.decode(&mut wire).unwrap(),
+            Some(ProtocolMsg::Vote(_))
+        ));
+
+        // Every connection says hello again
+        let mut again = BytesMut::new();
+        enc.clone().encode(vote(), &mut again).unwrap();
+        assert!(dec.clone().decode(&mut again).unwrap().is_some());
+
+        let mut wire = buf.clone();
+        let e = Codec::with_handshake(accept(8))
+            .decode(&mut wire)
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // A peer on an older build sends no hello
+        let mut old = BytesMut::new();
+        super::super::EnCodec::new()
+            .encode(vote(), &mut old)
+            .unwrap();
+        assert!(Codec::with_handshake(accept(7)).decode(&mut old).is_err());
+    }
+
+    #[test]
+    fn negotiate() {
+        let ours = Hello::new([7; 32], 0);
+        let mut newer = Hello::new([7; 32], 1);
+        newer.min_version = types::WIRE_VERSION;
+        newer.max_version = types::WIRE_VERSION + 3;
+        assert_eq!(ours.negotiate(&newer), Ok(types::WIRE_VERSION));
+        newer.min_version = types::WIRE_VERSION + 1;
+        assert!(matches!(
+            ours.negotiate(&newer),
+            Err(HandshakeError::Version { origin: 1, .. })
+        ));
+        assert_eq!(
+            ours.accept(&Hello::new([8; 32], 2), None),
+            Err(HandshakeError::Config(2))
+        );
+        assert_eq!(
+            ours.accept(&Hello::new([7; 32], 2), None),
+            Err(HandshakeError::UnknownNode(2))
+        );
     }
 }
@@ -10,6 +10,8 @@ mod tests {
 
 pub mod codec;
 pub mod io;
+pub mod noise;
+pub mod peers;
 // pub mod channel;
 
 pub fn new_dummy_tx(i: u64, payload: usize) -> Transaction {
@@ -0,0 +1,691 @@
+// An encrypted transport between the nodes, to use instead of the plain TCP
+// one of `net`. Every node dials every other node and only writes on the
+// links it dialed, and only reads on the links it accepted. A link opens with
+// a Noise IK handshake on the X25519 keys that go with the keys in `pk_map`:
+// the dialer knows the key of the node it dials, and proves its own. So the
+// messages read on a link come from the node they are attributed to, and
+// nobody else can read or change them. The dialer then sends its hello,
+// signed for the link: it holds the hash of the Noise handshake, which is new
+// for every link, so it cannot be sent again on another one. The dialer only
+// sends messages once the peer accepted its hello, so none is lost on a link
+// the peer turned away, e.g. because it did not follow a new membership yet.
+
+use crate::io::to_bytes;
+use crate::peers::{channel, Greeter, NetSender, Outgoing, Peers};
+use bytes::Bytes;
+use crypto::hash::Hash;
+use crypto::secret::SecretBytes;
+use crypto::Algorithm;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use types::{HandshakeError, Hello, ProtocolMsg, Replica};
+
+const PATTERN: &str = "Noise_IK_25519_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"randpiper transport";
+/// The largest Noise message
+const MAX_NOISE: usize = 65535;
+/// The size of the tag every Noise message ends with
+const TAG: usize = 16;
+/// The most plaintext a Noise message holds
+const CHUNK: usize = MAX_NOISE - TAG;
+/// The largest message a link carries, as much as a plain link does
+const MAX_MESSAGE: usize = 8 << 20;
+/// The largest frame a link carries: a message of `MAX_MESSAGE` once sealed
+const MAX_FRAME: usize = MAX_MESSAGE + (MAX_MESSAGE / CHUNK + 1) * TAG;
+/// How long a peer may take for the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait before dialing a node again
+const RETRY: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum TransportError {
+    Io(io::Error),
+    Noise(snow::Error),
+    Format(bincode::Error),
+    /// The peer did not finish the handshake in time
+    Timeout,
+    /// The peer went away during the handshake
+    Closed,
+    /// The peer proved a key that is not in the config
+    UnknownKey,
+    /// The peer proved the key of one node but said hello as another
+    Impostor {
+        claims: Replica,
+        is: Replica,
+    },
+    Hello(HandshakeError),
+    /// Our hello could not be signed
+    Sign(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Io(e) => write!(f, "{}", e),
+            TransportError::Noise(e) => write!(f, "noise: {}", e),
+            TransportError::Format(e) => write!(f, "invalid message: {}", e),
+            TransportError::Timeout => write!(f, "the handshake timed out"),
+            TransportError::Closed => write!(f, "the peer closed the link"),
+            TransportError::UnknownKey => write!(f, "the peer has a key that is not in the config"),
+            TransportError::Impostor { claims, is } => {
+                write!(f, "node {} said hello as node {}", is, claims)
+            }
+            TransportError::Hello(e) => write!(f, "{}", e),
+            TransportError::Sign(e) => write!(f, "cannot sign the hello: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<io::Error> for TransportError {
+    fn from(e: io::Error) -> Self {
+        TransportError::Io(e)
+    }
+}
+
+impl From<snow::Error> for TransportError {
+    fn from(e: snow::Error) -> Self {
+        TransportError::Noise(e)
+    }
+}
+
+impl From<bincode::Error> for TransportError {
+    fn from(e: bincode::Error) -> Self {
+        TransportError::Format(e)
+    }
+}
+
+impl From<HandshakeError> for TransportError {
+    fn from(e: HandshakeError) -> Self {
+        TransportError::Hello(e)
+    }
+}
+
+/// Signs a hello with the key the config has for the node. It may block on a
+/// signing daemon, so the transport runs it on the blocking pool.
+pub type SignHello = Arc<dyn Fn(&Hello) -> Result<Vec<u8>, String> + Send + Sync>;
+
+/// What a node needs to set up the encrypted transport
+pub struct Transport {
+    pub id: Replica,
+    /// The scheme of the keys in `Peers`
+    pub alg: Algorithm,
+    /// Our X25519 secret key
+    pub secret: SecretBytes,
+    /// The X25519 secret keys that go with the keys we may rotate to
+    pub rotated: Vec<SecretBytes>,
+    /// Our hello, which is signed for each link with `sign`, and the check
+    /// of the hellos of the others. It follows every `Peers`.
+    pub greeter: Greeter,
+    pub sign: SignHello,
+}
+
+impl Transport {
+    /// Our secret keys, the one of the config first
+    fn secrets(&self) -> impl Iterator<Item = &SecretBytes> {
+        std::iter::once(&self.secret).chain(self.rotated.iter())
+    }
+}
+
+/// The addresses and X25519 keys of the nodes, as of the last `Peers`
+struct Known {
+    addrs: HashMap<Replica, String>,
+    keys: HashMap<Replica, [u8; 32]>,
+    /// Which of our secret keys goes with our key in `keys`
+    own: usize,
+}
+
+impl Known {
+    fn new(transport: &Transport, peers: &Peers) -> Self {
+        let keys: HashMap<Replica, [u8; 32]> = match crypto::scheme::lookup(&transport.alg) {
+            Ok(scheme) => peers
+                .keys
+                .iter()
+                .filter_map(|(r, pk)| match scheme.transport_pk(pk) {
+                    Ok(key) => Some((*r, key)),
+                    Err(e) => {
+                        println!(
+                            "[WARN] Cannot use the key of node {} for the transport: {}",
+                            r, e
+                        );
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                println!("[WARN] Cannot use the keys for the transport: {}", e);
+                HashMap::new()
+            }
+        };
+        // Our key changes with a committed rotation
+        let own = keys.get(&transport.id).and_then(|pk| {
+            transport
+                .secrets()
+                .position(|sk| crypto::scheme::transport_public(sk).ok().as_ref() == Some(pk))
+        });
+        if own.is_none() {
+            println!(
+                "[WARN] None of our transport keys goes with our key; using the one of the config"
+            );
+        }
+        Known {
+            addrs: peers.addrs.clone(),
+            keys,
+            own: own.unwrap_or(0),
+        }
+    }
+
+    /// Where and with which key to dial `peer`
+    fn dial(&self, peer: Replica) -> Option<(String, [u8; 32])> {
+        Some((self.addrs.get(&peer)?.clone(), *self.keys.get(&peer)?))
+    }
+}
+
+/// A transport once it is set up
+struct Shared {
+    transport: Transport,
+    known: RwLock<Known>,
+}
+
+impl Transport {
+    /// Listens for the other nodes and dials them, like
+    /// `net::Protocol::server_setup`. A message sent to the number of nodes
+    /// goes to every other node. The membership changes with every `Peers`
+    /// sent on the channel of the messages.
+    pub async fn setup(
+        self,
+        peers: Peers,
+    ) -> (NetSender, UnboundedReceiver<(Replica, ProtocolMsg)>) {
+        let listener = TcpListener::bind(&peers.addrs[&self.id])
+            .await
+            .unwrap_or_else(|e| panic!("failed to listen for the other nodes: {}", e));
+        let this = Arc::new(Shared {
+            known: RwLock::new(Known::new(&self, &peers)),
+            transport: self,
+        });
+        let (recv_send, recv) = unbounded_channel();
+        tokio::spawn(accept(this.clone(), listener, recv_send.clone()));
+
+        let (send, mut outgoing) = channel();
+        let id = this.transport.id;
+        tokio::spawn(async move {
+            let mut links = HashMap::new();
+            this.dial_all(&mut links);
+            let mut broadcast = peers.num_nodes;
+            while let Some(out) = outgoing.recv().await {
+                match out {
+                    Outgoing::Peers(update) => {
+                        let known = Known::new(&this.transport, &update);
+                        // A node with a new address or key is dialed again,
+                        // and every node once our own key changed
+                        let old = this.known.read().unwrap();
+                        if old.own != known.own {
+                            links.clear();
+                        }
+                        links.retain(|r, _| old.dial(*r) == known.dial(*r));
+                        drop(old);
+                        *this.known.write().unwrap() = known;
+                        this.transport.greeter.update(&update);
+                        this.dial_all(&mut links);
+                        broadcast = update.num_nodes;
+                    }
+                    Outgoing::Msg(to, msg) if to == broadcast => {
+                        for link in links.values() {
+                            let _ = link.send(msg.clone());
+                        }
+                    }
+                    Outgoing::Msg(to, msg) if to == id => {
+                        let _ = recv_send.send((id, msg.as_ref().clone()));
+                    }
+                    Outgoing::Msg(to, msg) => {
+                        if let Some(link) = links.get(&to) {
+                            let _ = link.send(msg);
+                        }
+                    }
+                }
+            }
+        });
+        (send, recv)
+    }
+}
+
+impl Shared {
+    /// Starts dialing the known nodes we have no link to
+    fn dial_all(self: &Arc<Self>, links: &mut HashMap<Replica, UnboundedSender<Arc<ProtocolMsg>>>) {
+        let known = self.known.read().unwrap();
+        for peer in known.addrs.keys().copied() {
+            if peer == self.transport.id || links.contains_key(&peer) {
+                continue;
+            }
+            if let Some((addr, key)) = known.dial(peer) {
+                let (link_send, link_recv) = unbounded_channel();
+                tokio::spawn(dial(self.clone(), peer, addr, key, link_recv));
+                links.insert(peer, link_send);
+            }
+        }
+    }
+
+    fn builder(&self) -> snow::Builder<'_> {
+        let own = self.known.read().unwrap().own;
+        let secret = self.transport.secrets().nth(own).unwrap();
+        snow::Builder::new(PATTERN.parse().unwrap())
+            .prologue(PROLOGUE)
+            .local_private_key(secret)
+    }
+
+    /// Dials the node with key `key` at `addr` and says hello
+    async fn connect(&self, addr: &str, key: &[u8; 32]) -> Result<Link, TransportError> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let mut framed = Framed::new(stream, codec());
+        let mut noise = self.builder().remote_public_key(key).build_initiator()?;
+        let mut buf = vec![0u8; MAX_NOISE];
+        let len = noise.write_message(&[], &mut buf)?;
+        framed.send(Bytes::copy_from_slice(&buf[..len])).await?;
+        let reply = next_frame(&mut framed).await?;
+        noise.read_message(&reply, &mut buf)?;
+        let mut hello = self.transport.greeter.hello();
+        hello.session = Some(session(&noise));
+        let (sign, unsigned) = (self.transport.sign.clone(), hello.clone());
+        hello.sig = tokio::task::spawn_blocking(move || sign(&unsigned))
+            .await
+            .map_err(|e| TransportError::Sign(e.to_string()))?
+            .map_err(TransportError::Sign)?;
+        let mut link = Link {
+            framed,
+            noise: noise.into_transport_mode()?,
+        };
+        link.send(&hello.to_bytes()).await?;
+        // The peer closes the link if it turns the hello away
+        link.recv_timeout().await?;
+        Ok(link)
+    }
+
+    /// Runs the handshake of a link a peer dialed, and returns who it is
+    async fn greet(
+        &self,
+        mut framed: Framed<TcpStream, LengthDelimitedCodec>,
+    ) -> Result<(Replica, Link), TransportError> {
+        let mut noise = self.builder().build_responder()?;
+        let mut buf = vec![0u8; MAX_NOISE];
+        let first = next_frame(&mut framed).await?;
+        noise.read_message(&first, &mut buf)?;
+        let peer = noise
+            .get_remote_static()
+            .and_then(|key| {
+                self.known
+                    .read()
+                    .unwrap()
+                    .keys
+                    .iter()
+                    .find(|(_, k)| k[..] == *key)
+                    .map(|(r, _)| *r)
+            })
+            .ok_or(TransportError::UnknownKey)?;
+        let len = noise.write_message(&[], &mut buf)?;
+        framed.send(Bytes::copy_from_slice(&buf[..len])).await?;
+        let session = session(&noise);
+        let mut link = Link {
+            framed,
+            noise: noise.into_transport_mode()?,
+        };
+        let hello = Hello::from_bytes(&link.recv_timeout().await?)?;
+        if hello.origin != peer {
+            return Err(TransportError::Impostor {
+                claims: hello.origin,
+                is: peer,
+            });
+        }
+        if hello.session != Some(session) {
+            return Err(HandshakeError::Session(peer).into());
+        }
+        self.transport.greeter.accept(&hello)?;
+        link.send(&[]).await?;
+        Ok((peer, link))
+    }
+}
+
+/// Frames the messages of a link, and refuses frames over `MAX_FRAME`
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME)
+        .new_codec()
+}
+
+/// The hash of a finished Noise handshake, the same on both ends of the link
+fn session(noise: &snow::HandshakeState) -> Hash {
+    let mut hash = Hash::default();
+    hash.copy_from_slice(noise.get_handshake_hash());
+    hash
+}
+
+/// A link after the handshake
+struct Link {
+    framed: Framed<TcpStream, LengthDelimitedCodec>,
+    noise: snow::TransportState,
+}
+
+impl Link {
+    async fn send(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let frame = seal(&mut self.noise, msg)?;
+        self.framed.send(Bytes::from(frame)).await?;
+        Ok(())
+    }
+
+    /// The next message, or `None` once the peer closed the link
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, TransportError> {
+        match self.framed.next().await {
+            Some(frame) => Ok(Some(open(&mut self.noise, &frame?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn recv_timeout(&mut self) -> Result<Vec<u8>, TransportError> {
+        let frame = next_frame(&mut self.framed).await?;
+        open(&mut self.noise, &frame)
+    }
+}
+
+async fn next_frame(
+    framed: &mut Framed<TcpStream, LengthDelimitedCodec>,
+) -> Result<bytes::BytesMut, TransportError> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next()).await {
+        Err(_) => Err(TransportError::Timeout),
+        Ok(None) => Err(TransportError::Closed),
+        Ok(Some(frame)) => Ok(frame?),
+    }
+}
+
+/// Encrypts `msg` into as many Noise messages as it takes
+fn seal(noise: &mut snow::TransportState, msg: &[u8]) -> Result<Vec<u8>, snow::Error> {
+    let mut frame = Vec::with_capacity(msg.len() + (msg.len() / CHUNK + 1) * TAG);
+    let mut buf = vec![0u8; MAX_NOISE];
+    // An empty message still takes one Noise message
+    let chunks: Vec<&[u8]> = if msg.is_empty() {
+        vec![msg]
+    } else {
+        msg.chunks(CHUNK).collect()
+    };
+    for chunk in chunks {
+        let len = noise.write_message(chunk, &mut buf)?;
+        frame.extend_from_slice(&buf[..len]);
+    }
+    Ok(frame)
+}
+
+/// Decrypts a frame from `seal`. Every Noise message but the last one is full.
+fn open(noise: &mut snow::TransportState, frame: &[u8]) -> Result<Vec<u8>, TransportError> {
+    let mut msg = Vec::with_capacity(frame.len());
+    let mut buf = vec![0u8; MAX_NOISE];
+    for chunk in frame.chunks(MAX_NOISE) {
+        let len = noise.read_message(chunk, &mut buf)?;
+        msg.extend_from_slice(&buf[..len]);
+    }
+    Ok(msg)
+}
+
+/// Writes the messages for `peer`, and dials it again whenever the link
+/// breaks, as long as `addr` and `key` are still those of the node
+async fn dial(
+    this: Arc<Shared>,
+    peer: Replica,
+    addr: String,
+    key: [u8; 32],
+    mut queue: UnboundedReceiver<Arc<ProtocolMsg>>,
+) {
+    let mut pending = None;
+    loop {
+        if this.known.read().unwrap().dial(peer) != Some((addr.clone(), key)) {
+            return;
+        }
+        let mut link = match this.connect(&addr, &key).await {
+            Ok(link) => link,
+            // The node is not up yet
+            Err(TransportError::Io(_)) => {
+                tokio::time::sleep(RETRY).await;
+                continue;
+            }
+            Err(e) => {
+                println!("[WARN] Cannot open a link to node {}: {}", peer, e);
+                tokio::time::sleep(RETRY).await;
+                continue;
+            }
+        };
+        loop {
+            let msg = match pending.take() {
+                Some(msg) => msg,
+                None => match queue.recv().await {
+                    Some(msg) => msg,
+                    None => return,
+                },
+            };
+            let bytes = to_bytes(msg.as_ref());
+            if bytes.len() > MAX_MESSAGE {
+                println!(
+                    "[WARN] Dropped a message of {} bytes for node {}, more than a link carries",
+                    bytes.len(),
+                    peer
+                );
+                continue;
+            }
+            match link.send(&bytes).await {
+                Ok(()) => {}
+                // Sent again once the link is back
+                Err(e @ TransportError::Io(_)) => {
+                    println!("[WARN] Lost the link to node {}: {}", peer, e);
+                    pending = Some(msg);
+                    break;
+                }
+                // Sending it again would fail again, and the link starts over
+                Err(e) => {
+                    println!("[WARN] Dropped a message for node {}: {}", peer, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Accepts the links the other nodes dial
+async fn accept(
+    this: Arc<Shared>,
+    listener: TcpListener,
+    recv: UnboundedSender<(Replica, ProtocolMsg)>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("[WARN] Failed to accept a link: {}", e);
+                continue;
+            }
+        };
+        let this = this.clone();
+        let recv = recv.clone();
+        tokio::spawn(async move {
+            if let Err(e) = read(this, stream, recv).await {
+                println!("[WARN] Dropped the link from {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Reads the messages on a link a peer dialed
+async fn read(
+    this: Arc<Shared>,
+    stream: TcpStream,
+    recv: UnboundedSender<(Replica, ProtocolMsg)>,
+) -> Result<(), TransportError> {
+    stream.set_nodelay(true)?;
+    let framed = Framed::new(stream, codec());
+    let (peer, mut link) = this.greet(framed).await?;
+    while let Some(msg) = link.recv().await? {
+        let msg: ProtocolMsg = bincode::deserialize(&msg)?;
+        if recv.send((peer, msg)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (snow::TransportState, snow::TransportState) {
+        let builder = || snow::Builder::new(PATTERN.parse().unwrap());
+        let a = builder().generate_keypair().unwrap();
+        let b = builder().generate_keypair().unwrap();
+        let mut init = builder()
+            .local_private_key(&a.private)
+            .remote_public_key(&b.public)
+            .build_initiator()
+            .unwrap();
+        let mut resp = builder()
+            .local_private_key(&b.private)
+            .build_responder()
+            .unwrap();
+        let mut buf = vec![0u8; MAX_NOISE];
+        let mut out = vec![0u8; MAX_NOISE];
+        let len = init.write_message(&[], &mut buf).unwrap();
+        resp.read_message(&buf[..len], &mut out).unwrap();
+        assert_eq!(resp.get_remote_static(), Some(&a.public[..]));
+        let len = resp.write_message(&[], &mut buf).unwrap();
+        init.read_message(&buf[..len], &mut out).unwrap();
+        (
+            init.into_transport_mode().unwrap(),
+            resp.into_transport_mode().unwrap(),
+        )
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let (mut a, mut b) = pair();
+        for size in [0, 1, CHUNK, CHUNK + 1, 3 * CHUNK + 7].iter() {
+            let msg: Vec<u8> = (0..*size).map(|i| i as u8).collect();
+            let frame = seal(&mut a, &msg).unwrap();
+            assert_eq!(open(&mut b, &frame).unwrap(), msg);
+        }
+        let mut frame = seal(&mut a, b"vote").unwrap();
+        frame[2] ^= 1;
+        assert!(open(&mut b, &frame).is_err());
+        // The largest message fits in a frame
+        assert_eq!(
+            seal(&mut a, &vec![0; MAX_MESSAGE]).unwrap().len(),
+            MAX_FRAME
+        );
+    }
+
+    #[tokio::test]
+    async fn link() {
+        let scheme = crypto::scheme::lookup(&Algorithm::ED25519).unwrap();
+        let pairs: Vec<_> = (0..3).map(|_| scheme.generate().unwrap()).collect();
+        // The key node 1 rotates to
+        let new_key = scheme.generate().unwrap();
+        let addrs: Vec<_> = (0..3)
+            .map(|_| {
+                let port = std::net::TcpListener::bind("127.0.0.1:0")
+                    .unwrap()
+                    .local_addr()
+                    .unwrap()
+                    .port();
+                format!("127.0.0.1:{}", port)
+            })
+            .collect();
+        let peers = |n: Replica| Peers {
+            num_nodes: n,
+            addrs: (0..n).map(|r| (r, addrs[r as usize].clone())).collect(),
+            keys: (0..n).map(|r| (r, pairs[r as usize].0.clone())).collect(),
+            digest: [n as u8; 32],
+            epoch: 0,
+        };
+        // Every hello is signed for its own link, with the key of the epoch
+        // it is for
+        let sessions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sign = |i: Replica| -> SignHello {
+            let signed = sessions.clone();
+            let keys = match i {
+                1 => vec![(0, pairs[1].1.clone()), (5, new_key.1.clone())],
+                _ => vec![(0, pairs[i as usize].1.clone())],
+            };
+            Arc::new(move |hello: &Hello| {
+                signed.lock().unwrap().push(hello.session);
+                let (_, sk) = keys.iter().rev().find(|(e, _)| *e <= hello.epoch).unwrap();
+                let digest = types::sign_digest(types::SignKind::Hello, hello.epoch, &hello.hash());
+                let scheme = crypto::scheme::lookup(&Algorithm::ED25519).unwrap();
+                Ok(scheme.decode_sk(sk).unwrap().sign(&digest).unwrap())
+            })
+        };
+        let transport = |i: Replica, members: &Peers| Transport {
+            id: i,
+            alg: Algorithm::ED25519,
+            secret: scheme.transport_sk(&pairs[i as usize].1).unwrap(),
+            rotated: match i {
+                1 => vec![scheme.transport_sk(&new_key.1).unwrap()],
+                _ => Vec::new(),
+            },
+            greeter: Greeter::new(Algorithm::ED25519, i, members),
+            sign: sign(i),
+        };
+        let vote = |origin| {
+            Arc::new(ProtocolMsg::Vote(types::Vote {
+                msg: vec![0; 100_000],
+                origin,
+                auth: Vec::new(),
+            }))
+        };
+        let mut nodes = Vec::new();
+        for i in 0..2 {
+            nodes.push(transport(i, &peers(2)).setup(peers(2)).await);
+        }
+        nodes[0].0.send((1, vote(0))).unwrap();
+        nodes[1].0.send((2, vote(1))).unwrap();
+        let (from, msg) = nodes[1].1.recv().await.unwrap();
+        assert_eq!(from, 0);
+        assert!(matches!(msg, ProtocolMsg::Vote(v) if v.origin == 0 && v.msg.len() == 100_000));
+        let (from, _) = nodes[0].1.recv().await.unwrap();
+        assert_eq!(from, 1);
+
+        // Node 2 joins, and is turned away until the others follow
+        nodes.push(transport(2, &peers(3)).setup(peers(3)).await);
+        nodes[0].0.update(peers(3)).unwrap();
+        nodes[1].0.update(peers(3)).unwrap();
+        nodes[0].0.send((3, vote(0))).unwrap();
+        for node in nodes[1..].iter_mut() {
+            let (from, _) = node.1.recv().await.unwrap();
+            assert_eq!(from, 0);
+        }
+        nodes[2].0.send((0, vote(2))).unwrap();
+        let (from, _) = nodes[0].1.recv().await.unwrap();
+        assert_eq!(from, 2);
+
+        // Node 1 rotates its key, and its links are opened with the new one
+        let mut rotated = peers(3);
+        rotated.keys.insert(1, new_key.0.clone());
+        rotated.epoch = 5;
+        for node in nodes.iter() {
+            node.0.update(rotated.clone()).unwrap();
+        }
+        nodes[0].0.send((1, vote(0))).unwrap();
+        let (from, _) = nodes[1].1.recv().await.unwrap();
+        assert_eq!(from, 0);
+        nodes[1].0.send((2, vote(1))).unwrap();
+        let (from, _) = nodes[2].1.recv().await.unwrap();
+        assert_eq!(from, 1);
+
+        let mut sessions = sessions.lock().unwrap().clone();
+        let signed = sessions.len();
+        assert!(signed >= 4 && sessions.iter().all(Option::is_some));
+        sessions.sort();
+        sessions.dedup();
+        assert_eq!(sessions.len(), signed);
+    }
+}
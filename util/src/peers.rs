@@ -0,0 +1,250 @@
+// The nodes the transport connects to. The reactor sends the transport a new
+// `Peers` whenever a reconfiguration or a key rotation takes effect, on the
+// channel of the messages and before any message for the new membership.
+
+use crate::codec::proto::CheckHello;
+use crypto::hash::Hash;
+use crypto::scheme::VerifyingKey;
+use crypto::Algorithm;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use types::{HandshakeError, Height, Hello, ProtocolMsg, Replica};
+
+/// The membership as the transport needs it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Peers {
+    /// Also the id a message for every other node is sent to
+    pub num_nodes: Replica,
+    /// Where to reach every node
+    pub addrs: HashMap<Replica, String>,
+    /// The public key of every node, as in `pk_map`
+    pub keys: HashMap<Replica, Vec<u8>>,
+    /// The digest of the config of the membership, which the nodes say hello
+    /// with
+    pub digest: Hash,
+    /// The last epoch the keys changed in. A node signs its hello with the
+    /// key it uses in this epoch.
+    pub epoch: Height,
+}
+
+/// Our hello and the keys the hellos of the other nodes are checked with, as
+/// of the last `Peers`. The links of a transport share it, and the transport
+/// updates it with every `Peers` it receives, so that a node that joined or
+/// rotated its key can connect.
+#[derive(Clone)]
+pub struct Greeter {
+    alg: Algorithm,
+    inner: Arc<RwLock<Greeting>>,
+}
+
+struct Greeting {
+    hello: Hello,
+    keys: HashMap<Replica, Box<dyn VerifyingKey>>,
+}
+
+impl Greeter {
+    pub fn new(alg: Algorithm, id: Replica, peers: &Peers) -> Self {
+        let greeter = Greeter {
+            alg,
+            inner: Arc::new(RwLock::new(Greeting {
+                hello: Hello::new(peers.digest, id),
+                keys: HashMap::new(),
+            })),
+        };
+        greeter.update(peers);
+        greeter
+    }
+
+    /// Follows a new membership or key
+    pub fn update(&self, peers: &Peers) {
+        let keys = match crypto::scheme::lookup(&self.alg) {
+            Ok(scheme) => peers
+                .keys
+                .iter()
+                .filter_map(|(r, pk)| match scheme.decode_pk(pk) {
+                    Ok(pk) => Some((*r, pk)),
+                    Err(e) => {
+                        println!("[WARN] Cannot decode the key of node {}: {}", r, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                println!("[WARN] Cannot use the keys to check hellos: {}", e);
+                HashMap::new()
+            }
+        };
+        let mut greeting = self.inner.write().unwrap();
+        greeting.hello.config = peers.digest;
+        greeting.hello.epoch = peers.epoch;
+        greeting.keys = keys;
+    }
+
+    /// Our hello, yet to be signed
+    pub fn hello(&self) -> Hello {
+        self.inner.read().unwrap().hello.clone()
+    }
+
+    /// Checks the hello of a peer against ours and its key, and returns the
+    /// version to speak with it
+    pub fn accept(&self, theirs: &Hello) -> Result<u16, HandshakeError> {
+        let greeting = self.inner.read().unwrap();
+        let pk = greeting.keys.get(&theirs.origin).map(|pk| pk.as_ref());
+        greeting.hello.accept(theirs, pk)
+    }
+
+    /// `accept` as the codec of a plain link takes it
+    pub fn check(&self) -> CheckHello {
+        let greeter = self.clone();
+        Arc::new(move |theirs: &Hello| greeter.accept(theirs))
+    }
+}
+
+/// What a transport is handed: a message for a node, or a new membership
+#[derive(Debug)]
+pub enum Outgoing {
+    Msg(Replica, Arc<ProtocolMsg>),
+    Peers(Peers),
+}
+
+/// Hands a transport the messages and the new memberships on one channel, so
+/// that it applies a `Peers` after every message sent before it and before
+/// every message sent after it
+#[derive(Debug, Clone)]
+pub struct NetSender(UnboundedSender<Outgoing>);
+
+/// Sending only fails once the transport is gone
+impl NetSender {
+    pub fn send(&self, (to, msg): (Replica, Arc<ProtocolMsg>)) -> Result<(), SendError<()>> {
+        self.0
+            .send(Outgoing::Msg(to, msg))
+            .map_err(|_| SendError(()))
+    }
+
+    pub fn update(&self, peers: Peers) -> Result<(), SendError<()>> {
+        self.0
+            .send(Outgoing::Peers(peers))
+            .map_err(|_| SendError(()))
+    }
+}
+
+pub fn channel() -> (NetSender, UnboundedReceiver<Outgoing>) {
+    let (send, recv) = unbounded_channel();
+    (NetSender(send), recv)
+}
+
+/// Sits in front of a transport that keeps the nodes it was set up with,
+/// such as the one of `net`. A broadcast of a later membership goes to the
+/// broadcast id of the transport, and a message for a node the transport
+/// does not know is dropped.
+pub fn fixed(net_send: UnboundedSender<(Replica, Arc<ProtocolMsg>)>, peers: Peers) -> NetSender {
+    let (send, mut outgoing) = channel();
+    tokio::spawn(async move {
+        let mut broadcast = peers.num_nodes;
+        while let Some(out) = outgoing.recv().await {
+            match out {
+                Outgoing::Peers(update) => {
+                    if update
+                        .addrs
+                        .iter()
+                        .any(|(r, addr)| peers.addrs.get(r) != Some(addr))
+                    {
+                        println!("[WARN] The plain transport cannot connect to new nodes or addresses; restart the nodes with the new config or use --secure");
+                    }
+                    broadcast = update.num_nodes;
+                }
+                Outgoing::Msg(to, msg) if to == broadcast => {
+                    let _ = net_send.send((peers.num_nodes, msg));
+                }
+                Outgoing::Msg(to, msg) if to < peers.num_nodes => {
+                    let _ = net_send.send((to, msg));
+                }
+                Outgoing::Msg(to, _) => println!(
+                    "[WARN] Dropped a message for node {}, which the transport does not know.",
+                    to
+                ),
+            }
+        }
+    });
+    send
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(n: Replica) -> Peers {
+        Peers {
+            num_nodes: n,
+            addrs: (0..n)
+                .map(|r| (r, format!("127.0.0.1:{}", 9000 + r)))
+                .collect(),
+            keys: HashMap::new(),
+            ..Peers::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn fixed_membership() {
+        let (net_send, mut net_recv) = unbounded_channel();
+        let send = fixed(net_send, peers(3));
+        let msg = Arc::new(ProtocolMsg::Certificate(types::Certificate::empty_cert()));
+        // A broadcast sent before node 3 joins still goes to the old
+        // membership, and 4 is the broadcast id from then on
+        send.send((3, msg.clone())).unwrap();
+        send.update(peers(4)).unwrap();
+        send.send((3, msg.clone())).unwrap();
+        send.send((4, msg.clone())).unwrap();
+        send.send((1, msg)).unwrap();
+        assert_eq!(net_recv.recv().await.unwrap().0, 3);
+        assert_eq!(net_recv.recv().await.unwrap().0, 3);
+        assert_eq!(net_recv.recv().await.unwrap().0, 1);
+    }
+
+    #[test]
+    fn greeter() {
+        let scheme = crypto::scheme::lookup(&Algorithm::ED25519).unwrap();
+        let pairs: Vec<_> = (0..3).map(|_| scheme.generate().unwrap()).collect();
+        let new_key = scheme.generate().unwrap();
+        let members = |n: Replica| Peers {
+            keys: (0..n).map(|r| (r, pairs[r as usize].0.clone())).collect(),
+            digest: [n as u8; 32],
+            ..peers(n)
+        };
+        let hello = |peers: &Peers, origin: Replica, sk: &[u8]| {
+            let mut hello = Hello::new(peers.digest, origin);
+            hello.epoch = peers.epoch;
+            let digest = types::sign_digest(types::SignKind::Hello, hello.epoch, &hello.hash());
+            hello.sig = scheme.decode_sk(sk).unwrap().sign(&digest).unwrap();
+            hello
+        };
+        let greeter = Greeter::new(Algorithm::ED25519, 0, &members(2));
+        assert!(greeter.accept(&hello(&members(2), 1, &pairs[1].1)).is_ok());
+
+        // Node 2 joins
+        let joiner = hello(&members(3), 2, &pairs[2].1);
+        assert_eq!(greeter.accept(&joiner), Err(HandshakeError::Config(2)));
+        greeter.update(&members(3));
+        assert_eq!(greeter.hello().config, [3; 32]);
+        assert!(greeter.accept(&joiner).is_ok());
+
+        // Node 1 rotates its key in epoch 5
+        let mut rotated = members(3);
+        rotated.keys.insert(1, new_key.0.clone());
+        rotated.epoch = 5;
+        let before = hello(&rotated, 1, &new_key.1);
+        assert_eq!(
+            greeter.accept(&before),
+            Err(HandshakeError::BadSignature(1))
+        );
+        greeter.update(&rotated);
+        assert_eq!(greeter.hello().epoch, 5);
+        assert!(greeter.accept(&before).is_ok());
+        assert_eq!(
+            greeter.accept(&hello(&rotated, 1, &pairs[1].1)),
+            Err(HandshakeError::BadSignature(1))
+        );
+    }
+}